@@ -6,6 +6,8 @@ use crate::sha256_hash;
 pub const BLOCKHASH_MERKLE_TREE_DEPTH: usize = 32;
 /// Depth of the merkle tree that stores withdrawals, should be same with the bridge contract
 pub const WITHDRAWAL_MERKLE_TREE_DEPTH: usize = 32;
+/// Maximum depth of the merkle trees, the `ZEROES` table is computed up to this depth
+pub const MAX_MERKLE_DEPTH: usize = 32;
 /// Claim merkle tree depth
 pub const CLAIM_MERKLE_TREE_DEPTH: usize = 4;
 /// This is a period to handle remaining withdrawals, and inscribe connector tree preimages, 1 week = 7*24*6 = 1008
@@ -46,9 +48,9 @@ pub const EMPTYDATA: [u8; 32] = [
 
 lazy_static! {
     /// Zero subtree hashes
-    pub static ref ZEROES: [[u8; 32]; WITHDRAWAL_MERKLE_TREE_DEPTH + 1] = {
-        let mut a = [EMPTYDATA; WITHDRAWAL_MERKLE_TREE_DEPTH + 1];
-        for i in 0..WITHDRAWAL_MERKLE_TREE_DEPTH {
+    pub static ref ZEROES: [[u8; 32]; MAX_MERKLE_DEPTH + 1] = {
+        let mut a = [EMPTYDATA; MAX_MERKLE_DEPTH + 1];
+        for i in 0..MAX_MERKLE_DEPTH {
             a[i + 1] = sha256_hash!(a[i], a[i]);
         }
         a
@@ -1,5 +1,5 @@
 use crate::{
-    constants::{EMPTYDATA, ZEROES},
+    constants::{EMPTYDATA, MAX_MERKLE_DEPTH, ZEROES},
     sha256_hash, HashType,
 };
 // use serde::{de::DeserializeOwned, Deserialize, Serialize};
@@ -28,7 +28,11 @@ impl<const DEPTH: usize> IncrementalMerkleTree<DEPTH>
 // where
 //     [HashType; DEPTH]: Serialize + DeserializeOwned + Copy,
 {
+    /// Fails to compile for trees deeper than the `ZEROES` table
+    const DEPTH_IS_SUPPORTED: () = assert!(DEPTH <= MAX_MERKLE_DEPTH, "merkle tree is too deep");
+
     pub fn new() -> Self {
+        let () = Self::DEPTH_IS_SUPPORTED;
         Self {
             filled_subtrees: [EMPTYDATA; DEPTH],
             root: ZEROES[DEPTH],
@@ -36,6 +40,26 @@ impl<const DEPTH: usize> IncrementalMerkleTree<DEPTH>
         }
     }
 
+    /// Builds a tree from state computed elsewhere. Returns `None` if the
+    /// parts are not consistent with each other.
+    pub fn from_parts(
+        filled_subtrees: [HashType; DEPTH],
+        root: HashType,
+        index: u32,
+    ) -> Option<Self> {
+        let () = Self::DEPTH_IS_SUPPORTED;
+        let tree = Self {
+            filled_subtrees,
+            root,
+            index,
+        };
+        if tree.verify_internal_consistency() {
+            Some(tree)
+        } else {
+            None
+        }
+    }
+
     pub fn add(&mut self, a: HashType) {
         let mut current_index = self.index;
         let mut current_level_hash = a;
@@ -53,4 +77,73 @@ impl<const DEPTH: usize> IncrementalMerkleTree<DEPTH>
         self.root = current_level_hash;
         self.index += 1;
     }
+
+    /// Checks that `root` follows from `filled_subtrees` and `index`, to detect
+    /// a corrupted snapshot. The path of the last leaf is rebuilt from the
+    /// lowest level where it is a left child, which `filled_subtrees` holds.
+    /// A full tree has no such level, so only its index is checked.
+    pub fn verify_internal_consistency(&self) -> bool {
+        if self.index as u64 > 1u64 << DEPTH {
+            return false;
+        }
+        if self.index == 0 {
+            return self.root == ZEROES[DEPTH];
+        }
+
+        let last_index = self.index - 1;
+        let first_left_level = match (0..DEPTH).find(|i| (last_index >> i) % 2 == 0) {
+            Some(level) => level,
+            None => return true,
+        };
+
+        let mut current_level_hash = self.filled_subtrees[first_left_level];
+        for i in first_left_level..DEPTH {
+            current_level_hash = if (last_index >> i) % 2 == 0 {
+                if self.filled_subtrees[i] != current_level_hash {
+                    return false;
+                }
+                sha256_hash!(current_level_hash, ZEROES[i])
+            } else {
+                sha256_hash!(self.filled_subtrees[i], current_level_hash)
+            };
+        }
+        self.root == current_level_hash
+    }
+}
+
+/// Checks that inserting `leaves` in order into an empty tree gives `root`.
+pub fn verify_leaves<const DEPTH: usize>(root: HashType, leaves: &[HashType]) -> bool {
+    if leaves.len() as u64 > 1u64 << DEPTH {
+        return false;
+    }
+    let mut tree = IncrementalMerkleTree::<DEPTH>::new();
+    for leaf in leaves {
+        tree.add(*leaf);
+    }
+    tree.root == root
+}
+
+/// Checks an inclusion proof of `leaf` at `leaf_index`, given as the sibling
+/// hashes from the leaf level up. Siblings are hashed in the order `add` uses,
+/// the node with the even index on the left.
+pub fn verify_proof<const DEPTH: usize>(
+    leaf: HashType,
+    leaf_index: u32,
+    proof: &[HashType; DEPTH],
+    root: HashType,
+) -> bool {
+    if leaf_index as u64 >= 1u64 << DEPTH {
+        return false;
+    }
+    let mut current_index = leaf_index;
+    let mut current_level_hash = leaf;
+    for sibling in proof.iter() {
+        current_level_hash = if current_index % 2 == 0 {
+            sha256_hash!(current_level_hash, *sibling)
+        } else {
+            sha256_hash!(*sibling, current_level_hash)
+        };
+        current_index /= 2;
+    }
+    current_level_hash == root
 }
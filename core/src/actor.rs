@@ -1,8 +1,8 @@
 use crate::constants::NETWORK;
-use crate::errors::BridgeError;
+use crate::errors::{BridgeError, BridgeResult};
 use crate::transaction_builder::CreateTxOutputs;
 use bitcoin::sighash::SighashCache;
-use bitcoin::taproot::LeafVersion;
+use bitcoin::taproot::{LeafVersion, TAPROOT_ANNEX_PREFIX};
 use bitcoin::{
     hashes::Hash,
     secp256k1::{
@@ -11,7 +11,39 @@ use bitcoin::{
     Address, TapSighash, TapTweakHash,
 };
 
+use argon2::Argon2;
 use bitcoin::{TapLeafHash, TapNodeHash, TxOut};
+use chacha20poly1305::aead::rand_core::RngCore as _;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use secp256k1::rand::{CryptoRng, RngCore};
+
+/// How a taproot input is spent, which decides both the sighash and the
+/// shape of the witness.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SpendPath {
+    /// Signature for a script leaf, the witness also carries the script and
+    /// its control block
+    #[default]
+    ScriptSpend,
+    /// Signature for the tweaked output key, the witness is the signature alone
+    KeySpend,
+}
+
+/// Salt length of the key derivation of `Actor::export_encrypted`
+const ENCRYPTED_KEY_SALT_LEN: usize = 16;
+/// Nonce length of ChaCha20-Poly1305
+const ENCRYPTED_KEY_NONCE_LEN: usize = 12;
+
+/// Derives the ChaCha20-Poly1305 key of an encrypted secret key from the
+/// passphrase with Argon2id.
+fn derive_encryption_key(passphrase: &str, salt: &[u8]) -> BridgeResult<Key> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|_| BridgeError::DecryptionError)?;
+    Ok(key)
+}
 
 #[derive(Debug)]
 pub struct Actor {
@@ -23,12 +55,6 @@ pub struct Actor {
     pub address: Address,
 }
 
-impl Default for Actor {
-    fn default() -> Self {
-        unimplemented!("Actor::default is not implemented");
-    }
-}
-
 impl Actor {
     pub fn new(sk: SecretKey) -> Self {
         let secp: Secp256k1<All> = Secp256k1::new();
@@ -47,13 +73,54 @@ impl Actor {
         }
     }
 
+    /// Creates an actor with a fresh secret key drawn from the given RNG, so
+    /// tests can use a seeded RNG to get reproducible keys and signatures.
+    pub fn new_with_rng<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+        Actor::new(SecretKey::new(rng))
+    }
+
+    /// Encrypts the secret key with ChaCha20-Poly1305 under a key derived
+    /// from `passphrase`, so it can be stored at rest. The output is the
+    /// salt, then the nonce, then the ciphertext.
+    pub fn export_encrypted(&self, passphrase: &str) -> BridgeResult<Vec<u8>> {
+        let mut salt = [0u8; ENCRYPTED_KEY_SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let key = derive_encryption_key(passphrase, &salt)?;
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = ChaCha20Poly1305::new(&key)
+            .encrypt(&nonce, self.secret_key.secret_bytes().as_ref())
+            .map_err(|_| BridgeError::DecryptionError)?;
+
+        let mut data = Vec::with_capacity(salt.len() + nonce.len() + ciphertext.len());
+        data.extend_from_slice(&salt);
+        data.extend_from_slice(&nonce);
+        data.extend_from_slice(&ciphertext);
+        Ok(data)
+    }
+
+    /// Creates an actor from a secret key encrypted with `export_encrypted`.
+    /// A wrong passphrase or tampered data returns `DecryptionError`.
+    pub fn from_encrypted(data: &[u8], passphrase: &str) -> BridgeResult<Self> {
+        if data.len() < ENCRYPTED_KEY_SALT_LEN + ENCRYPTED_KEY_NONCE_LEN {
+            return Err(BridgeError::DecryptionError);
+        }
+        let (salt, rest) = data.split_at(ENCRYPTED_KEY_SALT_LEN);
+        let (nonce, ciphertext) = rest.split_at(ENCRYPTED_KEY_NONCE_LEN);
+        let key = derive_encryption_key(passphrase, salt)?;
+        let secret_bytes = ChaCha20Poly1305::new(&key)
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| BridgeError::DecryptionError)?;
+        let sk = SecretKey::from_slice(&secret_bytes).map_err(|_| BridgeError::DecryptionError)?;
+        Ok(Actor::new(sk))
+    }
+
     pub fn sign_with_tweak(
         &self,
         sighash: TapSighash,
         merkle_root: Option<TapNodeHash>,
-    ) -> Result<schnorr::Signature, BridgeError> {
+    ) -> BridgeResult<schnorr::Signature> {
         Ok(self.secp.sign_schnorr(
-            &Message::from_digest_slice(sighash.as_byte_array()).expect("should be hash"),
+            &Message::from_digest(sighash.to_byte_array()),
             &self.keypair.add_xonly_tweak(
                 &self.secp,
                 &TapTweakHash::from_key_and_tweak(self.xonly_public_key, merkle_root).to_scalar(),
@@ -63,32 +130,63 @@ impl Actor {
 
     pub fn sign(&self, sighash: TapSighash) -> schnorr::Signature {
         self.secp.sign_schnorr(
-            &Message::from_digest_slice(sighash.as_byte_array()).expect("should be hash"),
+            &Message::from_digest(sighash.to_byte_array()),
             &self.keypair,
         )
     }
 
     pub fn sign_ecdsa(&self, data: [u8; 32]) -> ecdsa::Signature {
-        self.secp.sign_ecdsa(
-            &Message::from_digest_slice(&data).expect("should be hash"),
-            &self.secret_key,
-        )
+        self.secp
+            .sign_ecdsa(&Message::from_digest(data), &self.secret_key)
     }
 
-    pub fn sign_taproot_script_spend_tx(
-        &self,
+    /// Calculates the script spend sighash of the given input. Taproot annexes
+    /// are not supported: The sighash is always computed without an annex and
+    /// an error is returned if the input's witness already carries one.
+    fn taproot_script_spend_sighash(
         tx: &mut bitcoin::Transaction,
         prevouts: &Vec<TxOut>,
         spend_script: &bitcoin::Script,
         input_index: usize,
-    ) -> Result<schnorr::Signature, BridgeError> {
+    ) -> BridgeResult<TapSighash> {
+        let input = tx
+            .input
+            .get(input_index)
+            .ok_or(BridgeError::TxInputNotFound)?;
+        let has_annex = input.witness.len() >= 2
+            && input
+                .witness
+                .last()
+                .is_some_and(|elem| elem.first() == Some(&TAPROOT_ANNEX_PREFIX));
+        if has_annex {
+            return Err(BridgeError::BitcoinSighashError(
+                bitcoin::sighash::Error::WrongAnnex,
+            ));
+        }
+
         let mut sighash_cache = SighashCache::new(tx);
-        let sig_hash = sighash_cache.taproot_script_spend_signature_hash(
+        let sig_hash = sighash_cache.taproot_signature_hash(
             input_index,
             &bitcoin::sighash::Prevouts::All(prevouts),
-            TapLeafHash::from_script(spend_script, LeafVersion::TapScript),
+            None,
+            Some((
+                TapLeafHash::from_script(spend_script, LeafVersion::TapScript),
+                0xFFFFFFFF,
+            )),
             bitcoin::sighash::TapSighashType::Default,
         )?;
+        Ok(sig_hash)
+    }
+
+    pub fn sign_taproot_script_spend_tx(
+        &self,
+        tx: &mut bitcoin::Transaction,
+        prevouts: &Vec<TxOut>,
+        spend_script: &bitcoin::Script,
+        input_index: usize,
+    ) -> BridgeResult<schnorr::Signature> {
+        let sig_hash =
+            Actor::taproot_script_spend_sighash(tx, prevouts, spend_script, input_index)?;
         Ok(self.sign(sig_hash))
     }
 
@@ -96,43 +194,60 @@ impl Actor {
         &self,
         tx: &mut CreateTxOutputs,
         input_index: usize,
-    ) -> Result<TapSighash, BridgeError> {
-        let mut sighash_cache: SighashCache<&mut bitcoin::Transaction> =
-            SighashCache::new(&mut tx.tx);
-        let sig_hash = sighash_cache.taproot_script_spend_signature_hash(
+    ) -> BridgeResult<TapSighash> {
+        Actor::taproot_script_spend_sighash(
+            &mut tx.tx,
+            &tx.prevouts,
+            &tx.scripts[input_index],
             input_index,
-            &bitcoin::sighash::Prevouts::All(&tx.prevouts),
-            TapLeafHash::from_script(&tx.scripts[input_index], LeafVersion::TapScript),
-            bitcoin::sighash::TapSighashType::Default,
-        )?;
-        Ok(sig_hash)
+        )
     }
 
     pub fn sign_taproot_script_spend_tx_new(
         &self,
         tx: &mut CreateTxOutputs,
         input_index: usize,
-    ) -> Result<schnorr::Signature, BridgeError> {
+    ) -> BridgeResult<schnorr::Signature> {
         // TODO: if sighash_cache exists in the CreateTxOutputs, use it
         // else create a new one and save it to the CreateTxOutputs
 
-        let mut sighash_cache: SighashCache<&mut bitcoin::Transaction> =
-            SighashCache::new(&mut tx.tx);
-        let sig_hash = sighash_cache.taproot_script_spend_signature_hash(
-            input_index,
-            &bitcoin::sighash::Prevouts::All(&tx.prevouts),
-            TapLeafHash::from_script(&tx.scripts[input_index], LeafVersion::TapScript),
-            bitcoin::sighash::TapSighashType::Default,
-        )?;
+        let sig_hash = self.sighash_taproot_script_spend(tx, input_index)?;
         Ok(self.sign(sig_hash))
     }
 
+    /// Signs the input either with the script of the input in `tx.scripts`, or
+    /// with the output key tweaked by the merkle root of the input's
+    /// `taproot_spend_infos`, if it has one.
+    pub fn sign_taproot_spend_tx(
+        &self,
+        tx: &mut CreateTxOutputs,
+        input_index: usize,
+        spend_path: SpendPath,
+    ) -> BridgeResult<schnorr::Signature> {
+        match spend_path {
+            SpendPath::ScriptSpend => self.sign_taproot_script_spend_tx_new(tx, input_index),
+            SpendPath::KeySpend => {
+                let merkle_root = tx
+                    .taproot_spend_infos
+                    .get(input_index)
+                    .and_then(|info| info.merkle_root());
+                let mut sighash_cache = SighashCache::new(&mut tx.tx);
+                let sig_hash = sighash_cache.taproot_key_spend_signature_hash(
+                    input_index,
+                    &bitcoin::sighash::Prevouts::All(&tx.prevouts),
+                    bitcoin::sighash::TapSighashType::Default,
+                )?;
+                self.sign_with_tweak(sig_hash, merkle_root)
+            }
+        }
+    }
+
     pub fn sign_taproot_pubkey_spend_tx(
         &self,
         tx: &mut bitcoin::Transaction,
         prevouts: &Vec<TxOut>,
         input_index: usize,
-    ) -> Result<schnorr::Signature, BridgeError> {
+    ) -> BridgeResult<schnorr::Signature> {
         let mut sighash_cache = SighashCache::new(tx);
         let sig_hash = sighash_cache.taproot_key_spend_signature_hash(
             input_index,
@@ -163,3 +278,137 @@ impl Actor {
     //     Some(true)
     // }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Actor, SpendPath};
+    use crate::errors::BridgeError;
+    use crate::transaction_builder::CreateTxOutputs;
+    use crate::utils::handle_taproot_spend_witness;
+    use bitcoin::key::TapTweak;
+    use bitcoin::secp256k1::Message;
+    use bitcoin::sighash::{Prevouts, SighashCache, TapSighashType};
+    use bitcoin::{
+        absolute, hashes::Hash, transaction::Version, Amount, OutPoint, ScriptBuf, Sequence,
+        Transaction, TxIn, TxOut, Txid, Witness,
+    };
+    use crypto_bigint::rand_core::OsRng;
+    use secp256k1::rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_encrypted_key_round_trip() {
+        let actor = Actor::new_with_rng(&mut OsRng);
+        let encrypted = actor.export_encrypted("correct horse").unwrap();
+        assert!(!encrypted
+            .windows(32)
+            .any(|window| window == actor.secret_key.secret_bytes()));
+
+        let decrypted = Actor::from_encrypted(&encrypted, "correct horse").unwrap();
+        assert_eq!(decrypted.secret_key, actor.secret_key);
+        assert_eq!(decrypted.address, actor.address);
+
+        assert!(matches!(
+            Actor::from_encrypted(&encrypted, "battery staple"),
+            Err(BridgeError::DecryptionError)
+        ));
+        assert!(matches!(
+            Actor::from_encrypted(&encrypted[..20], "correct horse"),
+            Err(BridgeError::DecryptionError)
+        ));
+    }
+
+    #[test]
+    fn test_new_with_rng_is_deterministic() {
+        let first = Actor::new_with_rng(&mut StdRng::from_seed([1u8; 32]));
+        let second = Actor::new_with_rng(&mut StdRng::from_seed([1u8; 32]));
+        assert_eq!(first.secret_key, second.secret_key);
+        assert_eq!(first.address, second.address);
+
+        let other = Actor::new_with_rng(&mut StdRng::from_seed([2u8; 32]));
+        assert_ne!(first.secret_key, other.secret_key);
+    }
+
+    #[test]
+    fn test_annex_is_rejected() {
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let (sk, _) = secp.generate_keypair(&mut OsRng);
+        let actor = Actor::new(sk);
+
+        let prevouts = vec![TxOut {
+            value: Amount::from_sat(1000),
+            script_pubkey: actor.address.script_pubkey(),
+        }];
+        let script = ScriptBuf::new();
+        let mut tx = Transaction {
+            version: Version(2),
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::all_zeros(), 0),
+                script_sig: ScriptBuf::default(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            }],
+            output: vec![],
+        };
+
+        assert!(actor
+            .sign_taproot_script_spend_tx(&mut tx, &prevouts, &script, 0)
+            .is_ok());
+
+        tx.input[0].witness.push([0u8; 64]);
+        tx.input[0].witness.push([0x50, 0x01]);
+        assert!(matches!(
+            actor.sign_taproot_script_spend_tx(&mut tx, &prevouts, &script, 0),
+            Err(BridgeError::BitcoinSighashError(_))
+        ));
+    }
+
+    #[test]
+    fn test_key_spend_path() {
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let (sk, _) = secp.generate_keypair(&mut OsRng);
+        let actor = Actor::new(sk);
+
+        let mut tx = CreateTxOutputs {
+            tx: Transaction {
+                version: Version(2),
+                lock_time: absolute::LockTime::ZERO,
+                input: vec![TxIn {
+                    previous_output: OutPoint::new(Txid::all_zeros(), 0),
+                    script_sig: ScriptBuf::default(),
+                    sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                    witness: Witness::new(),
+                }],
+                output: vec![],
+            },
+            prevouts: vec![TxOut {
+                value: Amount::from_sat(1000),
+                script_pubkey: actor.address.script_pubkey(),
+            }],
+            scripts: vec![],
+            taproot_spend_infos: vec![],
+        };
+
+        let sig = actor
+            .sign_taproot_spend_tx(&mut tx, 0, SpendPath::KeySpend)
+            .unwrap();
+        let sig_hash = SighashCache::new(&tx.tx)
+            .taproot_key_spend_signature_hash(
+                0,
+                &Prevouts::All(&tx.prevouts),
+                TapSighashType::Default,
+            )
+            .unwrap();
+        let (output_key, _) = actor.xonly_public_key.tap_tweak(&secp, None);
+        assert!(secp
+            .verify_schnorr(
+                &sig,
+                &Message::from_digest(sig_hash.to_byte_array()),
+                &output_key.to_inner()
+            )
+            .is_ok());
+
+        handle_taproot_spend_witness(&mut tx, &vec![sig.as_ref()], 0, SpendPath::KeySpend).unwrap();
+        assert_eq!(tx.tx.input[0].witness.len(), 1);
+    }
+}
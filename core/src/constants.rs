@@ -2,26 +2,52 @@ use bitcoin::BlockHash;
 use clementine_circuits::constants::CLAIM_MERKLE_TREE_DEPTH;
 use crypto_bigint::U256;
 
+use crate::timelock::Timelock;
+
 pub const NUM_VERIFIERS: usize = 4;
 pub const NUM_USERS: usize = 4;
 
 /// For connector tree utxos, we should wait some time for any verifier to burn the branch if preimage is revealed
-pub const CONNECTOR_TREE_OPERATOR_TAKES_AFTER: u16 = 1;
+pub const CONNECTOR_TREE_OPERATOR_TAKES_AFTER: Timelock = Timelock::Blocks(1);
+
+/// Expected time between blocks, in seconds
+pub const BLOCK_TIME_SECS: u64 = 600;
+/// Time a verifier needs to notice a preimage reveal and broadcast a burn, in seconds
+pub const VERIFIER_REACTION_SECS: u64 = 3600;
 
 /// Depth of the utxo tree from the source connector utxo, it is probably equal to claim merkle tree depth
 pub const CONNECTOR_TREE_DEPTH: usize = CLAIM_MERKLE_TREE_DEPTH;
 
+/// Number of children of every internal node of the connector tree. Claim
+/// proofs reveal the connector tree hashes of a binary tree, so this has to be
+/// 2 until they support other arities
+pub const CONNECTOR_TREE_ARITY: usize = 2;
+const _: () = assert!(CONNECTOR_TREE_ARITY == 2);
+
 /// Dust value for mempool acceptance
 pub const DUST_VALUE: u64 = 1000;
 /// Minimum relay fee for mempool acceptance
 pub const MIN_RELAY_FEE: u64 = 500;
+/// Fee band of the move (kickoff) transaction that verifiers are willing to
+/// presign. Below it the transaction may never confirm, above it the fee eats
+/// into the bridged amount
+pub const MIN_MOVE_TX_FEE: u64 = MIN_RELAY_FEE;
+pub const MAX_MOVE_TX_FEE: u64 = 20 * MIN_RELAY_FEE;
+/// Number of blocks fee estimates aim to confirm in
+pub const FEE_ESTIMATE_CONF_TARGET: u16 = 6;
+/// Number of the last scanned block hashes a connector tree watch keeps to
+/// notice reorgs. A reorg deeper than this rolls the watch back to the
+/// oldest kept block
+pub const WATCH_BLOCK_HASH_WINDOW: u64 = 100;
 
 /// This is temporary. to be able to set PERIOD_END_BLOCK_HEIGHTS
 pub const PERIOD_BLOCK_COUNT: u32 = 50; // 10 mins for 1 block, 6 months = 6*30*24*6 = 25920
 
 /// For deposits, every user makes a timelock to take the money back if deposit deos not happen,
 /// one reason is to not spam the bridge operator
-pub const USER_TAKES_AFTER: u32 = 200;
+pub const USER_TAKES_AFTER: Timelock = Timelock::Blocks(200);
+// Refund heights are derived from it
+const _: () = assert!(matches!(USER_TAKES_AFTER, Timelock::Blocks(_)));
 
 /// For deposits, bridge operator does not accept the tx if it is not confirmed
 pub const CONFIRMATION_BLOCK_COUNT: u32 = 6;
@@ -37,4 +63,34 @@ pub type VerifierChallenge = (BlockHash, U256, u8);
 /// Which of the Bitcoin's networks to act on
 pub const NETWORK: bitcoin::Network = bitcoin::Network::Regtest;
 
+/// Dust, fee and confirmation parameters that depend on the network
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BridgeParams {
+    pub dust_value: u64,
+    /// Lowest fee in satoshis a transaction is sent with, whatever the estimate
+    pub min_relay_fee: u64,
+    /// Confirmations before a spend is treated as final
+    pub confirmation_blocks: u32,
+}
+
+impl BridgeParams {
+    /// The connector tree leaves and the claim amounts are built with
+    /// `DUST_VALUE` and `MIN_RELAY_FEE` and are presigned, so only the fee
+    /// floor and the confirmations can be stricter on mainnet.
+    pub fn for_network(network: bitcoin::Network) -> Self {
+        match network {
+            bitcoin::Network::Bitcoin => BridgeParams {
+                dust_value: DUST_VALUE,
+                min_relay_fee: 2 * MIN_RELAY_FEE,
+                confirmation_blocks: 2 * CONFIRMATION_BLOCK_COUNT,
+            },
+            _ => BridgeParams {
+                dust_value: DUST_VALUE,
+                min_relay_fee: MIN_RELAY_FEE,
+                confirmation_blocks: CONFIRMATION_BLOCK_COUNT,
+            },
+        }
+    }
+}
+
 pub const TEXT_DATABASE: &str = "database";
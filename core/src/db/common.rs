@@ -2,13 +2,15 @@
 //!
 //! Common database operations for both operator and verifier.
 
-use super::text::TextDatabase;
+use super::text::FileStateStore;
 use crate::{
     constants::TEXT_DATABASE,
     errors::BridgeError::{self, DatabaseError},
     merkle::MerkleTree,
+    traits::state_store::StateStore,
     ConnectorUTXOTree, HashTree, InscriptionTxs, WithdrawalPayment,
 };
+use bitcoin::OutPoint;
 use clementine_circuits::{
     constants::{CLAIM_MERKLE_TREE_DEPTH, WITHDRAWAL_MERKLE_TREE_DEPTH},
     HashType, PreimageType,
@@ -22,7 +24,7 @@ use std::{
 /// Main database struct that holds all the information of the database.
 #[derive(Clone, Debug)]
 pub struct Database {
-    pub dbms: TextDatabase,
+    pub store: Arc<dyn StateStore>,
     pub lock: Arc<Mutex<usize>>,
 }
 
@@ -30,30 +32,35 @@ pub struct Database {
 /// functions for accessing the database.
 impl Database {
     pub fn new() -> Self {
+        Self::with_store(Arc::new(FileStateStore::default()))
+    }
+
+    /// Creates a database that keeps its content in `store`.
+    pub fn with_store(store: Arc<dyn StateStore>) -> Self {
         Self {
-            dbms: TextDatabase::new(TEXT_DATABASE.into()),
+            store,
             lock: Arc::new(Mutex::new(0)),
         }
     }
 
     /// Calls actual database read function and writes it's contents to memory.
     fn read(&self) -> Result<DatabaseContent, BridgeError> {
-        match self.dbms.read() {
-            Ok(c) => Ok(c),
-            Err(e) => match e.kind() {
+        match self.store.load(TEXT_DATABASE) {
+            Ok(c) => serde_json::from_slice(&c).map_err(|e| DatabaseError(e.into())),
+            Err(DatabaseError(e)) => match e.kind() {
                 // If database is not yet created, we should create now.
                 ErrorKind::NotFound => Ok(DatabaseContent::new()),
                 _ => return Err(DatabaseError(e)),
             },
+            Err(e) => Err(e),
         }
     }
 
     /// Calls actual database write function and writes input data to database.
     fn write(&self, content: DatabaseContent) -> Result<(), BridgeError> {
-        match self.dbms.write(content) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(DatabaseError(e)),
-        }
+        let serialized = serde_json::to_vec(&content).map_err(|e| DatabaseError(e.into()))?;
+
+        self.store.save(TEXT_DATABASE, &serialized)
     }
 }
 
@@ -85,6 +92,18 @@ impl Database {
             None => Err(DatabaseError(Error::other("Period could not be found"))),
         }
     }
+    pub fn get_all_connector_tree_hashes(&self) -> Result<Vec<HashTree>, BridgeError> {
+        let content = self.read()?;
+        Ok(content.connector_tree_hashes.clone())
+    }
+    pub fn get_connector_tree_hashes(&self, period: usize) -> Result<HashTree, BridgeError> {
+        let content = self.read()?;
+
+        match content.connector_tree_hashes.get(period) {
+            Some(v) => Ok(v.clone()),
+            None => Err(DatabaseError(Error::other("Period could not be found"))),
+        }
+    }
     pub fn set_connector_tree_hashes(
         &self,
         connector_tree_hashes: Vec<Vec<Vec<HashType>>>,
@@ -109,6 +128,12 @@ impl Database {
             None => Err(DatabaseError(Error::other("Period could not be found"))),
         }
     }
+    pub fn get_all_claim_proof_merkle_trees(
+        &self,
+    ) -> Result<Vec<MerkleTree<CLAIM_MERKLE_TREE_DEPTH>>, BridgeError> {
+        let content = self.read()?;
+        Ok(content.claim_proof_merkle_trees.clone())
+    }
     pub fn set_claim_proof_merkle_trees(
         &self,
         claim_proof_merkle_trees: Vec<MerkleTree<CLAIM_MERKLE_TREE_DEPTH>>,
@@ -151,7 +176,7 @@ impl Database {
         let _guard = self.lock.lock().unwrap();
 
         let mut content = self.read()?;
-        content.withdrawals_merkle_tree.add(hash);
+        content.withdrawals_merkle_tree.add(hash)?;
         self.write(content)?;
 
         Ok(())
@@ -185,6 +210,10 @@ impl Database {
         let content = self.read()?;
         Ok(content.connector_tree_utxos[idx].clone())
     }
+    pub fn get_all_connector_tree_utxos(&self) -> Result<Vec<ConnectorUTXOTree>, BridgeError> {
+        let content = self.read()?;
+        Ok(content.connector_tree_utxos.clone())
+    }
     pub fn set_connector_tree_utxos(
         &self,
         connector_tree_utxos: Vec<ConnectorUTXOTree>,
@@ -253,6 +282,39 @@ impl Database {
 
         Ok(())
     }
+
+    /// Returns the start UTXO of the deposit that the connector hash is
+    /// presigned for, if any.
+    pub fn get_presigned_deposit(&self, hash: HashType) -> Result<Option<OutPoint>, BridgeError> {
+        let content = self.read()?;
+
+        Ok(content
+            .presigned_connector_hashes
+            .iter()
+            .find(|(presigned_hash, _)| *presigned_hash == hash)
+            .map(|(_, start_utxo)| *start_utxo))
+    }
+    pub fn add_presigned_connector_hashes(
+        &self,
+        hashes: Vec<HashType>,
+        start_utxo: OutPoint,
+    ) -> Result<(), BridgeError> {
+        let _guard = self.lock.lock().unwrap();
+
+        let mut content = self.read()?;
+        for hash in hashes {
+            if !content
+                .presigned_connector_hashes
+                .iter()
+                .any(|(presigned_hash, _)| *presigned_hash == hash)
+            {
+                content.presigned_connector_hashes.push((hash, start_utxo));
+            }
+        }
+        self.write(content)?;
+
+        Ok(())
+    }
 }
 
 /// Actual information that database will hold. This information is not directly
@@ -269,6 +331,8 @@ pub struct DatabaseContent {
     connector_tree_utxos: Vec<ConnectorUTXOTree>,
     start_block_height: u64,
     period_relative_block_heights: Vec<u32>,
+    #[serde(default)]
+    presigned_connector_hashes: Vec<(HashType, OutPoint)>,
 }
 impl DatabaseContent {
     pub fn new() -> Self {
@@ -282,6 +346,7 @@ impl DatabaseContent {
             connector_tree_utxos: Vec::new(),
             start_block_height: 0,
             period_relative_block_heights: Vec::new(),
+            presigned_connector_hashes: Vec::new(),
         }
     }
 }
@@ -297,8 +362,9 @@ impl DatabaseContent {
 /// parameters: They are hard to mock.
 #[cfg(test)]
 mod tests {
-    use super::Database;
-    use crate::{constants::TEXT_DATABASE, db::text::TextDatabase, merkle::MerkleTree};
+    use super::{Database, DatabaseContent, FileStateStore};
+    use crate::{constants::TEXT_DATABASE, merkle::MerkleTree};
+    use bitcoin::{hashes::Hash, OutPoint, Txid};
     use clementine_circuits::{constants::*, HashType, PreimageType};
     use std::{
         fs,
@@ -320,19 +386,28 @@ mod tests {
         });
     }
 
-    /// Tests if running `new()` function returns an empty struct.
+    /// Tests if running `new()` function returns a database on the default
+    /// store.
     #[test]
     fn new() {
-        let database = unsafe {
-            initialize();
-            DATABASE.clone().unwrap()
-        };
-        let lock = unsafe { LOCK.clone().unwrap() };
-        let _guard = lock.lock().unwrap();
+        // The store is not comparable. So, we need to compare its debug
+        // output.
+        assert_eq!(
+            format!("{:?}", Database::new().store),
+            format!("{:?}", FileStateStore::default())
+        );
+
+        // A file store keeps the database in the `TEXT_DATABASE` file of its
+        // directory.
+        let directory = std::env::temp_dir().join(format!("database_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let database = Database::with_store(Arc::new(FileStateStore::new(directory.clone())));
+        database.write(DatabaseContent::new()).unwrap();
+        assert!(directory.join(TEXT_DATABASE).is_file());
+        assert_eq!(database.read().unwrap(), DatabaseContent::new());
 
-        // Some of the members of the `Database` struct are not comparable. So,
-        // we need to do this one by one.
-        assert_eq!(database.dbms, TextDatabase::new(TEXT_DATABASE.into()));
+        // Clean things up.
+        fs::remove_dir_all(directory).unwrap();
     }
 
     /// Writes mock data to database, then reads it. Compares if input equals
@@ -382,11 +457,18 @@ mod tests {
             Err(_) => (),
         };
 
-        let _ = database.set_connector_tree_hashes(mock_array);
-        match database.get_connector_tree_hash(0, 0, 0) {
-            Ok(r) => assert_eq!(r, mock_data),
-            Err(e) => println!("{}", e),
-        };
+        database
+            .set_connector_tree_hashes(mock_array.clone())
+            .unwrap();
+        assert_eq!(
+            database.get_connector_tree_hash(0, 0, 0).unwrap(),
+            mock_data
+        );
+        assert_eq!(
+            database.get_connector_tree_hashes(0).unwrap(),
+            mock_array[0]
+        );
+        assert!(database.get_connector_tree_hashes(1).is_err());
 
         // Clean things up.
         match fs::remove_file(TEXT_DATABASE) {
@@ -405,7 +487,7 @@ mod tests {
         let _guard = lock.lock().unwrap();
 
         let mut mock_data: Vec<MerkleTree<CLAIM_MERKLE_TREE_DEPTH>> = vec![MerkleTree::new()];
-        mock_data[0].add([0x45u8; 32]);
+        mock_data[0].add([0x45u8; 32]).unwrap();
 
         match database.get_claim_proof_merkle_tree(0) {
             Ok(r) => assert_ne!(r, mock_data[0].clone()),
@@ -540,4 +622,37 @@ mod tests {
             Err(_) => assert!(false),
         }
     }
+
+    #[test]
+    fn presigned_connector_hashes() {
+        let database = unsafe {
+            initialize();
+            DATABASE.clone().unwrap()
+        };
+        let lock = unsafe { LOCK.clone().unwrap() };
+        let _guard = lock.lock().unwrap();
+
+        let hash: HashType = [0x45u8; 32];
+        let first_deposit = OutPoint::new(Txid::all_zeros(), 0);
+        let second_deposit = OutPoint::new(Txid::all_zeros(), 1);
+
+        assert_eq!(database.get_presigned_deposit(hash).unwrap(), None);
+
+        database
+            .add_presigned_connector_hashes(vec![hash], first_deposit)
+            .unwrap();
+        database
+            .add_presigned_connector_hashes(vec![hash], second_deposit)
+            .unwrap();
+        assert_eq!(
+            database.get_presigned_deposit(hash).unwrap(),
+            Some(first_deposit)
+        );
+
+        // Clean things up.
+        match fs::remove_file(TEXT_DATABASE) {
+            Ok(_) => assert!(true),
+            Err(_) => assert!(false),
+        }
+    }
 }
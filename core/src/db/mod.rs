@@ -1,4 +1,4 @@
 mod common;
 pub mod operator;
-mod text;
+pub mod text;
 pub mod verifier;
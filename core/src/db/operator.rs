@@ -1,7 +1,12 @@
 use super::common::Database;
+use crate::traits::state_store::StateStore;
 use crate::{operator::OperatorClaimSigs, PreimageTree};
+use bitcoin::{Address, Amount};
 use clementine_circuits::PreimageType;
-use std::ops::{Deref, DerefMut};
+use std::{
+    ops::{Deref, DerefMut},
+    sync::Arc,
+};
 
 #[derive(Debug, Clone)]
 pub struct OperatorMockDB {
@@ -18,6 +23,15 @@ impl OperatorMockDB {
             connector_tree_preimages: Vec::new(),
         }
     }
+
+    /// Creates a database that keeps its common content in `store`.
+    pub fn with_store(store: Arc<dyn StateStore>) -> Self {
+        Self {
+            common_db: Database::with_store(store),
+            deposit_take_sigs: Vec::new(),
+            connector_tree_preimages: Vec::new(),
+        }
+    }
 }
 
 impl Deref for OperatorMockDB {
@@ -43,6 +57,16 @@ impl OperatorMockDB {
         self.deposit_take_sigs.push(deposit_take_sigs);
     }
 
+    /// Claim outputs presigned for the deposit, if it is presigned.
+    pub fn get_deposit_claim_outputs(
+        &self,
+        deposit_index: usize,
+    ) -> Option<Vec<(Amount, Address)>> {
+        self.deposit_take_sigs
+            .get(deposit_index)
+            .map(|deposit_take_sigs| deposit_take_sigs.claim_outputs.clone())
+    }
+
     pub fn get_connector_tree_preimages_level(
         &self,
         period: usize,
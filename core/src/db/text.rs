@@ -1,85 +1,96 @@
 //! # Text Database
 //!
-//! Raw text file as the database. This is a simple solution to the database
+//! Raw text files as the database. This is a simple solution to the database
 //! problem. It might not be a suitable solution. Therefore, it might be better
-//! to use a DBMS in production.
+//! to plug a DBMS into `StateStore` in production.
 //!
 //! ## File Format
 //!
-//! Data is serialized using serde, before writing to the file. So, it's a JSON
-//! file.
+//! Every key is a file in the directory of the store. `Database` serializes
+//! its content using serde, before writing to the file. So, it's a JSON file.
 
-use super::common::DatabaseContent;
-use serde::{Deserialize, Serialize};
+use crate::{errors::BridgeError, traits::state_store::StateStore};
 use std::io::prelude::*;
 use std::{
     fs::{self, File},
     path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
-/// Configuration for the text database.
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
-pub struct TextDatabase {
-    /// File to save database.
-    file: PathBuf,
+/// Number of temporary files created by this process, makes their names
+/// unique.
+static TEMP_FILE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// File backed `StateStore`, the default one.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FileStateStore {
+    /// Directory to save the files in.
+    directory: PathBuf,
 }
-impl TextDatabase {
-    pub fn new(file: PathBuf) -> Self {
-        Self { file }
+impl FileStateStore {
+    pub fn new(directory: PathBuf) -> Self {
+        Self { directory }
     }
+}
 
-    /// Reads data from a text file and returns deserialized `DatabaseContent`.
-    pub fn read(&self) -> Result<DatabaseContent, std::io::Error> {
-        let contents = fs::read_to_string(&self.file)?;
-
-        let deserialized: DatabaseContent = serde_json::from_str(&contents)?;
-
-        Ok(deserialized)
+impl StateStore for FileStateStore {
+    /// Reads the file named `key`.
+    fn load(&self, key: &str) -> Result<Vec<u8>, BridgeError> {
+        fs::read(self.directory.join(key)).map_err(BridgeError::DatabaseError)
     }
 
-    /// Serializes data and writes to a text file.
-    pub fn write(&self, content: DatabaseContent) -> Result<(), std::io::Error> {
-        let serialized = serde_json::to_string(&content)?;
-
-        let mut file = File::create(&self.file)?;
-
-        file.write_all(serialized.as_bytes())
+    /// Writes `value` to a temporary file, then renames it to the file named
+    /// `key`. A crash while writing leaves the previous value in place. The
+    /// temporary file is unique to the save, so concurrent saves of a key
+    /// do not write into each other's file; the last rename wins.
+    fn save(&self, key: &str, value: &[u8]) -> Result<(), BridgeError> {
+        let temp_path = self.directory.join(format!(
+            "{}.{}.{}.tmp",
+            key,
+            std::process::id(),
+            TEMP_FILE_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mut file = File::create(&temp_path).map_err(BridgeError::DatabaseError)?;
+
+        file.write_all(value).map_err(BridgeError::DatabaseError)?;
+        file.sync_all().map_err(BridgeError::DatabaseError)?;
+        fs::rename(&temp_path, self.directory.join(key)).map_err(BridgeError::DatabaseError)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::fs;
+    use std::{fs, io::ErrorKind};
 
-    use crate::db::{common::DatabaseContent, text::TextDatabase};
+    use crate::{db::text::FileStateStore, errors::BridgeError, traits::state_store::StateStore};
 
     const DATABASE: &str = "text_database";
 
     #[test]
     fn new() {
         assert_eq!(
-            TextDatabase::new(DATABASE.into()),
-            TextDatabase {
-                file: DATABASE.into()
+            FileStateStore::new(".".into()),
+            FileStateStore {
+                directory: ".".into()
             }
         );
     }
 
-    /// Writes mock database to file, then reads it. Compares if input equals
+    /// Writes a value to file, then reads it. Compares if input equals
     /// output.
     #[test]
     fn write_read() {
-        let expected = DatabaseContent::new();
-        let dbms = TextDatabase::new(DATABASE.into());
+        let expected = b"{}".to_vec();
+        let store = FileStateStore::default();
 
         // Check if file is writable.
-        match dbms.write(expected.clone()) {
+        match store.save(DATABASE, &expected) {
             Ok(_) => assert!(true),
             Err(_) => assert!(false),
         };
 
         // Check if read operation is successful.
-        match dbms.read() {
+        match store.load(DATABASE) {
             Ok(c) => assert_eq!(expected, c),
             Err(_) => assert!(false),
         }
@@ -89,5 +100,38 @@ mod tests {
             Ok(_) => assert!(true),
             Err(_) => assert!(false),
         }
+
+        // Missing keys are reported as not found.
+        match store.load(DATABASE) {
+            Err(BridgeError::DatabaseError(e)) => assert_eq!(e.kind(), ErrorKind::NotFound),
+            _ => assert!(false),
+        }
+    }
+
+    /// Saves a key from several threads at once. Every save must succeed,
+    /// leave one of the written values and no temporary files behind.
+    #[test]
+    fn concurrent_saves() {
+        let directory = std::env::temp_dir().join(format!("text_store_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let store = FileStateStore::new(directory.clone());
+
+        let values: Vec<Vec<u8>> = (0..8u8).map(|i| vec![i; 1024]).collect();
+        std::thread::scope(|scope| {
+            for value in values.iter() {
+                let store = &store;
+                scope.spawn(move || {
+                    for _ in 0..10 {
+                        store.save(DATABASE, value).unwrap();
+                    }
+                });
+            }
+        });
+
+        assert!(values.contains(&store.load(DATABASE).unwrap()));
+        let files: Vec<_> = fs::read_dir(&directory).unwrap().collect();
+        assert_eq!(files.len(), 1);
+
+        fs::remove_dir_all(directory).unwrap();
     }
 }
@@ -1,6 +1,10 @@
-use std::ops::{Deref, DerefMut};
+use std::{
+    ops::{Deref, DerefMut},
+    sync::Arc,
+};
 
 use super::common::Database;
+use crate::traits::state_store::StateStore;
 
 #[derive(Debug, Clone)]
 pub struct VerifierMockDB {
@@ -13,6 +17,13 @@ impl VerifierMockDB {
             common_db: Database::new(),
         }
     }
+
+    /// Creates a database that keeps its content in `store`.
+    pub fn with_store(store: Arc<dyn StateStore>) -> Self {
+        Self {
+            common_db: Database::with_store(store),
+        }
+    }
 }
 
 impl Deref for VerifierMockDB {
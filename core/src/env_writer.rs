@@ -275,7 +275,7 @@ impl<E: Environment> ENVWriter<E> {
     pub fn write_blocks_and_add_to_merkle_tree<const DEPTH: usize>(
         block_headers: Vec<Header>,
         blockhashes_mt: &mut MerkleTree<DEPTH>,
-    ) {
+    ) -> Result<(), BridgeError> {
         E::write_u32(block_headers.len() as u32);
         tracing::debug!(
             "WROTE block_headers.len(): {:?}",
@@ -284,8 +284,9 @@ impl<E: Environment> ENVWriter<E> {
         for header in block_headers.iter() {
             ENVWriter::<E>::write_block_header_without_prev(header);
             // tracing::debug!("WROTE block header without prev: {:?}", header);
-            blockhashes_mt.add(serialize(&header.block_hash()).try_into().unwrap());
+            blockhashes_mt.add(serialize(&header.block_hash()).try_into()?)?;
         }
+        Ok(())
     }
 
     pub fn write_preimages(operator_pk: XOnlyPublicKey, preimages: &Vec<[u8; 32]>) {
@@ -502,7 +503,8 @@ mod tests {
         ENVWriter::<MockEnvironment>::write_blocks_and_add_to_merkle_tree(
             headers.clone(),
             &mut write_mt,
-        );
+        )
+        .unwrap();
 
         let mut read_imt = IncrementalMerkleTree::<32>::new();
         let res = read_blocks_and_add_to_merkle_tree::<MockEnvironment>(
@@ -514,7 +516,9 @@ mod tests {
         let mut test_mt = MerkleTree::<32>::new();
 
         for header in headers {
-            test_mt.add(serialize(&header.block_hash()).try_into().unwrap());
+            test_mt
+                .add(serialize(&header.block_hash()).try_into().unwrap())
+                .unwrap();
         }
 
         // Make sure merkle trees are set up correctly
@@ -568,7 +572,9 @@ mod tests {
         let mut serialized_headers = Vec::new();
         for header in headers.iter() {
             serialized_headers.push(serialize(&header.block_hash()).try_into().unwrap());
-            test_mt.add(serialize(&header.block_hash()).try_into().unwrap());
+            test_mt
+                .add(serialize(&header.block_hash()).try_into().unwrap())
+                .unwrap();
             read_imt.add(serialize(&header.block_hash()).try_into().unwrap());
         }
 
@@ -7,6 +7,8 @@ use core::fmt::Debug;
 use std::array::TryFromSliceError;
 use thiserror::Error;
 
+use crate::verifier::SigningRequestKind;
+
 /// Errors related to periods
 #[derive(Debug, Error)]
 pub enum InvalidPeriodError {
@@ -16,6 +18,10 @@ pub enum InvalidPeriodError {
     PreimageRevealPeriodMismatch,
     #[error("DepositPeriodMismatch")]
     InscriptionPeriodMismatch,
+    #[error("PeriodNotFound")]
+    PeriodNotFound,
+    #[error("DepositPeriodMismatch")]
+    DepositPeriodMismatch,
 }
 /// Errors returned by the bridge
 #[derive(Debug, Error)]
@@ -63,6 +69,9 @@ pub enum BridgeError {
     /// TxInputNotFound is returned when the input is not found in the transaction
     #[error("TxInputNotFound")]
     TxInputNotFound,
+    /// TxOutputNotFound is returned when the output is not found in the transaction
+    #[error("TxOutputNotFound")]
+    TxOutputNotFound,
     /// PreimageNotFound is returned when the preimage is not found in the the connector tree or claim proof
     #[error("PreimageNotFound")]
     PreimageNotFound,
@@ -105,8 +114,77 @@ pub enum BridgeError {
     /// Database could not handle request and returned an error
     #[error("DatabaseError")]
     DatabaseError(std::io::Error),
+    /// Relative timelock is out of the BIP68 range or the sequence does not encode one
+    #[error("InvalidTimelock")]
+    InvalidTimelock,
+    /// Returned when a transaction derived locally differs from the one derived by another party
+    #[error("ConsensusMismatch")]
+    ConsensusMismatch,
+    /// Returned when a leaf is added to a merkle tree that has no empty leaves left
+    #[error("MerkleTreeFull")]
+    MerkleTreeFull,
+    /// Returned when a leaf index is bigger than the number of leaves in a merkle tree
+    #[error("MerkleIndexOutOfBounds")]
+    MerkleIndexOutOfBounds,
+    /// Returned when the deposit index is bigger than the number of connector tree leaves
+    #[error("InvalidDepositIndex")]
+    InvalidDepositIndex,
+    /// FeeTooLow is returned when the fee rate is below the configured floor
+    #[error("FeeTooLow")]
+    FeeTooLow,
+    /// FeeTooHigh is returned when the fee rate is above the configured ceiling
+    #[error("FeeTooHigh")]
+    FeeTooHigh,
+    /// ConnectorTreeRootMismatch is returned when the connector tree leaves do not commit to the expected root
+    #[error("ConnectorTreeRootMismatch")]
+    ConnectorTreeRootMismatch,
+    /// DuplicateVerifier is returned when the same public key is given for more than one verifier
+    #[error("DuplicateVerifier")]
+    DuplicateVerifier,
+    /// DuplicateDepositHash is returned when a connector hash is already presigned for another deposit
+    #[error("DuplicateDepositHash")]
+    DuplicateDepositHash,
+    /// OperatorPendingDeposit is returned when an operator requests presigns faster than its rate limit allows
+    #[error("OperatorPendingDeposit")]
+    OperatorPendingDeposit,
+    /// ConnectorTreeValueMismatch is returned when the outputs of a connector tree node and its fee do not add up to its value
+    #[error("ConnectorTreeValueMismatch")]
+    ConnectorTreeValueMismatch,
+    /// DecryptionError is returned when an encrypted key can not be decrypted, most likely because of a wrong passphrase
+    #[error("DecryptionError")]
+    DecryptionError,
+    /// InvalidClaimOutputs is returned when the outputs of an operator claim do not add up to the claim amount or one of them is dust
+    #[error("InvalidClaimOutputs")]
+    InvalidClaimOutputs,
+    /// WatcherPanicked is returned when a watch loop of a `WatcherSet` panics
+    #[error("WatcherPanicked")]
+    WatcherPanicked,
+    /// InvalidThreshold is returned when a signature threshold is zero or bigger than the number of signers
+    #[error("InvalidThreshold")]
+    InvalidThreshold,
+    /// InvalidPresign is returned when a presigned signature of a verifier does not verify, naming the transaction it is for
+    #[error("InvalidPresign: {0:?}")]
+    InvalidPresign(SigningRequestKind),
+    /// PresignCountMismatch is returned when a verifier presigned a different number of operator claims than there are rounds
+    #[error("PresignCountMismatch")]
+    PresignCountMismatch,
+    /// InvalidConnectorTreeSpend is returned when a transaction spends connector tree nodes into neither `CONNECTOR_TREE_ARITY` children each nor a single output
+    #[error("InvalidConnectorTreeSpend")]
+    InvalidConnectorTreeSpend,
+    /// VerifierSetMismatch is returned when a request is for another verifier set than the one of the verifier
+    #[error("VerifierSetMismatch")]
+    VerifierSetMismatch,
+    /// TxIndexDisabled is returned when the Bitcoin node does not keep a transaction index
+    #[error("TxIndexDisabled")]
+    TxIndexDisabled,
+    /// EsploraError is returned when a request to an Esplora server fails or its response can not be read
+    #[error("EsploraError: {0}")]
+    EsploraError(String),
 }
 
+/// Result type used by the bridge
+pub type BridgeResult<T> = Result<T, BridgeError>;
+
 impl From<secp256k1::Error> for BridgeError {
     fn from(err: secp256k1::Error) -> Self {
         BridgeError::Secpk256Error(err)
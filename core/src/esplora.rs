@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::io::Read;
+
+use bitcoin::consensus::{deserialize, encode::serialize_hex};
+use bitcoin::{Block, BlockHash, FeeRate, OutPoint, Transaction, TxOut, Txid};
+use serde::Deserialize;
+
+use crate::errors::BridgeError;
+use crate::traits::chain_backend::ChainBackend;
+
+/// `ChainBackend` on the HTTP API of an Esplora server, for operators that do
+/// not run a full node. `base_url` is the API root, like
+/// `https://blockstream.info/api`.
+#[derive(Debug, Clone)]
+pub struct EsploraBackend {
+    pub base_url: String,
+    agent: ureq::Agent,
+}
+
+#[derive(Debug, Deserialize)]
+struct TxStatus {
+    confirmed: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct OutSpend {
+    spent: bool,
+    status: Option<TxStatus>,
+}
+
+impl EsploraBackend {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            agent: ureq::Agent::new(),
+        }
+    }
+
+    /// Response of `GET {base_url}{path}`, `None` if the server does not
+    /// know the requested object.
+    fn get(&self, path: &str) -> Result<Option<ureq::Response>, BridgeError> {
+        match self.agent.get(&format!("{}{}", self.base_url, path)).call() {
+            Ok(response) => Ok(Some(response)),
+            Err(ureq::Error::Status(404, _)) => Ok(None),
+            Err(e) => Err(BridgeError::EsploraError(e.to_string())),
+        }
+    }
+
+    fn get_text(&self, path: &str) -> Result<Option<String>, BridgeError> {
+        self.get(path)?
+            .map(|response| {
+                response
+                    .into_string()
+                    .map_err(|e| BridgeError::EsploraError(e.to_string()))
+            })
+            .transpose()
+    }
+
+    fn get_bytes(&self, path: &str) -> Result<Option<Vec<u8>>, BridgeError> {
+        self.get(path)?
+            .map(|response| {
+                let mut bytes = Vec::new();
+                response
+                    .into_reader()
+                    .read_to_end(&mut bytes)
+                    .map_err(|e| BridgeError::EsploraError(e.to_string()))?;
+                Ok(bytes)
+            })
+            .transpose()
+    }
+
+    fn get_json<T: for<'de> Deserialize<'de>>(&self, path: &str) -> Result<Option<T>, BridgeError> {
+        self.get_text(path)?
+            .map(|text| {
+                serde_json::from_str(&text).map_err(|e| BridgeError::EsploraError(e.to_string()))
+            })
+            .transpose()
+    }
+}
+
+/// Esplora only estimates a fixed set of confirmation targets, so takes the
+/// estimate of the largest target that is not above `conf_target`. Rates are
+/// in sat/vB.
+fn fee_rate_for_target(estimates: &HashMap<String, f64>, conf_target: u16) -> Option<FeeRate> {
+    estimates
+        .iter()
+        .filter_map(|(target, sat_per_vb)| Some((target.parse::<u16>().ok()?, *sat_per_vb)))
+        .filter(|(target, _)| *target <= conf_target)
+        .max_by_key(|(target, _)| *target)
+        // 1 sat/vB is 250 sat/kwu
+        .map(|(_, sat_per_vb)| FeeRate::from_sat_per_kwu((sat_per_vb * 250.0).ceil() as u64))
+}
+
+impl ChainBackend for EsploraBackend {
+    fn get_best_block_hash(&self) -> Result<BlockHash, BridgeError> {
+        self.get_text("/blocks/tip/hash")?
+            .ok_or(BridgeError::BlockhashNotFound)?
+            .trim()
+            .parse()
+            .map_err(|_| BridgeError::BlockhashNotFound)
+    }
+
+    fn get_block_count(&self) -> Result<u64, BridgeError> {
+        let height = self
+            .get_text("/blocks/tip/height")?
+            .ok_or(BridgeError::BlockNotFound)?;
+        height
+            .trim()
+            .parse()
+            .map_err(|_| BridgeError::EsploraError(format!("Invalid block height {}", height)))
+    }
+
+    fn get_block(&self, block_hash: &BlockHash) -> Result<Block, BridgeError> {
+        let bytes = self
+            .get_bytes(&format!("/block/{}/raw", block_hash))?
+            .ok_or(BridgeError::BlockNotFound)?;
+        deserialize(&bytes).map_err(|_| BridgeError::BlockNotFound)
+    }
+
+    fn get_block_hash(&self, height: u64) -> Result<BlockHash, BridgeError> {
+        self.get_text(&format!("/block-height/{}", height))?
+            .ok_or(BridgeError::BlockhashNotFound)?
+            .trim()
+            .parse()
+            .map_err(|_| BridgeError::BlockhashNotFound)
+    }
+
+    /// Like the Core RPC implementation, only confirmed outputs count and
+    /// spends that are only in the mempool are ignored.
+    fn get_tx_out(&self, outpoint: &OutPoint) -> Result<Option<TxOut>, BridgeError> {
+        let Some(status) = self.get_json::<TxStatus>(&format!("/tx/{}/status", outpoint.txid))?
+        else {
+            return Ok(None);
+        };
+        if !status.confirmed {
+            return Ok(None);
+        }
+        let outspend = self
+            .get_json::<OutSpend>(&format!("/tx/{}/outspend/{}", outpoint.txid, outpoint.vout))?
+            .ok_or(BridgeError::TxOutputNotFound)?;
+        if outspend.spent && outspend.status.is_some_and(|status| status.confirmed) {
+            return Ok(None);
+        }
+        let bytes = self
+            .get_bytes(&format!("/tx/{}/raw", outpoint.txid))?
+            .ok_or(BridgeError::TxidNotFound)?;
+        let tx: Transaction =
+            deserialize(&bytes).map_err(|_| BridgeError::BitcoinTransactionError)?;
+        Ok(tx.output.get(outpoint.vout as usize).cloned())
+    }
+
+    fn send_raw_transaction(&self, tx: &Transaction) -> Result<Txid, BridgeError> {
+        let txid = self
+            .agent
+            .post(&format!("{}/tx", self.base_url))
+            .send_string(&serialize_hex(tx))
+            .map_err(|e| BridgeError::EsploraError(e.to_string()))?
+            .into_string()
+            .map_err(|e| BridgeError::EsploraError(e.to_string()))?;
+        txid.trim()
+            .parse()
+            .map_err(|_| BridgeError::EsploraError(format!("Invalid txid {}", txid)))
+    }
+
+    fn estimate_fee_rate(&self, conf_target: u16) -> Result<Option<FeeRate>, BridgeError> {
+        Ok(self
+            .get_json::<HashMap<String, f64>>("/fee-estimates")?
+            .and_then(|estimates| fee_rate_for_target(&estimates, conf_target)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fee_rate_for_target;
+    use bitcoin::FeeRate;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_fee_rate_for_target() {
+        let estimates: HashMap<String, f64> =
+            serde_json::from_str(r#"{"1": 20.5, "2": 12.0, "6": 5.0, "144": 1.0}"#).unwrap();
+
+        assert_eq!(
+            fee_rate_for_target(&estimates, 1),
+            Some(FeeRate::from_sat_per_kwu(5125))
+        );
+        // Targets between the estimated ones fall back to the next faster one
+        assert_eq!(
+            fee_rate_for_target(&estimates, 5),
+            Some(FeeRate::from_sat_per_vb_unchecked(12))
+        );
+        assert_eq!(
+            fee_rate_for_target(&estimates, 1008),
+            Some(FeeRate::from_sat_per_vb_unchecked(1))
+        );
+        assert_eq!(fee_rate_for_target(&estimates, 0), None);
+    }
+}
@@ -11,13 +11,45 @@ use bitcoincore_rpc::Client;
 use bitcoincore_rpc::RpcApi;
 use crypto_bigint::Encoding;
 use crypto_bigint::U256;
+use std::collections::hash_map::{Entry, HashMap};
 use std::env;
+use std::time::Duration;
 
-use crate::errors::BridgeError;
+use crate::errors::{BridgeError, BridgeResult};
+use crate::utils::{is_rpc_not_found, rpc_retry, send_retry};
+
+/// Spend status of a UTXO, see `ExtendedRpc::utxo_spend_status`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UtxoSpendStatus {
+    Unspent,
+    SpentConfirmed,
+    SpentMempool,
+    /// The UTXO was never created, as far as the node knows
+    NotFound,
+}
+
+/// How `ExtendedRpc` retries calls that fail because the node is unreachable
+/// or busy, see `utils::rpc_retry`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RpcRetryPolicy {
+    pub max_retries: u32,
+    /// Wait before the first retry, doubled for every retry after it
+    pub base_delay: Duration,
+}
+
+impl Default for RpcRetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct ExtendedRpc {
     pub inner: Client,
+    pub retry_policy: RpcRetryPolicy,
 }
 
 impl Clone for ExtendedRpc {
@@ -32,7 +64,10 @@ impl Clone for ExtendedRpc {
         let new_client = Client::new(&rpc_url, Auth::UserPass(rpc_user, rpc_pass))
             .unwrap_or_else(|e| panic!("Failed to clone Bitcoin RPC client: {}", e));
 
-        Self { inner: new_client }
+        Self {
+            inner: new_client,
+            retry_policy: self.retry_policy,
+        }
     }
 }
 
@@ -50,7 +85,22 @@ impl ExtendedRpc {
             Auth::UserPass("admin".to_string(), "admin".to_string()),
         )
         .unwrap_or_else(|e| panic!("Failed to connect to Bitcoin RPC: {}", e));
-        Self { inner: rpc }
+        Self {
+            inner: rpc,
+            retry_policy: RpcRetryPolicy::default(),
+        }
+    }
+
+    /// Runs `call` with the retries of `retry_policy`
+    pub fn with_retry<T>(
+        &self,
+        call: impl FnMut() -> Result<T, bitcoincore_rpc::Error>,
+    ) -> BridgeResult<T> {
+        rpc_retry(
+            self.retry_policy.max_retries,
+            self.retry_policy.base_delay,
+            call,
+        )
     }
 
     pub fn confirmation_blocks(&self, txid: &bitcoin::Txid) -> Result<u32, BridgeError> {
@@ -68,21 +118,65 @@ impl ExtendedRpc {
         amount_sats: u64,
     ) -> Result<bool, BridgeError> {
         let tx = self.inner.get_raw_transaction(&outpoint.txid, None)?;
-        let current_output = tx.output[outpoint.vout as usize].clone();
+        let current_output = match tx.output.get(outpoint.vout as usize) {
+            Some(output) => output,
+            None => return Ok(false),
+        };
         let expected_output = TxOut {
             script_pubkey: address.clone(),
             value: Amount::from_sat(amount_sats),
         };
-        Ok(expected_output == current_output)
+        Ok(expected_output == *current_output)
     }
 
-    pub fn is_utxo_spent(&self, _outpoint: &OutPoint) -> Result<bool, BridgeError> {
+    /// Checks if the UTXO is spent. With `include_mempool`, spends that are
+    /// only in the mempool count too.
+    pub fn is_utxo_spent(
+        &self,
+        outpoint: &OutPoint,
+        include_mempool: bool,
+    ) -> Result<bool, BridgeError> {
         let res = self
             .inner
-            .get_tx_out(&_outpoint.txid, _outpoint.vout, Some(true))?;
+            .get_tx_out(&outpoint.txid, outpoint.vout, Some(include_mempool))?;
         Ok(res.is_none())
     }
 
+    /// Returns the amount of the UTXO, or `None` if it does not exist or is
+    /// spent, mempool spends included.
+    pub fn get_utxo_amount(&self, outpoint: &OutPoint) -> Result<Option<Amount>, BridgeError> {
+        let res = self
+            .inner
+            .get_tx_out(&outpoint.txid, outpoint.vout, Some(true))?;
+        Ok(res.map(|tx_out| tx_out.value))
+    }
+
+    /// Returns whether the UTXO is unspent, spent in a block, spent only in
+    /// the mempool or was never created. A mempool spend can still be dropped,
+    /// so it should not be treated as final. Telling a spent UTXO from one
+    /// that never existed needs `-txindex`, see `check_txindex`.
+    pub fn utxo_spend_status(&self, outpoint: &OutPoint) -> Result<UtxoSpendStatus, BridgeError> {
+        let confirmed = self
+            .inner
+            .get_tx_out(&outpoint.txid, outpoint.vout, Some(false))?;
+        let with_mempool = self
+            .inner
+            .get_tx_out(&outpoint.txid, outpoint.vout, Some(true))?;
+
+        Ok(match (confirmed, with_mempool) {
+            (_, Some(_)) => UtxoSpendStatus::Unspent,
+            (Some(_), None) => UtxoSpendStatus::SpentMempool,
+            (None, None) => match self.inner.get_raw_transaction(&outpoint.txid, None) {
+                Ok(tx) if (outpoint.vout as usize) < tx.output.len() => {
+                    UtxoSpendStatus::SpentConfirmed
+                }
+                Ok(_) => UtxoSpendStatus::NotFound,
+                Err(e) if is_rpc_not_found(&e) => UtxoSpendStatus::NotFound,
+                Err(e) => return Err(e.into()),
+            },
+        })
+    }
+
     pub fn generate_dummy_block(&self) -> Result<Vec<bitcoin::BlockHash>, BridgeError> {
         // Use `generatetoaddress` or similar RPC method to mine a new block
         // containing the specified transactions
@@ -177,6 +271,38 @@ impl ExtendedRpc {
         Ok(total_work)
     }
 
+    /// Height of the block that includes the transaction, or `None` if the
+    /// node does not know it or it is not in the active chain.
+    pub fn confirmation_height(&self, txid: &bitcoin::Txid) -> Result<Option<u64>, BridgeError> {
+        let info = match self.inner.get_raw_transaction_info(txid, None) {
+            Ok(info) => info,
+            // The transaction is unknown
+            Err(e) if is_rpc_not_found(&e) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let confirmations = match info.confirmations {
+            Some(confirmations) if confirmations > 0 => confirmations as u64,
+            _ => return Ok(None),
+        };
+        let tip_height = self.get_block_height()?;
+        Ok(Some((tip_height + 1).saturating_sub(confirmations)))
+    }
+
+    /// Checks that the node keeps a transaction index (`-txindex`). Without
+    /// it, lookups of confirmed transactions outside the wallet fail as if
+    /// the transactions did not exist, which the bridge would take as a
+    /// refund or a reorg.
+    pub fn check_txindex(&self) -> Result<(), BridgeError> {
+        let indexes: HashMap<String, serde_json::Value> = self.inner.call("getindexinfo", &[])?;
+        if !indexes.contains_key("txindex") {
+            tracing::error!(
+                "Bitcoin node does not keep a transaction index, start it with -txindex"
+            );
+            return Err(BridgeError::TxIndexDisabled);
+        }
+        Ok(())
+    }
+
     pub fn get_block_height(&self) -> Result<u64, BridgeError> {
         let chain_info = self.inner.get_blockchain_info()?;
         let block_height = chain_info.blocks;
@@ -206,6 +332,30 @@ impl ExtendedRpc {
         self.inner.get_raw_transaction(txid, block_hash)
     }
 
+    /// Looks up the output spent by each input of `tx`, in input order, to be
+    /// used as the prevouts of taproot sighashes. Needs the previous
+    /// transactions to be in the mempool, the wallet or the `txindex`.
+    pub fn fetch_prevouts(&self, tx: &Transaction) -> Result<Vec<TxOut>, BridgeError> {
+        let mut prev_txs: HashMap<bitcoin::Txid, Transaction> = HashMap::new();
+        tx.input
+            .iter()
+            .map(|input| {
+                let outpoint = input.previous_output;
+                let prev_tx = match prev_txs.entry(outpoint.txid) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => {
+                        entry.insert(self.inner.get_raw_transaction(&outpoint.txid, None)?)
+                    }
+                };
+                prev_tx
+                    .output
+                    .get(outpoint.vout as usize)
+                    .cloned()
+                    .ok_or(BridgeError::TxOutputNotFound)
+            })
+            .collect()
+    }
+
     pub fn get_transaction(
         &self,
         txid: &bitcoin::Txid,
@@ -214,11 +364,23 @@ impl ExtendedRpc {
         self.inner.get_transaction(txid, include_watchonly)
     }
 
-    pub fn send_raw_transaction(
+    /// Sending a transaction again is harmless, so it is retried, see
+    /// `utils::send_retry`.
+    pub fn send_raw_transaction(&self, tx: &Transaction) -> BridgeResult<bitcoin::Txid> {
+        send_retry(
+            self.retry_policy.max_retries,
+            self.retry_policy.base_delay,
+            tx.txid(),
+            || self.inner.send_raw_transaction(tx),
+        )
+    }
+
+    pub fn test_mempool_accept(
         &self,
-        tx: &Transaction,
-    ) -> Result<bitcoin::Txid, bitcoincore_rpc::Error> {
-        self.inner.send_raw_transaction(tx)
+        txs: &[Transaction],
+    ) -> Result<Vec<bitcoincore_rpc::json::TestMempoolAcceptResult>, bitcoincore_rpc::Error> {
+        self.inner
+            .test_mempool_accept(&txs.iter().collect::<Vec<_>>())
     }
 
     pub fn get_block(
@@ -6,17 +6,21 @@ pub mod constants;
 pub mod db;
 pub mod env_writer;
 pub mod errors;
+pub mod esplora;
 pub mod extended_rpc;
 pub mod keys;
 pub mod merkle;
 pub mod mock_env;
 pub mod operator;
+pub mod rate_limit;
 pub mod script_builder;
+pub mod timelock;
 pub mod traits;
 pub mod transaction_builder;
 pub mod user;
 pub mod utils;
 pub mod verifier;
+pub mod watcher;
 
 pub type ConnectorUTXOTree = Vec<Vec<OutPoint>>;
 pub type HashTree = Vec<Vec<HashType>>;
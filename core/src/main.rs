@@ -23,10 +23,11 @@ use tracing_subscriber::{fmt, EnvFilter};
 lazy_static::lazy_static! {
     static ref SHARED_STATE: Mutex<i32> = Mutex::new(0);
 }
-use clementine_core::keys;
+use clementine_core::{keys, utils};
 
 fn test_flow() -> Result<(), BridgeError> {
     let rpc = ExtendedRpc::new();
+    rpc.check_txindex()?;
 
     let secp = bitcoin::secp256k1::Secp256k1::new();
 
@@ -164,5 +165,6 @@ pub fn initialize_logging() {
 
 fn main() {
     initialize_logging();
+    utils::check_connector_tree_csv();
     test_flow().unwrap();
 }
@@ -1,8 +1,10 @@
-use clementine_circuits::constants::{EMPTYDATA, ZEROES};
+use clementine_circuits::constants::{EMPTYDATA, MAX_MERKLE_DEPTH, ZEROES};
 use clementine_circuits::incremental_merkle::IncrementalMerkleTree;
 use clementine_circuits::{sha256_hash, HashType};
 use serde::{Deserialize, Serialize};
 
+use crate::errors::{BridgeError, BridgeResult};
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct MerkleTree<const DEPTH: usize> {
     data: Vec<Vec<HashType>>,
@@ -16,7 +18,11 @@ impl<const DEPTH: usize> Default for MerkleTree<DEPTH> {
 }
 
 impl<const DEPTH: usize> MerkleTree<DEPTH> {
+    /// Fails to compile for trees deeper than the `ZEROES` table
+    const DEPTH_IS_SUPPORTED: () = assert!(DEPTH <= MAX_MERKLE_DEPTH, "merkle tree is too deep");
+
     pub fn new() -> Self {
+        let () = Self::DEPTH_IS_SUPPORTED;
         Self {
             data: {
                 let mut v = Vec::new();
@@ -29,7 +35,10 @@ impl<const DEPTH: usize> MerkleTree<DEPTH> {
         }
     }
 
-    pub fn add(&mut self, a: HashType) {
+    pub fn add(&mut self, a: HashType) -> BridgeResult<()> {
+        if self.index as u64 >= 1u64 << DEPTH {
+            return Err(BridgeError::MerkleTreeFull);
+        }
         let mut current_index = self.index;
         let mut current_level_hash = a;
         let trz = self.index.trailing_zeros();
@@ -49,6 +58,7 @@ impl<const DEPTH: usize> MerkleTree<DEPTH> {
             current_index /= 2;
         }
         self.index += 1;
+        Ok(())
     }
 
     pub fn path(&self, index: u32) -> [HashType; DEPTH] {
@@ -66,6 +76,41 @@ impl<const DEPTH: usize> MerkleTree<DEPTH> {
         p
     }
 
+    /// Brings a proof returned by `path` up to date with the leaves added
+    /// since. Left siblings can not change after the leaf is added, so only the
+    /// right siblings are refreshed.
+    pub fn update_proof(
+        &self,
+        proof: &mut [HashType; DEPTH],
+        proof_leaf_index: u32,
+    ) -> BridgeResult<()> {
+        if proof_leaf_index >= self.index {
+            return Err(BridgeError::MerkleIndexOutOfBounds);
+        }
+        let mut i = proof_leaf_index as usize;
+        for level in 0..DEPTH {
+            if i % 2 == 0 {
+                proof[level] = match self.data[level].get(i + 1) {
+                    Some(sibling) => *sibling,
+                    None => ZEROES[level],
+                };
+            }
+            i /= 2;
+        }
+        Ok(())
+    }
+
+    /// Inclusion proof of the leaf at `leaf_index`, checked against the root
+    /// of this tree or the `IncrementalMerkleTree` with the same leaves by
+    /// `incremental_merkle::verify_proof`. The incremental tree forgets the
+    /// nodes it does not need to add leaves, so proofs are generated here.
+    pub fn generate_proof(&self, leaf_index: u32) -> BridgeResult<[HashType; DEPTH]> {
+        if leaf_index >= self.index {
+            return Err(BridgeError::MerkleIndexOutOfBounds);
+        }
+        Ok(self.path(leaf_index))
+    }
+
     pub fn root(&self) -> HashType {
         if self.data[DEPTH].is_empty() {
             ZEROES[DEPTH]
@@ -84,7 +129,10 @@ impl<const DEPTH: usize> MerkleTree<DEPTH> {
         None
     }
 
-    pub fn to_incremental_tree(&self, index: u32) -> IncrementalMerkleTree<DEPTH> {
+    pub fn to_incremental_tree(&self, index: u32) -> BridgeResult<IncrementalMerkleTree<DEPTH>> {
+        if index >= self.index {
+            return Err(BridgeError::MerkleIndexOutOfBounds);
+        }
         let mut fst = [EMPTYDATA; DEPTH];
         let mut i = index as usize;
         let mut current_level_hash = self.data[0][i];
@@ -102,19 +150,138 @@ impl<const DEPTH: usize> MerkleTree<DEPTH> {
             current_level_hash = sha256_hash!(left, right);
             i /= 2;
         }
-        IncrementalMerkleTree {
+        Ok(IncrementalMerkleTree {
             filled_subtrees: fst,
             root: current_level_hash,
             index,
-        }
+        })
     }
 }
 
+/// Adopts incremental tree state computed by another component, rejecting
+/// parts that are not consistent with each other.
+pub fn incremental_tree_from_parts<const DEPTH: usize>(
+    filled_subtrees: [HashType; DEPTH],
+    root: HashType,
+    index: u32,
+) -> BridgeResult<IncrementalMerkleTree<DEPTH>> {
+    IncrementalMerkleTree::from_parts(filled_subtrees, root, index)
+        .ok_or(BridgeError::VecConversionError)
+}
+
+/// Serializes a Merkle path in the layout the EVM side Merkle verifier
+/// expects: the 32 byte sibling hashes from the leaf level up, followed by
+/// the leaf index as a big endian `uint256`.
+pub fn proof_to_evm_bytes(proof: &[HashType], leaf_index: u32) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity((proof.len() + 1) * 32);
+    for sibling in proof {
+        bytes.extend_from_slice(sibling);
+    }
+    let mut index_word = [0u8; 32];
+    index_word[28..].copy_from_slice(&leaf_index.to_be_bytes());
+    bytes.extend_from_slice(&index_word);
+    bytes
+}
+
 // cargo test --package operator --lib  -- merkle::tests::test_merkle_cross_check --nocapture
 #[cfg(test)]
 mod tests {
-    use crate::merkle::MerkleTree;
-    use clementine_circuits::incremental_merkle::IncrementalMerkleTree;
+    use crate::{
+        errors::BridgeError,
+        merkle::{incremental_tree_from_parts, proof_to_evm_bytes, MerkleTree},
+    };
+    use clementine_circuits::incremental_merkle::{
+        verify_leaves, verify_proof, IncrementalMerkleTree,
+    };
+    use clementine_circuits::sha256_hash;
+
+    #[test]
+    fn test_imt_internal_consistency() {
+        let mut imt = IncrementalMerkleTree::<3>::new();
+        assert!(imt.verify_internal_consistency());
+        for i in 0..8u8 {
+            imt.add([i; 32]);
+            assert!(imt.verify_internal_consistency());
+        }
+
+        let mut imt = IncrementalMerkleTree::<3>::new();
+        for i in 0..5u8 {
+            imt.add([i; 32]);
+        }
+        let mut wrong_index = imt.clone();
+        wrong_index.index = 4;
+        assert!(!wrong_index.verify_internal_consistency());
+        wrong_index.index = 9;
+        assert!(!wrong_index.verify_internal_consistency());
+
+        let mut wrong_root = imt.clone();
+        wrong_root.root = [0xff; 32];
+        assert!(!wrong_root.verify_internal_consistency());
+
+        let mut wrong_subtree = imt;
+        wrong_subtree.filled_subtrees[2] = [0xff; 32];
+        assert!(!wrong_subtree.verify_internal_consistency());
+    }
+
+    #[test]
+    fn test_incremental_tree_from_parts() {
+        let mut imt = IncrementalMerkleTree::<3>::new();
+        for i in 0..5u8 {
+            imt.add([i; 32]);
+        }
+        let mut adopted =
+            incremental_tree_from_parts(imt.filled_subtrees, imt.root, imt.index).unwrap();
+        imt.add([5; 32]);
+        adopted.add([5; 32]);
+        assert_eq!(adopted.root, imt.root);
+
+        assert!(matches!(
+            incremental_tree_from_parts(imt.filled_subtrees, [0xff; 32], imt.index),
+            Err(BridgeError::VecConversionError)
+        ));
+    }
+
+    #[test]
+    fn test_verify_leaves() {
+        let leaves: Vec<[u8; 32]> = (0..5u8).map(|i| [i; 32]).collect();
+        let mut mt = MerkleTree::<3>::new();
+        for leaf in &leaves {
+            mt.add(*leaf).unwrap();
+        }
+        assert!(verify_leaves::<3>(mt.root(), &leaves));
+        assert!(!verify_leaves::<3>(mt.root(), &leaves[..4]));
+        assert!(!verify_leaves::<3>(mt.root(), &[leaves[1], leaves[0]]));
+        assert!(!verify_leaves::<1>(mt.root(), &leaves));
+    }
+
+    #[test]
+    fn test_generate_proof() {
+        for num_leaves in [1u32, 5, 8] {
+            let mut mt = MerkleTree::<3>::new();
+            let mut imt = IncrementalMerkleTree::<3>::new();
+            for i in 0..num_leaves {
+                mt.add([i as u8; 32]).unwrap();
+                imt.add([i as u8; 32]);
+            }
+            for i in 0..num_leaves {
+                let proof = mt.generate_proof(i).unwrap();
+                assert!(verify_proof([i as u8; 32], i, &proof, imt.root));
+            }
+            assert!(matches!(
+                mt.generate_proof(num_leaves),
+                Err(BridgeError::MerkleIndexOutOfBounds)
+            ));
+        }
+
+        let mut mt = MerkleTree::<3>::new();
+        for i in 0..5 {
+            mt.add([i as u8; 32]).unwrap();
+        }
+        let proof = mt.generate_proof(2).unwrap();
+        assert!(!verify_proof([1u8; 32], 2, &proof, mt.root()));
+        assert!(!verify_proof([2u8; 32], 3, &proof, mt.root()));
+        assert!(!verify_proof([2u8; 32], 10, &proof, mt.root()));
+    }
 
     #[test]
     fn test_merkle_cross_check() {
@@ -128,7 +295,7 @@ mod tests {
         assert_eq!(mt.root(), contract_empty_root);
         assert_eq!(mt.root(), imt.root);
         let a = [1 as u8; 32];
-        mt.add(a);
+        mt.add(a).unwrap();
         imt.add(a);
         let contract_insert_1_root: [u8; 32] = [
             0x15, 0xf4, 0x6f, 0x6e, 0x63, 0xb6, 0xbf, 0x80, 0xf7, 0x1e, 0x67, 0xa6, 0x70, 0x46,
@@ -138,4 +305,69 @@ mod tests {
         assert_eq!(mt.root(), contract_insert_1_root);
         assert_eq!(mt.root(), imt.root);
     }
+
+    #[test]
+    fn test_merkle_tree_full() {
+        let mut mt = MerkleTree::<2>::new();
+        for i in 0..4 {
+            mt.add([i as u8; 32]).unwrap();
+        }
+        assert!(matches!(
+            mt.add([4u8; 32]),
+            Err(BridgeError::MerkleTreeFull)
+        ));
+        assert!(mt.to_incremental_tree(3).is_ok());
+        assert!(matches!(
+            mt.to_incremental_tree(4),
+            Err(BridgeError::MerkleIndexOutOfBounds)
+        ));
+    }
+
+    #[test]
+    fn test_proof_to_evm_bytes() {
+        let mut mt = MerkleTree::<2>::new();
+        for i in 0..3 {
+            mt.add([i as u8; 32]).unwrap();
+        }
+        let path = mt.path(2);
+        let bytes = proof_to_evm_bytes(&path, 2);
+        assert_eq!(bytes.len(), 3 * 32);
+        assert_eq!(bytes[0..32], path[0]);
+        assert_eq!(bytes[32..64], path[1]);
+        assert_eq!(bytes[64..95], [0u8; 31]);
+        assert_eq!(bytes[95], 2);
+    }
+
+    #[test]
+    fn test_update_proof() {
+        let mut mt = MerkleTree::<3>::new();
+        for i in 0..3 {
+            mt.add([i as u8; 32]).unwrap();
+        }
+        let mut proof = mt.path(1);
+        for i in 3..7 {
+            mt.add([i as u8; 32]).unwrap();
+        }
+        assert_ne!(proof, mt.path(1));
+
+        mt.update_proof(&mut proof, 1).unwrap();
+        assert_eq!(proof, mt.path(1));
+
+        let mut root = [1u8; 32];
+        let mut index = 1;
+        for sibling in proof.iter() {
+            root = if index % 2 == 0 {
+                sha256_hash!(root, *sibling)
+            } else {
+                sha256_hash!(*sibling, root)
+            };
+            index /= 2;
+        }
+        assert_eq!(root, mt.root());
+
+        assert!(matches!(
+            mt.update_proof(&mut proof, 7),
+            Err(BridgeError::MerkleIndexOutOfBounds)
+        ));
+    }
 }
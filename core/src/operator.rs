@@ -2,39 +2,43 @@ use std::vec;
 
 use crate::actor::Actor;
 use crate::constants::{
-    VerifierChallenge, CONNECTOR_TREE_DEPTH, DUST_VALUE, K_DEEP,
+    VerifierChallenge, CONNECTOR_TREE_ARITY, CONNECTOR_TREE_DEPTH,
+    CONNECTOR_TREE_OPERATOR_TAKES_AFTER, DUST_VALUE, FEE_ESTIMATE_CONF_TARGET, K_DEEP,
     MAX_BITVM_CHALLENGE_RESPONSE_BLOCKS, MIN_RELAY_FEE, PERIOD_BLOCK_COUNT,
 };
 use crate::db::operator::OperatorMockDB;
 use crate::env_writer::ENVWriter;
 use crate::errors::{BridgeError, InvalidPeriodError};
-use crate::extended_rpc::ExtendedRpc;
+use crate::extended_rpc::{ExtendedRpc, UtxoSpendStatus};
 
 use crate::merkle::MerkleTree;
 use crate::script_builder::ScriptBuilder;
 use crate::traits::verifier::VerifierConnector;
-use crate::transaction_builder::TransactionBuilder;
+use crate::transaction_builder::{default_claim_outputs, TransactionBuilder};
 use crate::utils::{
-    calculate_amount, check_deposit_utxo, get_claim_reveal_indices, handle_taproot_witness,
+    self, calculate_amount, check_deposit_utxo, get_claim_reveal_indices, handle_taproot_witness,
     handle_taproot_witness_new,
 };
-use crate::{EVMAddress, WithdrawalPayment};
+use crate::verifier::SigningRequestKind;
+use crate::{ConnectorUTXOTree, EVMAddress, HashTree, WithdrawalPayment};
 
 use bitcoin::address::NetworkChecked;
 use bitcoin::block::Header;
 use bitcoin::hashes::Hash;
 
 use bitcoin::{secp256k1, secp256k1::schnorr, Address};
-use bitcoin::{Amount, BlockHash, OutPoint};
+use bitcoin::{Amount, BlockHash, FeeRate, OutPoint, Txid};
 use clementine_circuits::constants::{
     BLOCKHASH_MERKLE_TREE_DEPTH, BRIDGE_AMOUNT_SATS, CLAIM_MERKLE_TREE_DEPTH, MAX_BLOCK_HANDLE_OPS,
     NUM_ROUNDS, WITHDRAWAL_MERKLE_TREE_DEPTH,
 };
 use clementine_circuits::env::Environment;
+use clementine_circuits::incremental_merkle;
 use clementine_circuits::{sha256_hash, HashType, PreimageType};
 use crypto_bigint::{Encoding, U256};
 use secp256k1::rand::{Rng, RngCore};
 use secp256k1::{Message, SecretKey, XOnlyPublicKey};
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 
 pub fn create_connector_tree_preimages_and_hashes(
@@ -49,7 +53,7 @@ pub fn create_connector_tree_preimages_and_hashes(
     for i in 1..(depth + 1) {
         let mut preimages_current_level: Vec<PreimageType> = Vec::new();
         let mut hashes_current_level: Vec<PreimageType> = Vec::new();
-        for _ in 0..2u32.pow(i as u32) {
+        for _ in 0..CONNECTOR_TREE_ARITY.pow(i as u32) {
             let temp: PreimageType = rng.gen();
             preimages_current_level.push(temp);
             hashes_current_level.push(sha256_hash!(temp));
@@ -75,15 +79,216 @@ pub fn create_all_rounds_connector_preimages(
     (preimages, hashes)
 }
 
+/// Hashes of a connector tree level, given the preimages of its nodes.
+pub fn expected_leaf_hashes(preimages: &[PreimageType]) -> Vec<HashType> {
+    preimages
+        .iter()
+        .map(|preimage| sha256_hash!(*preimage))
+        .collect()
+}
+
+/// Reproduces every connector tree hash from the revealed preimages, level by
+/// level, in the layout `set_connector_tree_hashes` expects for one round.
+pub fn expected_connector_tree_hashes(preimages: &[Vec<PreimageType>]) -> HashTree {
+    preimages
+        .iter()
+        .map(|level_preimages| expected_leaf_hashes(level_preimages))
+        .collect()
+}
+
+/// Builds the connector tree of one round from its root UTXO and the
+/// preimages of every node, level by level from the root. The hashes are the
+/// ones verifiers check revealed preimages against, and the UTXOs are the
+/// ones `TransactionBuilder::create_all_connector_trees` derives from them.
+#[derive(Debug, Clone)]
+pub struct ConnectorTreeBuilder {
+    pub root_utxo: OutPoint,
+    pub operator_pk: XOnlyPublicKey,
+    pub preimages: Vec<Vec<PreimageType>>,
+}
+
+impl ConnectorTreeBuilder {
+    pub fn new(
+        root_utxo: OutPoint,
+        operator_pk: XOnlyPublicKey,
+        preimages: Vec<Vec<PreimageType>>,
+    ) -> Self {
+        Self {
+            root_utxo,
+            operator_pk,
+            preimages,
+        }
+    }
+
+    /// Returns the UTXO tree and the hash tree. Level `i` must have
+    /// `CONNECTOR_TREE_ARITY^i` preimages.
+    pub fn build(
+        &self,
+        transaction_builder: &TransactionBuilder,
+    ) -> Result<(ConnectorUTXOTree, HashTree), BridgeError> {
+        if self.preimages.is_empty()
+            || self
+                .preimages
+                .iter()
+                .enumerate()
+                .any(|(level, preimages)| preimages.len() != CONNECTOR_TREE_ARITY.pow(level as u32))
+        {
+            return Err(BridgeError::PreimageNotFound);
+        }
+        let depth = self.preimages.len() - 1;
+        let hashes = expected_connector_tree_hashes(&self.preimages);
+        // The period does not change the tree
+        let utxos = transaction_builder.create_connector_binary_tree(
+            0,
+            &self.operator_pk,
+            &self.root_utxo,
+            depth,
+            hashes.clone(),
+        )?;
+        Ok((utxos, hashes))
+    }
+}
+
+/// Everything that identifies a deposit for the operator and the verifiers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DepositRequest {
+    pub start_utxo: OutPoint,
+    pub return_address: XOnlyPublicKey,
+    pub deposit_index: u32,
+    pub evm_address: EVMAddress,
+}
+
+/// Block heights of the periods, as given to the verifiers with
+/// `connector_roots_created`. Period `i` ends at `start_block_height +
+/// period_relative_block_heights[i]`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeriodSchedule {
+    pub start_block_height: u64,
+    pub period_relative_block_heights: Vec<u32>,
+}
+
+impl PeriodSchedule {
+    /// Index of the period that `height` falls into, if any.
+    pub fn period_of(&self, height: u64) -> Option<u32> {
+        if height < self.start_block_height {
+            return None;
+        }
+        self.period_relative_block_heights
+            .iter()
+            .position(|relative_height| height < self.start_block_height + *relative_height as u64)
+            .map(|period| period as u32)
+    }
+
+    /// Whether the period of `height` still accepts deposits. The last
+    /// `MAX_BLOCK_HANDLE_OPS` blocks of a period are left for withdrawals and
+    /// preimage reveals, like in `get_current_withdrawal_period`.
+    pub fn accepts_deposits(&self, height: u64) -> bool {
+        match self.period_of(height) {
+            Some(period) => {
+                let period_end = self.start_block_height
+                    + self.period_relative_block_heights[period as usize] as u64;
+                height < period_end.saturating_sub(MAX_BLOCK_HANDLE_OPS as u64)
+            }
+            None => false,
+        }
+    }
+
+    /// Period of a deposit confirmed at `deposit_confirmed_height`. Deposits
+    /// outside the schedule or after the cutoff of their period are rejected.
+    pub fn deposit_period(&self, deposit_confirmed_height: u64) -> Result<u32, BridgeError> {
+        let period = self
+            .period_of(deposit_confirmed_height)
+            .ok_or(BridgeError::InvalidPeriod(
+                InvalidPeriodError::PeriodNotFound,
+            ))?;
+        if !self.accepts_deposits(deposit_confirmed_height) {
+            return Err(BridgeError::InvalidPeriod(
+                InvalidPeriodError::DepositPeriodMismatch,
+            ));
+        }
+        Ok(period)
+    }
+}
+
+fn period_deposits_tree(
+    period_deposits: &[HashType],
+) -> Result<MerkleTree<CLAIM_MERKLE_TREE_DEPTH>, BridgeError> {
+    let mut tree = MerkleTree::new();
+    for deposit in period_deposits {
+        tree.add(*deposit)?;
+    }
+    Ok(tree)
+}
+
+/// Checks a proof from `Operator::prove_deposit_in_period` against the
+/// deposit root the operator committed to for the period.
+pub fn verify_deposit_in_period(
+    period_root: HashType,
+    deposit: HashType,
+    deposit_index: u32,
+    proof: &[HashType],
+) -> bool {
+    match <&[HashType; CLAIM_MERKLE_TREE_DEPTH]>::try_from(proof) {
+        Ok(proof) => incremental_merkle::verify_proof(deposit, deposit_index, proof, period_root),
+        Err(_) => false,
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct DepositPresigns {
     pub move_sign: schnorr::Signature,
     pub operator_claim_sign: Vec<schnorr::Signature>,
 }
 
+/// Orders presigns received in any order to match the order of `verifiers`,
+/// which is the order of the keys in the n-of-n script. Returns an error if a
+/// verifier has no presigns.
+pub fn order_presigns(
+    verifiers: &[XOnlyPublicKey],
+    mut presigns: Vec<(XOnlyPublicKey, DepositPresigns)>,
+) -> Result<Vec<DepositPresigns>, BridgeError> {
+    verifiers
+        .iter()
+        .map(|verifier| {
+            let position = presigns
+                .iter()
+                .position(|(pk, _)| pk == verifier)
+                .ok_or_else(|| {
+                    tracing::error!("Missing presigns from verifier {:?}", verifier);
+                    BridgeError::FailedToGetPresigns
+                })?;
+            Ok(presigns.swap_remove(position).1)
+        })
+        .collect()
+}
+
+/// Receipt given to the depositor after the deposit is presigned. It is enough
+/// to later find the move transaction on chain and check that the presigns
+/// used for it are the ones given at deposit time.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DepositReceipt {
+    pub move_txid: Txid,
+    pub deposit_index: u32,
+    pub verifiers_pks: Vec<XOnlyPublicKey>,
+    pub presigns_hash: HashType,
+}
+
 #[derive(Debug, Clone)]
 pub struct OperatorClaimSigs {
     pub operator_claim_sigs: Vec<Vec<schnorr::Signature>>,
+    /// Outputs of the operator claims, the presigns are bound to them
+    pub claim_outputs: Vec<(Amount, Address)>,
+}
+
+/// What the operator does when a connector tree spend can not be broadcast
+/// while it is spending several UTXOs of the tree.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FailureMode {
+    /// Log the error and go on with the rest of the spends
+    #[default]
+    ContinueOnError,
+    /// Stop at the first failed spend, the view of the chain may be stale
+    AbortOnSpendError,
 }
 
 #[derive(Debug)]
@@ -93,6 +298,7 @@ pub struct Operator {
     pub transaction_builder: TransactionBuilder,
     pub verifiers_pks: Vec<XOnlyPublicKey>,
     pub verifier_connector: Vec<Box<dyn VerifierConnector>>,
+    pub spend_failure_mode: FailureMode,
     operator_db_connector: OperatorMockDB,
 }
 
@@ -103,6 +309,7 @@ impl Operator {
         operator_sk: SecretKey,
         verifiers: Vec<Box<dyn VerifierConnector>>,
     ) -> Result<Self, BridgeError> {
+        utils::check_verifiers_pks(&all_xonly_pks)?;
         let num_verifiers = all_xonly_pks.len() - 1;
         let signer = Actor::new(operator_sk); // Operator is the last one
 
@@ -119,10 +326,18 @@ impl Operator {
             transaction_builder,
             verifier_connector: verifiers,
             verifiers_pks: all_xonly_pks.clone(),
+            spend_failure_mode: FailureMode::default(),
             operator_db_connector,
         })
     }
 
+    /// Number of verifier signatures the bridge address needs, see
+    /// `TransactionBuilder::set_threshold`. It must be the threshold of every
+    /// verifier, or the operator moves deposits to another bridge address.
+    pub fn set_threshold(&mut self, threshold: usize) -> Result<(), BridgeError> {
+        self.transaction_builder.set_threshold(threshold)
+    }
+
     /// this is a public endpoint that every depositor can call
     /// it will get signatures from all verifiers.
     /// 1. Check if the deposit utxo is valid and finalized (6 blocks confirmation)
@@ -135,6 +350,22 @@ impl Operator {
         return_address: &XOnlyPublicKey,
         evm_address: &EVMAddress,
     ) -> Result<OutPoint, BridgeError> {
+        let claim_outputs = default_claim_outputs(&self.signer.address)?;
+        self.new_deposit_with_claim_outputs(start_utxo, return_address, evm_address, claim_outputs)
+    }
+
+    /// Same as `new_deposit`, with the operator claims of the deposit paying
+    /// `claim_outputs` instead of the operator address. They must add up to
+    /// the claim amount, and the verifiers presign this exact output set.
+    pub fn new_deposit_with_claim_outputs(
+        &mut self,
+        start_utxo: OutPoint,
+        return_address: &XOnlyPublicKey,
+        evm_address: &EVMAddress,
+        claim_outputs: Vec<(Amount, Address)>,
+    ) -> Result<OutPoint, BridgeError> {
+        // An empty verifier set makes the n-of-n script spendable by anyone
+        utils::check_verifiers_pks(&self.verifiers_pks)?;
         check_deposit_utxo(
             &self.rpc,
             &self.transaction_builder,
@@ -161,7 +392,7 @@ impl Operator {
                         return_address,
                         deposit_index as u32,
                         evm_address,
-                        &self.signer.address,
+                        &claim_outputs,
                     )
                     .map_err(|e| {
                         // Log the error or convert it to BridgeError if necessary
@@ -183,6 +414,35 @@ impl Operator {
             self.transaction_builder
                 .create_move_tx(start_utxo, evm_address, &return_address)?;
 
+        let num_verifiers = self.verifiers_pks.len() - 1;
+        let move_sigs_with_pks = self.verifiers_pks[..num_verifiers]
+            .iter()
+            .zip(presigns_from_all_verifiers.iter())
+            .map(|(pk, presign)| (*pk, presign.move_sign))
+            .collect::<Vec<_>>();
+        let move_sig_hash = self.signer.sighash_taproot_script_spend(&mut move_tx, 0)?;
+        utils::threshold_satisfied(
+            &self.signer.secp,
+            &self.verifiers_pks[..num_verifiers],
+            &move_sigs_with_pks,
+            &Message::from_digest(move_sig_hash.to_byte_array()),
+        )?;
+        // Nothing is broadcast before every presign is known to be valid
+        for (verifier_pk, presigns) in self.verifiers_pks[..num_verifiers]
+            .iter()
+            .zip(presigns_from_all_verifiers.iter())
+        {
+            self.verify_deposit_presigns(
+                verifier_pk,
+                presigns,
+                start_utxo,
+                return_address,
+                evm_address,
+                deposit_index as u32,
+                &claim_outputs,
+            )?;
+        }
+
         // TODO: Simplify this move_signatures thing, maybe with a macro
         let mut move_signatures = presigns_from_all_verifiers
             .iter()
@@ -202,7 +462,9 @@ impl Operator {
 
         handle_taproot_witness_new(&mut move_tx, &witness_elements, 0)?;
         // tracing::debug!("move_tx: {:?}", move_tx);
+        self.check_fee_against_estimate(&move_tx.tx, &move_tx.prevouts);
         let rpc_move_txid = self.rpc.send_raw_transaction(&move_tx.tx)?;
+        tracing::info!("Operator broadcast move tx: {:?}", rpc_move_txid);
         let move_utxo = OutPoint {
             txid: rpc_move_txid,
             vout: 0,
@@ -212,61 +474,255 @@ impl Operator {
                 .iter()
                 .map(|presign| presign.operator_claim_sign.clone())
                 .collect::<Vec<_>>(),
+            claim_outputs: claim_outputs.clone(),
         };
         self.operator_db_connector
             .add_deposit_take_sigs(operator_claim_sigs);
 
-        for i in 0..NUM_ROUNDS {
-            let connector_utxo = self.operator_db_connector.get_connector_tree_utxo(i)?
+        Ok(move_utxo)
+    }
+
+    /// Checks the presigns of the verifier with `verifier_pk` against the
+    /// move transaction and the operator claims of the deposit, rebuilt the
+    /// same way the verifier built them. Fails with `InvalidPresign` naming
+    /// the first signature that does not verify.
+    #[allow(clippy::too_many_arguments)]
+    pub fn verify_deposit_presigns(
+        &self,
+        verifier_pk: &XOnlyPublicKey,
+        presigns: &DepositPresigns,
+        start_utxo: OutPoint,
+        return_address: &XOnlyPublicKey,
+        evm_address: &EVMAddress,
+        deposit_index: u32,
+        claim_outputs: &[(Amount, Address)],
+    ) -> Result<(), BridgeError> {
+        if presigns.operator_claim_sign.len() != NUM_ROUNDS {
+            tracing::error!(
+                "Verifier {:?} presigned {} operator claims instead of {}",
+                verifier_pk,
+                presigns.operator_claim_sign.len(),
+                NUM_ROUNDS
+            );
+            return Err(BridgeError::PresignCountMismatch);
+        }
+
+        let (move_sig_hash, operator_claim_sig_hashes) = self.deposit_presign_sighashes(
+            start_utxo,
+            return_address,
+            evm_address,
+            deposit_index,
+            claim_outputs,
+        )?;
+        self.verify_presign(
+            verifier_pk,
+            &presigns.move_sign,
+            move_sig_hash,
+            SigningRequestKind::Move,
+        )?;
+        for (period, (sig, sig_hash)) in presigns
+            .operator_claim_sign
+            .iter()
+            .zip(operator_claim_sig_hashes)
+            .enumerate()
+        {
+            self.verify_presign(
+                verifier_pk,
+                sig,
+                sig_hash,
+                SigningRequestKind::OperatorClaim { period },
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Sighashes the verifiers presign for a deposit: the one of the move
+    /// transaction and the ones of the operator claims of every round.
+    fn deposit_presign_sighashes(
+        &self,
+        start_utxo: OutPoint,
+        return_address: &XOnlyPublicKey,
+        evm_address: &EVMAddress,
+        deposit_index: u32,
+        claim_outputs: &[(Amount, Address)],
+    ) -> Result<(bitcoin::TapSighash, Vec<bitcoin::TapSighash>), BridgeError> {
+        let mut move_tx =
+            self.transaction_builder
+                .create_move_tx(start_utxo, evm_address, return_address)?;
+        let move_sig_hash = self.signer.sighash_taproot_script_spend(&mut move_tx, 0)?;
+
+        // The txid does not commit to the witness, so it is known before signing
+        let move_utxo = OutPoint {
+            txid: move_tx.tx.txid(),
+            vout: 0,
+        };
+        let mut operator_claim_sig_hashes = Vec::with_capacity(NUM_ROUNDS);
+        for period in 0..NUM_ROUNDS {
+            let connector_utxo = self.operator_db_connector.get_connector_tree_utxo(period)?
                 [CONNECTOR_TREE_DEPTH][deposit_index as usize];
             let connector_hash = self.operator_db_connector.get_connector_tree_hash(
-                i,
+                period,
                 CONNECTOR_TREE_DEPTH,
                 deposit_index as usize,
             )?;
-            let mut operator_claim_tx = self.transaction_builder.create_operator_claim_tx(
-                move_utxo,
-                connector_utxo,
-                &self.signer.address,
-                &self.signer.xonly_public_key,
-                &connector_hash,
-            )?;
+            let mut operator_claim_tx = self
+                .transaction_builder
+                .create_operator_claim_tx_with_outputs(
+                    move_utxo,
+                    connector_utxo,
+                    claim_outputs,
+                    &self.signer.xonly_public_key,
+                    &connector_hash,
+                )?;
+            operator_claim_sig_hashes.push(
+                self.signer
+                    .sighash_taproot_script_spend(&mut operator_claim_tx, 0)?,
+            );
+        }
 
-            let sig_hash = self
-                .signer
-                .sighash_taproot_script_spend(&mut operator_claim_tx, 0)?;
+        Ok((move_sig_hash, operator_claim_sig_hashes))
+    }
 
-            let op_claim_sigs_for_period_i = presigns_from_all_verifiers
-                .iter()
-                .map(|presign| {
-                    // tracing::debug!(
-                    //     "presign.operator_claim_sign[{:?}]: {:?}",
-                    //     i, presign.operator_claim_sign[i]
-                    // );
-                    presign.operator_claim_sign[i]
-                })
-                .collect::<Vec<_>>();
-            // tracing::debug!(
-            //     "len of op_claim_sigs_for_period_i: {:?}",
-            //     op_claim_sigs_for_period_i.len()
-            // );
-            for (idx, sig) in op_claim_sigs_for_period_i.iter().enumerate() {
-                // tracing::debug!("verifying presigns for index {:?}: ", idx);
-                // tracing::debug!("sig: {:?}", sig);
-                self.signer.secp.verify_schnorr(
-                    sig,
-                    &Message::from_digest_slice(sig_hash.as_byte_array()).expect("should be hash"),
-                    &self.verifiers_pks[idx],
-                )?;
+    fn verify_presign(
+        &self,
+        verifier_pk: &XOnlyPublicKey,
+        sig: &schnorr::Signature,
+        sig_hash: bitcoin::TapSighash,
+        kind: SigningRequestKind,
+    ) -> Result<(), BridgeError> {
+        self.signer
+            .secp
+            .verify_schnorr(
+                sig,
+                &Message::from_digest(sig_hash.to_byte_array()),
+                verifier_pk,
+            )
+            .map_err(|_| {
+                tracing::error!(
+                    "Invalid presign of verifier {:?} for {:?}",
+                    verifier_pk,
+                    kind
+                );
+                BridgeError::InvalidPresign(kind)
+            })
+    }
+
+    /// Indices of the `deposits` whose presigns may no longer hold after a
+    /// reorg of the blocks from `reorg_from_height` on. A deposit is flagged
+    /// when its UTXO is no longer confirmed, was confirmed again inside the
+    /// reorged range (its period may have changed), or was spent by anything
+    /// other than its move transaction. Only the flagged deposits need to be
+    /// presigned again.
+    pub fn presigns_invalidated_by_reorg(
+        &self,
+        deposits: &[(DepositRequest, DepositPresigns)],
+        reorg_from_height: u64,
+    ) -> Result<Vec<usize>, BridgeError> {
+        let mut invalidated = Vec::new();
+        for (i, (deposit, _)) in deposits.iter().enumerate() {
+            let deposit_height = self.rpc.confirmation_height(&deposit.start_utxo.txid)?;
+            if !deposit_height.is_some_and(|height| height < reorg_from_height) {
+                tracing::warn!(
+                    "Deposit {} is not confirmed below height {} anymore",
+                    deposit.deposit_index,
+                    reorg_from_height
+                );
+                invalidated.push(i);
+                continue;
+            }
+
+            match self.rpc.utxo_spend_status(&deposit.start_utxo)? {
+                UtxoSpendStatus::Unspent => continue,
+                UtxoSpendStatus::NotFound => {
+                    tracing::warn!("Deposit {} UTXO does not exist", deposit.deposit_index);
+                    invalidated.push(i);
+                    continue;
+                }
+                UtxoSpendStatus::SpentConfirmed | UtxoSpendStatus::SpentMempool => {}
+            }
+            let move_tx = self.transaction_builder.create_move_tx(
+                deposit.start_utxo,
+                &deposit.evm_address,
+                &deposit.return_address,
+            )?;
+            let move_txid = move_tx.tx.txid();
+            // The move transaction is known to the node if it is the spend,
+            // whether it is confirmed or still in the mempool
+            match self.rpc.get_raw_transaction(&move_txid, None) {
+                Ok(_) => {}
+                // The move transaction does not exist
+                Err(e) if utils::is_rpc_not_found(&e) => {
+                    tracing::warn!(
+                        "Deposit {} UTXO is spent by a transaction other than the move transaction",
+                        deposit.deposit_index
+                    );
+                    invalidated.push(i);
+                }
+                Err(e) => return Err(e.into()),
             }
         }
+        Ok(invalidated)
+    }
 
-        Ok(move_utxo)
+    /// Commitment to the connector trees of every round, see
+    /// `utils::connector_tree_commitment`. Equals `Verifier::connector_tree_root`
+    /// when the operator and the verifier hold the same trees.
+    pub fn connector_tree_root(&self) -> Result<HashType, BridgeError> {
+        utils::connector_tree_commitment(
+            &self.operator_db_connector.get_all_connector_tree_hashes()?,
+        )
+    }
+
+    /// Root of the Merkle tree of the deposits of a period, in the order they
+    /// were made. This is what the operator commits to for the period.
+    pub fn period_deposits_root(
+        &self,
+        period_deposits: &[HashType],
+    ) -> Result<HashType, BridgeError> {
+        Ok(period_deposits_tree(period_deposits)?.root())
+    }
+
+    /// Inclusion proof of the deposit at `deposit_index` of `period_deposits`
+    /// in `period_deposits_root`, checked with `verify_deposit_in_period`.
+    pub fn prove_deposit_in_period(
+        &self,
+        deposit_index: u32,
+        period_deposits: &[HashType],
+    ) -> Result<Vec<HashType>, BridgeError> {
+        let tree = period_deposits_tree(period_deposits)?;
+        Ok(tree.generate_proof(deposit_index)?.to_vec())
+    }
+
+    /// Period of a deposit confirmed at `deposit_confirmed_height`, see
+    /// `PeriodSchedule::deposit_period`.
+    pub fn deposit_period(
+        &self,
+        deposit_confirmed_height: u64,
+        period_schedule: &PeriodSchedule,
+    ) -> Result<u32, BridgeError> {
+        period_schedule.deposit_period(deposit_confirmed_height)
+    }
+
+    /// Whether a deposit confirmed at `height` would be accepted by the period
+    /// schedule of the operator.
+    pub fn period_accepting_deposits(&self, height: u64) -> Result<bool, BridgeError> {
+        Ok(self.period_schedule()?.accepts_deposits(height))
+    }
+
+    /// Period schedule of the connector trees created in `initial_setup`.
+    pub fn period_schedule(&self) -> Result<PeriodSchedule, BridgeError> {
+        Ok(PeriodSchedule {
+            start_block_height: self.operator_db_connector.get_start_block_height()?,
+            period_relative_block_heights: self
+                .operator_db_connector
+                .get_period_relative_block_heights()?,
+        })
     }
 
     /// Returns the current withdrawal
     fn get_current_withdrawal_period(&self) -> Result<usize, BridgeError> {
-        let cur_block_height = self.rpc.get_block_count().unwrap();
+        let cur_block_height = self.rpc.get_block_count()?;
         let start_block_height = self.operator_db_connector.get_start_block_height()?;
         let period_relative_block_heights = self
             .operator_db_connector
@@ -285,7 +741,7 @@ impl Operator {
     }
 
     fn get_current_preimage_reveal_period(&self) -> Result<usize, BridgeError> {
-        let cur_block_height = self.rpc.get_block_count().unwrap();
+        let cur_block_height = self.rpc.get_block_count()?;
         tracing::debug!("Cur block height: {:?}", cur_block_height);
         let start_block_height = self.operator_db_connector.get_start_block_height()?;
         tracing::debug!("Start block height: {:?}", start_block_height);
@@ -361,21 +817,21 @@ impl Operator {
             &hash,
         )?;
 
-        let base_tx = match self.rpc.get_raw_transaction(&utxo.txid, None) {
-            Ok(txid) => Some(txid),
-            Err(e) => {
+        let utxo_tx = self
+            .rpc
+            .get_raw_transaction(&utxo.txid, None)
+            .map_err(|e| {
                 tracing::error!("Failed to get raw transaction: {}", e);
-                None
-            }
-        };
-        // tracing::debug!("base_tx: {:?}", base_tx);
-
-        if base_tx.is_none() {
-            return Ok(());
-        }
-        let depth = u32::ilog2(
-            ((base_tx.unwrap().output[utxo.vout as usize].value.to_sat() + MIN_RELAY_FEE)
-                / (DUST_VALUE + MIN_RELAY_FEE)) as u32,
+                e
+            })?;
+        let utxo_txout = utxo_tx
+            .output
+            .get(utxo.vout as usize)
+            .ok_or(BridgeError::TxOutputNotFound)?
+            .clone();
+        let depth = u32::ilog(
+            ((utxo_txout.value.to_sat() + MIN_RELAY_FEE) / (DUST_VALUE + MIN_RELAY_FEE)) as u32,
+            CONNECTOR_TREE_ARITY as u32,
         );
         // tracing::debug!("depth: {:?}", depth);
         let level = tree_depth - depth as usize;
@@ -386,77 +842,493 @@ impl Operator {
             .iter()
             .position(|x| *x == preimage)
             .ok_or(BridgeError::PreimageNotFound)?;
-        let hashes = (
-            self.operator_db_connector
-                .get_connector_tree_hash(period, level + 1, 2 * index)?,
-            self.operator_db_connector
-                .get_connector_tree_hash(period, level + 1, 2 * index + 1)?,
-        );
-
-        let utxo_tx = self.rpc.get_raw_transaction(&utxo.txid, None)?;
-        // tracing::debug!("utxo_tx: {:?}", utxo_tx);
-        // tracing::debug!("utxo_txid: {:?}", utxo_tx.txid());
-        let timelock_script =
-            ScriptBuilder::generate_timelock_script(&self.signer.xonly_public_key, 1);
+        let hashes = (0..CONNECTOR_TREE_ARITY)
+            .map(|k| {
+                self.operator_db_connector.get_connector_tree_hash(
+                    period,
+                    level + 1,
+                    CONNECTOR_TREE_ARITY * index + k,
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
 
-        let (first_address, _) = TransactionBuilder::create_connector_tree_node_address(
-            &self.signer.secp,
+        let timelock_script = ScriptBuilder::generate_timelock_script(
             &self.signer.xonly_public_key,
-            &hashes.0,
-        )?;
+            CONNECTOR_TREE_OPERATOR_TAKES_AFTER,
+        );
 
-        let (second_address, _) = TransactionBuilder::create_connector_tree_node_address(
-            &self.signer.secp,
-            &self.signer.xonly_public_key,
-            &hashes.1,
-        )?;
+        let child_addresses = hashes
+            .iter()
+            .map(|hash| {
+                TransactionBuilder::create_connector_tree_node_address(
+                    &self.signer.secp,
+                    &self.signer.xonly_public_key,
+                    hash,
+                )
+                .map(|(address, _)| address)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
 
         let mut tx = TransactionBuilder::create_connector_tree_tx(
             &utxo,
             depth as usize - 1,
-            first_address,
-            second_address,
+            child_addresses,
         );
         // tracing::debug!("created spend tx: {:?}", tx);
 
         let sig = self.signer.sign_taproot_script_spend_tx(
             &mut tx,
-            &vec![utxo_tx.output[utxo.vout as usize].clone()],
+            &vec![utxo_txout.clone()],
             &timelock_script,
             0,
         )?;
-        // let spend_control_block = tree_info
-        //     .control_block(&(timelock_script.clone(), LeafVersion::TapScript))
-        //     .expect("Cannot create control block");
-        // let mut sighash_cache = SighashCache::new(tx.borrow_mut());
-        // let witness = sighash_cache.witness_mut(0).unwrap();
-        // witness.push(sig.as_ref());
-        // witness.push(timelock_script);
-        // witness.push(&spend_control_block.serialize());
 
+        // The operator takes the node through its timelock leaf, so the
+        // witness is [signature, timelock script, control block]. Only
+        // verifiers burning the node use the preimage leaf.
         let mut witness_elements: Vec<&[u8]> = Vec::new();
         witness_elements.push(sig.as_ref());
 
         handle_taproot_witness(&mut tx, 0, &witness_elements, &timelock_script, &tree_info)?;
+        self.check_fee_against_estimate(&tx, &[utxo_txout.clone()]);
 
         // tracing::debug!("bytes_connector_tree_tx length: {:?}", bytes_connector_tree_tx.len());
         // let hex_utxo_tx = hex::encode(bytes_utxo_tx.clone());
-        let _spending_txid = match self.rpc.send_raw_transaction(&tx) {
-            Ok(txid) => Some(txid),
-            Err(e) => {
-                tracing::error!("Failed to send raw transaction: {}", e);
-                None
+        let spending_txid = self.rpc.send_raw_transaction(&tx).map_err(|e| {
+            tracing::error!("Failed to send raw transaction: {}", e);
+            e
+        })?;
+        tracing::info!(
+            "Operator broadcast connector tree spend: {:?}",
+            spending_txid
+        );
+        Ok(())
+    }
+
+    /// Warns when `fee` is below the current estimate for `tx`. The fees of
+    /// the move transaction and the connector tree are fixed when they are
+    /// presigned and committed to, so they can not follow the estimate. A
+    /// stuck move transaction can be bumped through its anyone can spend
+    /// output. Only warns, so a failing estimate never stops a broadcast.
+    fn check_fee_against_estimate(&self, tx: &bitcoin::Transaction, prevouts: &[bitcoin::TxOut]) {
+        let fee_and_estimate = utils::transaction_fee(tx, prevouts).and_then(|fee| {
+            utils::estimate_fee(&self.rpc, tx.vsize(), FEE_ESTIMATE_CONF_TARGET)
+                .map(|estimated_fee| (fee, estimated_fee))
+        });
+        match fee_and_estimate {
+            Ok((fee, estimated_fee)) if fee < estimated_fee => tracing::warn!(
+                "Transaction {:?} pays {} but the fee estimate is {}, it may not confirm in time",
+                tx.txid(),
+                fee,
+                estimated_fee
+            ),
+            Ok(_) => {}
+            Err(e) => tracing::warn!(
+                "Could not check the fee of transaction {:?} against the estimate: {:?}",
+                tx.txid(),
+                e
+            ),
+        }
+    }
+
+    /// Gets a stuck transaction of the operator, like an operator claim, mined
+    /// faster by spending its output at `parent_vout`, which must pay the
+    /// operator address, back to the operator address. The child pays enough
+    /// for the parent and the child together to reach `target_feerate_sat_vb`,
+    /// which must be within `[min_fee_rate, max_fee_rate]`.
+    pub fn bump_fee_cpfp(
+        &self,
+        parent_txid: Txid,
+        parent_vout: u32,
+        parent_amount: Amount,
+        target_feerate_sat_vb: u64,
+        min_fee_rate: FeeRate,
+        max_fee_rate: FeeRate,
+    ) -> Result<Txid, BridgeError> {
+        let parent_utxo = OutPoint {
+            txid: parent_txid,
+            vout: parent_vout,
+        };
+        if self.rpc.is_utxo_spent(&parent_utxo, true)? {
+            tracing::error!("Output {:?} to bump is already spent", parent_utxo);
+            return Err(BridgeError::UTXOSpent);
+        }
+        let target_fee_rate =
+            utils::cpfp_target_fee_rate(target_feerate_sat_vb, min_fee_rate, max_fee_rate)?;
+
+        let parent = self.rpc.get_raw_transaction(&parent_txid, None)?;
+        let parent_prevouts = self.rpc.fetch_prevouts(&parent)?;
+        let child_prevouts = vec![parent
+            .output
+            .get(parent_vout as usize)
+            .filter(|txout| {
+                txout.value == parent_amount
+                    && txout.script_pubkey == self.signer.address.script_pubkey()
+            })
+            .cloned()
+            .ok_or(BridgeError::TxOutputNotFound)?];
+
+        let mut child = TransactionBuilder::create_consolidation_tx(
+            vec![parent_utxo],
+            &child_prevouts,
+            &self.signer.address,
+            FeeRate::ZERO,
+        )?;
+        // The signature is always 64 bytes, so the weight is final once signed
+        let sig = self
+            .signer
+            .sign_taproot_pubkey_spend_tx(&mut child, &child_prevouts, 0)?;
+        child.input[0].witness.push(sig.as_ref());
+        let child_fee =
+            utils::cpfp_child_fee(&parent, &parent_prevouts, child.weight(), target_fee_rate)?;
+        child.output[0].value = parent_amount
+            .checked_sub(child_fee)
+            .filter(|value| *value >= self.signer.address.script_pubkey().dust_value())
+            .ok_or(BridgeError::FeeTooHigh)?;
+
+        child.input[0].witness.clear();
+        let sig = self
+            .signer
+            .sign_taproot_pubkey_spend_tx(&mut child, &child_prevouts, 0)?;
+        child.input[0].witness.push(sig.as_ref());
+
+        let child_txid = self.rpc.send_raw_transaction(&child)?;
+        tracing::info!(
+            "Operator broadcast CPFP child {:?} of {:?}, paying {}",
+            child_txid,
+            parent_txid,
+            child_fee
+        );
+        Ok(child_txid)
+    }
+
+    /// Spends several connector tree UTXOs of `period`, each with its
+    /// preimage. Failed spends are handled by `spend_failure_mode`: with
+    /// `ContinueOnError` they are returned together with their UTXO, with
+    /// `AbortOnSpendError` the first one is returned as the error.
+    pub fn spend_connector_tree_utxos(
+        &self,
+        period: usize,
+        spends: &[(OutPoint, PreimageType)],
+        tree_depth: usize,
+    ) -> Result<Vec<(OutPoint, BridgeError)>, BridgeError> {
+        let mut failed_spends = Vec::new();
+        for (utxo, preimage) in spends {
+            if let Err(e) = self.spend_connector_tree_utxo(period, *utxo, *preimage, tree_depth) {
+                match self.spend_failure_mode {
+                    FailureMode::AbortOnSpendError => return Err(e),
+                    FailureMode::ContinueOnError => failed_spends.push((*utxo, e)),
+                }
             }
+        }
+        Ok(failed_spends)
+    }
+
+    /// Starts the connector tree of `period` by spending its source UTXO into
+    /// the root of the tree. Can only be mined once the period ends, and
+    /// nothing in the tree can be claimed before that.
+    pub fn start_connector_tree(
+        &self,
+        period: usize,
+        source_utxo: OutPoint,
+    ) -> Result<Txid, BridgeError> {
+        let start_block_height = self.operator_db_connector.get_start_block_height()?;
+        let period_relative_block_heights = self
+            .operator_db_connector
+            .get_period_relative_block_heights()?;
+        let root_hash = self
+            .operator_db_connector
+            .get_connector_tree_hash(period, 0, 0)?;
+
+        let source_tx = self.rpc.get_raw_transaction(&source_utxo.txid, None)?;
+        let source_amount = source_tx
+            .output
+            .get(source_utxo.vout as usize)
+            .ok_or(BridgeError::TxOutputNotFound)?
+            .value;
+
+        let mut root_tx = self.transaction_builder.create_connector_tree_root_tx(
+            period,
+            source_utxo,
+            source_amount,
+            start_block_height,
+            &period_relative_block_heights,
+            &root_hash,
+        )?;
+        let sig = self
+            .signer
+            .sign_taproot_script_spend_tx_new(&mut root_tx, 0)?;
+        handle_taproot_witness_new(&mut root_tx, &vec![sig.as_ref()], 0)?;
+
+        let txid = self.rpc.send_raw_transaction(&root_tx.tx)?;
+        tracing::info!("Operator broadcast connector tree root tx: {:?}", txid);
+        Ok(txid)
+    }
+
+    /// Returns the connector tree leaves that are worth claiming at the given
+    /// fee rate. Leaves that would cost more in fees than they hold are skipped.
+    /// Returns an error if the fee rate is out of `[min_fee_rate, max_fee_rate]`.
+    pub fn select_claimable_leaves(
+        &self,
+        leaves: &[(OutPoint, Amount)],
+        fee_rate: FeeRate,
+        min_fee_rate: FeeRate,
+        max_fee_rate: FeeRate,
+    ) -> Result<Vec<OutPoint>, BridgeError> {
+        utils::check_fee_rate_bounds(fee_rate, min_fee_rate, max_fee_rate)?;
+        let claimable = utils::select_claimable_leaves(leaves, fee_rate);
+        tracing::debug!(
+            "{} of {} connector tree leaves are claimable at {:?}",
+            claimable.len(),
+            leaves.len(),
+            fee_rate
+        );
+        Ok(claimable)
+    }
+
+    /// Assembles the fully signed move transaction and the operator claim
+    /// transactions of every round for a deposit, and checks them against the
+    /// node with `testmempoolaccept` without broadcasting anything. The claim
+    /// transactions spend their connector leaves through the preimage path.
+    /// The claims of the rounds conflict with each other, so each of them is
+    /// checked in its own package with the move transaction. Returns the
+    /// acceptance of each transaction, move transaction first; the move
+    /// transaction counts as accepted if every package accepted it.
+    pub fn testmempoolaccept_flow(
+        &self,
+        deposit: &DepositRequest,
+        presigns: &[DepositPresigns],
+    ) -> Result<Vec<bool>, BridgeError> {
+        let mut txs = self.signed_deposit_txs(deposit, presigns)?.into_iter();
+        let move_tx = txs.next().ok_or(BridgeError::TxidNotFound)?;
+        let operator_claim_txs = txs.collect::<Vec<_>>();
+        let packages = if operator_claim_txs.is_empty() {
+            vec![vec![move_tx]]
+        } else {
+            operator_claim_txs
+                .into_iter()
+                .map(|operator_claim_tx| vec![move_tx.clone(), operator_claim_tx])
+                .collect()
         };
-        // tracing::debug!("operator_spending_txid: {:?}", spending_txid);
-        Ok(())
+
+        let mut move_allowed = true;
+        let mut claims_allowed = Vec::new();
+        for package in packages.iter() {
+            let results = self.rpc.test_mempool_accept(package)?;
+            for result in results.iter().filter(|result| !result.allowed) {
+                tracing::debug!(
+                    "Transaction {:?} rejected: {:?}",
+                    result.txid,
+                    result.reject_reason
+                );
+            }
+            let mut results = results.into_iter();
+            move_allowed &= results.next().is_some_and(|result| result.allowed);
+            claims_allowed.extend(results.map(|result| result.allowed));
+        }
+        Ok(std::iter::once(move_allowed)
+            .chain(claims_allowed)
+            .collect())
     }
 
-    fn get_num_withdrawals_for_period(&self, _period: usize) -> u32 {
+    /// Checks the move transaction of a deposit together with each of its
+    /// operator claims as a package with `testmempoolaccept`, so a claim is
+    /// checked against the move output it spends. The claims of the rounds
+    /// spend the same move output, so each of them gets its own package.
+    /// Returns whether every package is accepted.
+    pub fn validate_deposit_package(
+        &self,
+        deposit: &DepositRequest,
+        presigns: &[DepositPresigns],
+    ) -> Result<bool, BridgeError> {
+        let mut txs = self.signed_deposit_txs(deposit, presigns)?.into_iter();
+        let move_tx = txs.next().ok_or(BridgeError::TxidNotFound)?;
+        for operator_claim_tx in txs {
+            let package = [move_tx.clone(), operator_claim_tx];
+            let results = self.rpc.test_mempool_accept(&package)?;
+            if let Some(rejected) = results.iter().find(|result| !result.allowed) {
+                tracing::debug!(
+                    "Package of {:?} rejected at {:?}: {:?}",
+                    package[1].txid(),
+                    rejected.txid,
+                    rejected.reject_reason
+                );
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Fully signed move transaction of a deposit followed by its operator
+    /// claim transaction of every round.
+    fn signed_deposit_txs(
+        &self,
+        deposit: &DepositRequest,
+        presigns: &[DepositPresigns],
+    ) -> Result<Vec<bitcoin::Transaction>, BridgeError> {
+        let mut move_tx = self.transaction_builder.create_move_tx(
+            deposit.start_utxo,
+            &deposit.evm_address,
+            &deposit.return_address,
+        )?;
+        let mut move_signatures = presigns
+            .iter()
+            .map(|presign| presign.move_sign)
+            .collect::<Vec<_>>();
+        move_signatures.push(
+            self.signer
+                .sign_taproot_script_spend_tx_new(&mut move_tx, 0)?,
+        );
+        move_signatures.reverse();
+        let witness_elements: Vec<&[u8]> = move_signatures.iter().map(|sig| sig.as_ref()).collect();
+        handle_taproot_witness_new(&mut move_tx, &witness_elements, 0)?;
+
+        let move_utxo = OutPoint {
+            txid: move_tx.tx.txid(),
+            vout: 0,
+        };
+        let mut txs = vec![move_tx.tx];
+
+        let deposit_index = deposit.deposit_index as usize;
+        let claim_outputs = match self
+            .operator_db_connector
+            .get_deposit_claim_outputs(deposit_index)
+        {
+            Some(claim_outputs) => claim_outputs,
+            None => default_claim_outputs(&self.signer.address)?,
+        };
+        for i in 0..NUM_ROUNDS {
+            let connector_utxo = *self
+                .operator_db_connector
+                .get_connector_tree_utxo(i)?
+                .get(CONNECTOR_TREE_DEPTH)
+                .and_then(|leaves| leaves.get(deposit_index))
+                .ok_or(BridgeError::InvalidDepositIndex)?;
+            let connector_hash = self.operator_db_connector.get_connector_tree_hash(
+                i,
+                CONNECTOR_TREE_DEPTH,
+                deposit_index,
+            )?;
+            let mut operator_claim_tx = self
+                .transaction_builder
+                .create_operator_claim_tx_with_outputs(
+                    move_utxo,
+                    connector_utxo,
+                    &claim_outputs,
+                    &self.signer.xonly_public_key,
+                    &connector_hash,
+                )?;
+
+            let mut claim_signatures = presigns
+                .iter()
+                .map(|presign| {
+                    presign
+                        .operator_claim_sign
+                        .get(i)
+                        .copied()
+                        .ok_or(BridgeError::FailedToGetPresigns)
+                })
+                .collect::<Result<Vec<_>, BridgeError>>()?;
+            claim_signatures.push(
+                self.signer
+                    .sign_taproot_script_spend_tx_new(&mut operator_claim_tx, 0)?,
+            );
+            claim_signatures.reverse();
+            let witness_elements: Vec<&[u8]> =
+                claim_signatures.iter().map(|sig| sig.as_ref()).collect();
+            handle_taproot_witness_new(&mut operator_claim_tx, &witness_elements, 0)?;
+
+            let preimage = self.operator_db_connector.get_connector_tree_preimages(
+                i,
+                CONNECTOR_TREE_DEPTH,
+                deposit_index,
+            );
+            let preimage_script = ScriptBuilder::generate_hash_script(connector_hash);
+            let control_block = utils::create_control_block(
+                operator_claim_tx.taproot_spend_infos[1].clone(),
+                &preimage_script,
+            )?;
+            let connector_witness = &mut operator_claim_tx.tx.input[1].witness;
+            connector_witness.push(preimage);
+            connector_witness.push(preimage_script);
+            connector_witness.push(control_block.serialize());
+
+            txs.push(operator_claim_tx.tx);
+        }
+
+        Ok(txs)
+    }
+
+    /// Returns the indices of the presigns whose deposit UTXO is spent by
+    /// something other than its move (kickoff) transaction, i.e. refunded, or
+    /// does not exist. Those presigns can never be used and can be purged.
+    pub fn find_orphaned_presigns(
+        &self,
+        presigns: &[(DepositRequest, DepositPresigns)],
+    ) -> Result<Vec<usize>, BridgeError> {
+        let mut orphaned = Vec::new();
+        for (i, (deposit, _)) in presigns.iter().enumerate() {
+            match self.rpc.utxo_spend_status(&deposit.start_utxo)? {
+                UtxoSpendStatus::Unspent => continue,
+                UtxoSpendStatus::NotFound => {
+                    orphaned.push(i);
+                    continue;
+                }
+                UtxoSpendStatus::SpentConfirmed | UtxoSpendStatus::SpentMempool => {}
+            }
+            let move_tx = self.transaction_builder.create_move_tx(
+                deposit.start_utxo,
+                &deposit.evm_address,
+                &deposit.return_address,
+            )?;
+            match self.rpc.get_raw_transaction_info(&move_tx.tx.txid(), None) {
+                Ok(_) => {}
+                // The move transaction does not exist
+                Err(e) if utils::is_rpc_not_found(&e) => orphaned.push(i),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(orphaned)
+    }
+
+    /// Creates the receipt of a presigned deposit. `presigns` must be in the
+    /// same order as the verifiers, as returned by them in `new_deposit`.
+    pub fn deposit_receipt(
+        &self,
+        deposit: &DepositRequest,
+        presigns: &[DepositPresigns],
+    ) -> Result<DepositReceipt, BridgeError> {
+        let move_tx = self.transaction_builder.create_move_tx(
+            deposit.start_utxo,
+            &deposit.evm_address,
+            &deposit.return_address,
+        )?;
+        let verifiers_pks = self
+            .verifiers_pks
+            .get(..presigns.len())
+            .ok_or(BridgeError::PublicKeyNotFound)?
+            .to_vec();
+
+        let mut hasher = Sha256::new();
+        for presign in presigns.iter() {
+            hasher.update(presign.move_sign.as_ref());
+            for sig in presign.operator_claim_sign.iter() {
+                hasher.update(sig.as_ref());
+            }
+        }
+
+        Ok(DepositReceipt {
+            move_txid: move_tx.tx.txid(),
+            deposit_index: deposit.deposit_index,
+            verifiers_pks,
+            presigns_hash: hasher.finalize().into(),
+        })
+    }
+
+    fn get_num_withdrawals_for_period(&self, _period: usize) -> Result<u32, BridgeError> {
+        // TODO: This is not correct, we should have a cutoff
         self.operator_db_connector
             .get_withdrawals_merkle_tree_index()
-            .unwrap() // TODO: This is not correct, we should have a cutoff
-                      // TODO2: This should not unwrap
     }
 
     /// This is called internally when every withdrawal for the current period is satisfied
@@ -478,10 +1350,10 @@ impl Operator {
                 InvalidPeriodError::InscriptionPeriodMismatch,
             ));
         }
-        let number_of_funds_claim = self.get_num_withdrawals_for_period(period);
+        let number_of_funds_claim = self.get_num_withdrawals_for_period(period)?;
         tracing::debug!("number_of_funds_claim: {:?}", number_of_funds_claim);
 
-        let indices = get_claim_reveal_indices(CONNECTOR_TREE_DEPTH, number_of_funds_claim);
+        let indices = get_claim_reveal_indices(CONNECTOR_TREE_DEPTH, number_of_funds_claim)?;
         tracing::debug!("indices for preimages: {:?}", indices);
 
         let preimages_to_be_revealed = indices
@@ -517,6 +1389,10 @@ impl Operator {
         handle_taproot_witness_new(&mut reveal_tx, &vec![sig.as_ref()], 0)?;
 
         let reveal_txid = self.rpc.send_raw_transaction(&reveal_tx.tx)?;
+        tracing::info!(
+            "Operator broadcast inscription reveal tx: {:?}",
+            reveal_txid
+        );
 
         self.operator_db_connector
             .add_to_inscription_txs((commit_utxo, reveal_txid))?;
@@ -553,7 +1429,7 @@ impl Operator {
         let lc_cutoff_blockhash = block_headers_vec
             [block_headers_vec.len() - 1 - MAX_BLOCK_HANDLE_OPS as usize]
             .block_hash();
-        ENVWriter::<E>::write_blocks_and_add_to_merkle_tree(block_headers_vec, blockhashes_mt);
+        ENVWriter::<E>::write_blocks_and_add_to_merkle_tree(block_headers_vec, blockhashes_mt)?;
         Ok(lc_cutoff_blockhash)
     }
 
@@ -601,7 +1477,7 @@ impl Operator {
                 blockhash.to_byte_array()
             );
 
-            withdrawal_mt.add(hash);
+            withdrawal_mt.add(hash)?;
         }
         // tracing::debug!("WROTE WITHDRAWALS AND ADDED TO MERKLE TREE");
         // tracing::debug!("withdrawal_mt.root(): {:?}", withdrawal_mt.root());
@@ -727,13 +1603,13 @@ impl Operator {
 
         // write all the remaining blocks so that we will have more pow than the given challenge
         // adding more block hashes to the tree is not a problem.
-        let cur_block_height = self.rpc.get_block_count().unwrap();
+        let cur_block_height = self.rpc.get_block_count()?;
 
         let mut k_deep_blocks: Vec<Header> = Vec::new();
 
         for i in end_height..cur_block_height {
-            let blockhash = self.rpc.get_block_hash(i).unwrap();
-            let block_header = self.rpc.get_block_header(&blockhash).unwrap();
+            let blockhash = self.rpc.get_block_hash(i)?;
+            let block_header = self.rpc.get_block_header(&blockhash)?;
             k_deep_blocks.push(block_header);
         }
 
@@ -949,3 +1825,259 @@ impl Operator {
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        create_all_rounds_connector_preimages, create_connector_tree_preimages_and_hashes,
+        expected_connector_tree_hashes, order_presigns, period_deposits_tree,
+        verify_deposit_in_period, ConnectorTreeBuilder, DepositPresigns, Operator, PeriodSchedule,
+    };
+    use crate::actor::Actor;
+    use crate::constants::{CONNECTOR_TREE_DEPTH, PERIOD_BLOCK_COUNT};
+    use crate::db::operator::OperatorMockDB;
+    use crate::errors::{BridgeError, InvalidPeriodError};
+    use crate::extended_rpc::ExtendedRpc;
+    use crate::traits::state_store::MockStateStore;
+    use crate::transaction_builder::{default_claim_outputs, TransactionBuilder};
+    use crate::utils::operator_address;
+    use crate::verifier::SigningRequestKind;
+    use bitcoin::{hashes::Hash, Network, OutPoint, Txid};
+    use clementine_circuits::constants::{CLAIM_MERKLE_TREE_DEPTH, NUM_ROUNDS};
+    use clementine_circuits::sha256_hash;
+    use crypto_bigint::rand_core::OsRng;
+    use secp256k1::{schnorr, Secp256k1, XOnlyPublicKey};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_deposit_period() {
+        let schedule = PeriodSchedule {
+            start_block_height: 100,
+            period_relative_block_heights: vec![50, 100],
+        };
+        assert!(matches!(
+            schedule.deposit_period(99),
+            Err(BridgeError::InvalidPeriod(
+                InvalidPeriodError::PeriodNotFound
+            ))
+        ));
+        assert_eq!(schedule.deposit_period(100).unwrap(), 0);
+        assert_eq!(schedule.deposit_period(146).unwrap(), 0);
+        assert!(matches!(
+            schedule.deposit_period(147),
+            Err(BridgeError::InvalidPeriod(
+                InvalidPeriodError::DepositPeriodMismatch
+            ))
+        ));
+        assert_eq!(schedule.deposit_period(150).unwrap(), 1);
+        assert!(!schedule.accepts_deposits(199));
+        assert!(schedule.period_of(200).is_none());
+    }
+
+    #[test]
+    fn test_order_presigns() {
+        let secp = Secp256k1::new();
+        let verifiers: Vec<XOnlyPublicKey> = (0..3)
+            .map(|_| secp.generate_keypair(&mut OsRng).1.x_only_public_key().0)
+            .collect();
+        let presigns = |i: u8| DepositPresigns {
+            move_sign: schnorr::Signature::from_slice(&[i; 64]).unwrap(),
+            operator_claim_sign: vec![],
+        };
+
+        let ordered = order_presigns(
+            &verifiers,
+            vec![
+                (verifiers[2], presigns(2)),
+                (verifiers[0], presigns(0)),
+                (verifiers[1], presigns(1)),
+            ],
+        )
+        .unwrap();
+        for (i, presign) in ordered.iter().enumerate() {
+            assert_eq!(presign.move_sign, presigns(i as u8).move_sign);
+        }
+
+        assert!(matches!(
+            order_presigns(
+                &verifiers,
+                vec![(verifiers[2], presigns(2)), (verifiers[0], presigns(0))]
+            ),
+            Err(BridgeError::FailedToGetPresigns)
+        ));
+    }
+
+    #[test]
+    fn test_expected_connector_tree_hashes() {
+        let (preimages, hashes) = create_connector_tree_preimages_and_hashes(3, &mut OsRng);
+        assert_eq!(expected_connector_tree_hashes(&preimages), hashes);
+    }
+
+    #[test]
+    fn test_verify_deposit_in_period() {
+        let period_deposits: Vec<[u8; 32]> = (0..5u8).map(|i| [i; 32]).collect();
+        let tree = period_deposits_tree(&period_deposits).unwrap();
+        let proof = tree.path(3).to_vec();
+        assert!(verify_deposit_in_period(
+            tree.root(),
+            period_deposits[3],
+            3,
+            &proof
+        ));
+        assert!(!verify_deposit_in_period(
+            tree.root(),
+            period_deposits[2],
+            3,
+            &proof
+        ));
+        assert!(!verify_deposit_in_period(
+            tree.root(),
+            period_deposits[3],
+            3,
+            &proof[1..]
+        ));
+
+        let too_many_deposits: Vec<[u8; 32]> = (0..(1u32 << CLAIM_MERKLE_TREE_DEPTH) + 1)
+            .map(|i| [i as u8; 32])
+            .collect();
+        assert!(matches!(
+            period_deposits_tree(&too_many_deposits),
+            Err(BridgeError::MerkleTreeFull)
+        ));
+    }
+
+    #[test]
+    fn test_connector_tree_builder() {
+        let operator = Actor::new_with_rng(&mut OsRng);
+        let transaction_builder = TransactionBuilder::new(vec![operator.xonly_public_key]);
+        let root_utxo = OutPoint::new(Txid::all_zeros(), 0);
+        let (preimages, _) =
+            create_connector_tree_preimages_and_hashes(CONNECTOR_TREE_DEPTH, &mut OsRng);
+
+        let builder =
+            ConnectorTreeBuilder::new(root_utxo, operator.xonly_public_key, preimages.clone());
+        let (utxo_tree, hash_tree) = builder.build(&transaction_builder).unwrap();
+        assert_eq!(utxo_tree.len(), CONNECTOR_TREE_DEPTH + 1);
+        assert_eq!(hash_tree.len(), CONNECTOR_TREE_DEPTH + 1);
+        assert_eq!(utxo_tree[0], vec![root_utxo]);
+        assert_eq!(
+            utxo_tree[CONNECTOR_TREE_DEPTH].len(),
+            1 << CONNECTOR_TREE_DEPTH
+        );
+
+        // Every revealed preimage opens the hash lock of its node, as the
+        // verifier checks it when the connector is spent
+        for (level, level_preimages) in preimages.iter().enumerate() {
+            assert_eq!(hash_tree[level].len(), 1 << level);
+            assert_eq!(utxo_tree[level].len(), 1 << level);
+            for (index, preimage) in level_preimages.iter().enumerate() {
+                assert_eq!(sha256_hash!(*preimage), hash_tree[level][index]);
+            }
+        }
+
+        // A level with a missing preimage can not be built
+        let mut missing_preimage = preimages;
+        missing_preimage[CONNECTOR_TREE_DEPTH].pop();
+        assert!(matches!(
+            ConnectorTreeBuilder::new(root_utxo, operator.xonly_public_key, missing_preimage)
+                .build(&transaction_builder),
+            Err(BridgeError::PreimageNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_verify_deposit_presigns() {
+        let verifier = Actor::new_with_rng(&mut OsRng);
+        let operator_signer = Actor::new_with_rng(&mut OsRng);
+        let pks = vec![
+            verifier.xonly_public_key,
+            Actor::new_with_rng(&mut OsRng).xonly_public_key,
+            operator_signer.xonly_public_key,
+        ];
+        let mut operator = Operator::new(
+            ExtendedRpc::new(),
+            pks,
+            operator_signer.secret_key,
+            Vec::new(),
+        )
+        .unwrap();
+        operator.operator_db_connector =
+            OperatorMockDB::with_store(Arc::new(MockStateStore::new()));
+        let (_, connector_tree_hashes) =
+            create_all_rounds_connector_preimages(CONNECTOR_TREE_DEPTH, NUM_ROUNDS, &mut OsRng);
+        let period_relative_block_heights = (0..NUM_ROUNDS as u32 + 1)
+            .map(|i| PERIOD_BLOCK_COUNT * (i + 1))
+            .collect();
+        let (_, _, utxo_trees, _) = operator
+            .transaction_builder
+            .create_all_connector_trees(
+                &connector_tree_hashes,
+                &OutPoint::new(Txid::all_zeros(), 0),
+                100,
+                &period_relative_block_heights,
+            )
+            .unwrap();
+        operator
+            .operator_db_connector
+            .set_connector_tree_utxos(utxo_trees)
+            .unwrap();
+        operator
+            .operator_db_connector
+            .set_connector_tree_hashes(connector_tree_hashes)
+            .unwrap();
+
+        let start_utxo = OutPoint::new(Txid::all_zeros(), 1);
+        let return_address = verifier.xonly_public_key;
+        let evm_address = [1u8; 20];
+        let claim_outputs = default_claim_outputs(&operator_address(
+            operator_signer.xonly_public_key,
+            Network::Regtest,
+        ))
+        .unwrap();
+        let (move_sig_hash, operator_claim_sig_hashes) = operator
+            .deposit_presign_sighashes(start_utxo, &return_address, &evm_address, 0, &claim_outputs)
+            .unwrap();
+        let presigns = DepositPresigns {
+            move_sign: verifier.sign(move_sig_hash),
+            operator_claim_sign: operator_claim_sig_hashes
+                .into_iter()
+                .map(|sig_hash| verifier.sign(sig_hash))
+                .collect(),
+        };
+        let verify = |presigns: &DepositPresigns| {
+            operator.verify_deposit_presigns(
+                &verifier.xonly_public_key,
+                presigns,
+                start_utxo,
+                &return_address,
+                &evm_address,
+                0,
+                &claim_outputs,
+            )
+        };
+        verify(&presigns).unwrap();
+
+        // Signatures of one transaction do not verify for another one
+        let mut tampered = presigns.clone();
+        tampered.move_sign = presigns.operator_claim_sign[0];
+        assert!(matches!(
+            verify(&tampered),
+            Err(BridgeError::InvalidPresign(SigningRequestKind::Move))
+        ));
+        let mut tampered = presigns.clone();
+        tampered.operator_claim_sign[1] = presigns.operator_claim_sign[0];
+        assert!(matches!(
+            verify(&tampered),
+            Err(BridgeError::InvalidPresign(
+                SigningRequestKind::OperatorClaim { period: 1 }
+            ))
+        ));
+
+        let mut missing_claim = presigns.clone();
+        missing_claim.operator_claim_sign.pop();
+        assert!(matches!(
+            verify(&missing_claim),
+            Err(BridgeError::PresignCountMismatch)
+        ));
+    }
+}
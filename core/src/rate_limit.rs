@@ -0,0 +1,93 @@
+//! # Rate Limits
+//!
+//! Presigning a deposit costs a verifier one signature per round and several
+//! RPC calls, so the number of presigns an operator can request is limited
+//! with a token bucket.
+
+use std::time::{Duration, Instant};
+
+/// Allows bursts of up to `capacity` requests, then one request per
+/// `refill_interval`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub capacity: u32,
+    pub refill_interval: Duration,
+}
+
+/// Token bucket enforcing a `RateLimit`. Starts full.
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    limit: RateLimit,
+    tokens: u32,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(limit: RateLimit, now: Instant) -> Self {
+        Self {
+            limit,
+            tokens: limit.capacity,
+            last_refill: now,
+        }
+    }
+
+    /// Takes a token if one is left after refilling up to `now`. Returns
+    /// whether the request is allowed.
+    pub fn try_acquire(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        if self.tokens == 0 {
+            return false;
+        }
+        self.tokens -= 1;
+        true
+    }
+
+    fn refill(&mut self, now: Instant) {
+        if self.limit.refill_interval.is_zero() {
+            self.tokens = self.limit.capacity;
+            self.last_refill = now;
+            return;
+        }
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        let refills = elapsed.as_nanos() / self.limit.refill_interval.as_nanos();
+        if refills == 0 {
+            return;
+        }
+        if refills >= self.limit.capacity as u128 {
+            self.tokens = self.limit.capacity;
+            self.last_refill = now;
+            return;
+        }
+        let refills = refills as u32;
+        self.tokens = self.tokens.saturating_add(refills).min(self.limit.capacity);
+        self.last_refill += self.limit.refill_interval * refills;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RateLimit, TokenBucket};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_token_bucket() {
+        let limit = RateLimit {
+            capacity: 2,
+            refill_interval: Duration::from_secs(30),
+        };
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(limit, start);
+
+        assert!(bucket.try_acquire(start));
+        assert!(bucket.try_acquire(start));
+        assert!(!bucket.try_acquire(start + Duration::from_secs(29)));
+        assert!(bucket.try_acquire(start + Duration::from_secs(30)));
+        assert!(!bucket.try_acquire(start + Duration::from_secs(59)));
+
+        // Refills never go above the capacity
+        let later = start + Duration::from_secs(600);
+        assert!(bucket.try_acquire(later));
+        assert!(bucket.try_acquire(later));
+        assert!(!bucket.try_acquire(later));
+    }
+}
@@ -5,7 +5,7 @@ use bitcoin::{
 };
 use secp256k1::XOnlyPublicKey;
 
-use crate::EVMAddress;
+use crate::{timelock::Timelock, EVMAddress};
 
 #[derive(Debug, Clone)]
 pub struct ScriptBuilder {
@@ -49,6 +49,24 @@ impl ScriptBuilder {
         builder.into_script()
     }
 
+    /// Script that needs signatures from at least `threshold` of the
+    /// verifiers. Missing signatures are given as empty witness elements.
+    pub fn generate_script_k_of_n(&self, threshold: usize) -> ScriptBuf {
+        let mut builder = Builder::new();
+        for (i, vpk) in self.verifiers_pks.iter().enumerate() {
+            builder = builder.push_x_only_key(vpk);
+            builder = if i == 0 {
+                builder.push_opcode(OP_CHECKSIG)
+            } else {
+                builder.push_opcode(OP_CHECKSIGADD)
+            };
+        }
+        builder = builder
+            .push_int(threshold as i64)
+            .push_opcode(OP_GREATERTHANOREQUAL);
+        builder.into_script()
+    }
+
     pub fn generate_script_n_of_n_with_user_pk(&self, user_pk: &XOnlyPublicKey) -> ScriptBuf {
         let mut builder = Builder::new();
         for vpk in self.verifiers_pks.clone() {
@@ -97,9 +115,9 @@ impl ScriptBuilder {
     // ATTENTION: If you want to spend a UTXO using timelock script, the condition is that
     // # in the script < # in the sequence of the tx < # of blocks mined after UTXO appears on the chain
 
-    pub fn generate_timelock_script(actor_pk: &XOnlyPublicKey, block_count: u32) -> ScriptBuf {
+    pub fn generate_timelock_script(actor_pk: &XOnlyPublicKey, timelock: Timelock) -> ScriptBuf {
         Builder::new()
-            .push_int(block_count as i64)
+            .push_int(timelock.to_script_operand())
             .push_opcode(OP_CSV)
             .push_opcode(OP_DROP)
             .push_x_only_key(actor_pk)
@@ -0,0 +1,108 @@
+//! # Relative Timelocks
+//!
+//! BIP68 relative timelocks can be expressed either in blocks or in units of
+//! 512 seconds. Both the `OP_CSV` operand in the scripts and the `nSequence`
+//! of the spending input must use the same encoding, so every relative
+//! timelock in the bridge goes through `Timelock`.
+
+use bitcoin::Sequence;
+
+use crate::errors::BridgeError;
+
+/// Granularity of the time based relative timelocks, in seconds.
+pub const TIMELOCK_TIME_GRANULARITY: u32 = 512;
+
+/// A BIP68 relative timelock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timelock {
+    /// Number of blocks that must be mined after the UTXO is confirmed
+    Blocks(u16),
+    /// Number of 512 second intervals that must pass after the UTXO is confirmed
+    Time(u16),
+}
+
+impl Timelock {
+    /// Creates a time based timelock from seconds, rounding up to the next
+    /// 512 second interval. Returns an error if it does not fit in 16 bits.
+    pub fn from_seconds_ceil(seconds: u32) -> Result<Self, BridgeError> {
+        let intervals = seconds.div_ceil(TIMELOCK_TIME_GRANULARITY);
+        match u16::try_from(intervals) {
+            Ok(intervals) => Ok(Timelock::Time(intervals)),
+            Err(_) => Err(BridgeError::InvalidTimelock),
+        }
+    }
+
+    /// Decodes the relative timelock of an input's `nSequence`. Returns an
+    /// error if the disable flag is set.
+    pub fn from_sequence(sequence: Sequence) -> Result<Self, BridgeError> {
+        if !sequence.is_relative_lock_time() {
+            return Err(BridgeError::InvalidTimelock);
+        }
+        let value = (sequence.to_consensus_u32() & 0xFFFF) as u16;
+        if sequence.is_time_locked() {
+            Ok(Timelock::Time(value))
+        } else {
+            Ok(Timelock::Blocks(value))
+        }
+    }
+
+    /// `nSequence` value that satisfies this timelock.
+    pub fn to_sequence(&self) -> Sequence {
+        match self {
+            Timelock::Blocks(blocks) => Sequence::from_height(*blocks),
+            Timelock::Time(intervals) => Sequence::from_512_second_intervals(*intervals),
+        }
+    }
+
+    /// Operand for `OP_CSV`. It uses the same bit layout as `nSequence`, so
+    /// the type flag is kept for time based timelocks.
+    pub fn to_script_operand(&self) -> i64 {
+        self.to_sequence().to_consensus_u32() as i64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Timelock;
+    use bitcoin::Sequence;
+
+    #[test]
+    fn test_sequence_encoding() {
+        assert_eq!(Timelock::Blocks(200).to_sequence(), Sequence(200));
+        assert_eq!(Timelock::Time(3).to_sequence(), Sequence((1 << 22) | 3));
+        assert_eq!(Timelock::Blocks(1).to_script_operand(), 1);
+        assert_eq!(Timelock::Time(3).to_script_operand(), (1 << 22) | 3);
+    }
+
+    #[test]
+    fn test_sequence_round_trip() {
+        for timelock in [
+            Timelock::Blocks(0),
+            Timelock::Blocks(u16::MAX),
+            Timelock::Time(1),
+            Timelock::Time(u16::MAX),
+        ] {
+            assert_eq!(
+                Timelock::from_sequence(timelock.to_sequence()).unwrap(),
+                timelock
+            );
+        }
+        assert!(Timelock::from_sequence(Sequence::ENABLE_RBF_NO_LOCKTIME).is_err());
+        assert!(Timelock::from_sequence(Sequence::MAX).is_err());
+    }
+
+    #[test]
+    fn test_from_seconds_ceil() {
+        assert_eq!(Timelock::from_seconds_ceil(0).unwrap(), Timelock::Time(0));
+        assert_eq!(Timelock::from_seconds_ceil(1).unwrap(), Timelock::Time(1));
+        assert_eq!(
+            Timelock::from_seconds_ceil(1024).unwrap(),
+            Timelock::Time(2)
+        );
+        assert_eq!(
+            Timelock::from_seconds_ceil(u16::MAX as u32 * 512).unwrap(),
+            Timelock::Time(u16::MAX)
+        );
+        assert!(Timelock::from_seconds_ceil(u16::MAX as u32 * 512 + 1).is_err());
+    }
+}
@@ -0,0 +1,304 @@
+use bitcoin::{Block, BlockHash, FeeRate, OutPoint, ScriptBuf, Transaction, TxOut, Txid};
+use bitcoincore_rpc::RpcApi;
+
+use crate::{errors::BridgeError, extended_rpc::ExtendedRpc};
+
+/// Chain operations the bridge needs from a Bitcoin backend. Keeps the bridge
+/// logic independent of whether a full node or an indexer is behind it.
+pub trait ChainBackend: std::fmt::Debug {
+    fn get_best_block_hash(&self) -> Result<BlockHash, BridgeError>;
+
+    /// Height of the tip of the active chain.
+    fn get_block_count(&self) -> Result<u64, BridgeError>;
+
+    fn get_block(&self, block_hash: &BlockHash) -> Result<Block, BridgeError>;
+
+    /// Hash of the block at `height` in the active chain.
+    fn get_block_hash(&self, height: u64) -> Result<BlockHash, BridgeError>;
+
+    /// Returns the output if it is unspent, `None` otherwise.
+    fn get_tx_out(&self, outpoint: &OutPoint) -> Result<Option<TxOut>, BridgeError>;
+
+    fn send_raw_transaction(&self, tx: &Transaction) -> Result<Txid, BridgeError>;
+
+    /// Fee rate needed to confirm in `conf_target` blocks, if the backend has
+    /// enough data to estimate it.
+    fn estimate_fee_rate(&self, conf_target: u16) -> Result<Option<FeeRate>, BridgeError>;
+}
+
+impl ChainBackend for ExtendedRpc {
+    fn get_best_block_hash(&self) -> Result<BlockHash, BridgeError> {
+        Ok(self.inner.get_best_block_hash()?)
+    }
+
+    fn get_block_count(&self) -> Result<u64, BridgeError> {
+        Ok(self.inner.get_block_count()?)
+    }
+
+    fn get_block(&self, block_hash: &BlockHash) -> Result<Block, BridgeError> {
+        Ok(self.inner.get_block(block_hash)?)
+    }
+
+    fn get_block_hash(&self, height: u64) -> Result<BlockHash, BridgeError> {
+        Ok(self.inner.get_block_hash(height)?)
+    }
+
+    fn get_tx_out(&self, outpoint: &OutPoint) -> Result<Option<TxOut>, BridgeError> {
+        let tx_out = self
+            .inner
+            .get_tx_out(&outpoint.txid, outpoint.vout, Some(false))?;
+        Ok(tx_out.map(|tx_out| TxOut {
+            value: tx_out.value,
+            script_pubkey: ScriptBuf::from_bytes(tx_out.script_pub_key.hex),
+        }))
+    }
+
+    fn send_raw_transaction(&self, tx: &Transaction) -> Result<Txid, BridgeError> {
+        ExtendedRpc::send_raw_transaction(self, tx)
+    }
+
+    fn estimate_fee_rate(&self, conf_target: u16) -> Result<Option<FeeRate>, BridgeError> {
+        let estimate = self.inner.estimate_smart_fee(conf_target, None)?;
+        // Core returns the fee rate per 1000 vbytes, which is 4000 weight units
+        Ok(estimate
+            .fee_rate
+            .map(|fee_rate| FeeRate::from_sat_per_kwu(fee_rate.to_sat() / 4)))
+    }
+}
+
+/// In-memory `ChainBackend` for tests. Blocks are added by the test with
+/// `add_block`, and broadcast transactions wait in the mempool until a block
+/// includes them. Only confirmed outputs are returned by `get_tx_out`. Clones
+/// share the chain, so a test can keep changing it after handing a clone to
+/// the code under test.
+#[cfg(test)]
+#[derive(Debug, Default, Clone)]
+pub struct MockChainBackend {
+    chain: std::sync::Arc<std::sync::Mutex<MockChain>>,
+}
+
+#[cfg(test)]
+#[derive(Debug, Default)]
+struct MockChain {
+    blocks: Vec<Block>,
+    utxos: std::collections::HashMap<OutPoint, TxOut>,
+    mempool: Vec<Transaction>,
+    fee_rate: Option<FeeRate>,
+}
+
+#[cfg(test)]
+impl MockChainBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mines a block with the given transactions on top of the tip. Their
+    /// inputs are spent and their outputs become UTXOs, even if the inputs
+    /// were never created, so tests can start from arbitrary outpoints.
+    pub fn add_block(&self, txdata: Vec<Transaction>) -> BlockHash {
+        use bitcoin::{block, hashes::Hash, CompactTarget, TxMerkleNode};
+
+        let mut chain = self.chain.lock().unwrap();
+        let prev_blockhash = chain
+            .blocks
+            .last()
+            .map(|block| block.block_hash())
+            .unwrap_or_else(BlockHash::all_zeros);
+        let mut block = Block {
+            header: block::Header {
+                version: block::Version::ONE,
+                prev_blockhash,
+                merkle_root: TxMerkleNode::all_zeros(),
+                time: chain.blocks.len() as u32,
+                bits: CompactTarget::from_consensus(0x207fffff),
+                nonce: 0,
+            },
+            txdata,
+        };
+        if let Some(merkle_root) = block.compute_merkle_root() {
+            block.header.merkle_root = merkle_root;
+        }
+
+        for tx in block.txdata.iter() {
+            for input in tx.input.iter() {
+                chain.utxos.remove(&input.previous_output);
+            }
+            let txid = tx.txid();
+            for (vout, output) in tx.output.iter().enumerate() {
+                chain.utxos.insert(
+                    OutPoint {
+                        txid,
+                        vout: vout as u32,
+                    },
+                    output.clone(),
+                );
+            }
+        }
+        let mined: Vec<Txid> = block.txdata.iter().map(|tx| tx.txid()).collect();
+        chain.mempool.retain(|tx| !mined.contains(&tx.txid()));
+
+        let block_hash = block.block_hash();
+        chain.blocks.push(block);
+        block_hash
+    }
+
+    /// Mines a block with every transaction in the mempool.
+    pub fn mine_mempool(&self) -> BlockHash {
+        let txdata = self.chain.lock().unwrap().mempool.clone();
+        self.add_block(txdata)
+    }
+
+    pub fn set_fee_rate(&self, fee_rate: Option<FeeRate>) {
+        self.chain.lock().unwrap().fee_rate = fee_rate;
+    }
+
+    /// Drops the blocks from `height` on, so the blocks added next fork the
+    /// chain there. The UTXOs of the dropped blocks are not rolled back.
+    pub fn truncate(&self, height: u64) {
+        self.chain.lock().unwrap().blocks.truncate(height as usize);
+    }
+}
+
+#[cfg(test)]
+impl ChainBackend for MockChainBackend {
+    fn get_best_block_hash(&self) -> Result<BlockHash, BridgeError> {
+        self.chain
+            .lock()
+            .unwrap()
+            .blocks
+            .last()
+            .map(|block| block.block_hash())
+            .ok_or(BridgeError::BlockhashNotFound)
+    }
+
+    /// Height of the last block added, the first one is at height 0.
+    fn get_block_count(&self) -> Result<u64, BridgeError> {
+        (self.chain.lock().unwrap().blocks.len() as u64)
+            .checked_sub(1)
+            .ok_or(BridgeError::BlockNotFound)
+    }
+
+    fn get_block(&self, block_hash: &BlockHash) -> Result<Block, BridgeError> {
+        self.chain
+            .lock()
+            .unwrap()
+            .blocks
+            .iter()
+            .find(|block| block.block_hash() == *block_hash)
+            .cloned()
+            .ok_or(BridgeError::BlockNotFound)
+    }
+
+    /// The first block added is at height 0.
+    fn get_block_hash(&self, height: u64) -> Result<BlockHash, BridgeError> {
+        self.chain
+            .lock()
+            .unwrap()
+            .blocks
+            .get(height as usize)
+            .map(|block| block.block_hash())
+            .ok_or(BridgeError::BlockhashNotFound)
+    }
+
+    fn get_tx_out(&self, outpoint: &OutPoint) -> Result<Option<TxOut>, BridgeError> {
+        Ok(self.chain.lock().unwrap().utxos.get(outpoint).cloned())
+    }
+
+    /// Accepts the transaction into the mempool if all of its inputs are
+    /// confirmed and unspent by the mempool.
+    fn send_raw_transaction(&self, tx: &Transaction) -> Result<Txid, BridgeError> {
+        let mut chain = self.chain.lock().unwrap();
+        for input in tx.input.iter() {
+            let spent_in_mempool = chain.mempool.iter().any(|mempool_tx| {
+                mempool_tx
+                    .input
+                    .iter()
+                    .any(|mempool_input| mempool_input.previous_output == input.previous_output)
+            });
+            if !chain.utxos.contains_key(&input.previous_output) || spent_in_mempool {
+                return Err(BridgeError::UTXOSpent);
+            }
+        }
+        chain.mempool.push(tx.clone());
+        Ok(tx.txid())
+    }
+
+    fn estimate_fee_rate(&self, _conf_target: u16) -> Result<Option<FeeRate>, BridgeError> {
+        Ok(self.chain.lock().unwrap().fee_rate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChainBackend, MockChainBackend};
+    use crate::errors::BridgeError;
+    use bitcoin::{
+        absolute, hashes::Hash, transaction::Version, Amount, OutPoint, ScriptBuf, Sequence,
+        Transaction, TxIn, TxOut, Txid, Witness,
+    };
+
+    fn spend(previous_output: OutPoint, value: u64) -> Transaction {
+        Transaction {
+            version: Version(2),
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(value),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_mock_chain_backend() {
+        let backend = MockChainBackend::new();
+        assert!(backend.get_best_block_hash().is_err());
+
+        let funding_tx = spend(OutPoint::new(Txid::all_zeros(), 0), 10_000);
+        let funding_utxo = OutPoint::new(funding_tx.txid(), 0);
+        let block_hash = backend.add_block(vec![funding_tx.clone()]);
+        assert_eq!(backend.get_best_block_hash().unwrap(), block_hash);
+        assert_eq!(backend.get_block_count().unwrap(), 0);
+        assert_eq!(
+            backend.get_block(&block_hash).unwrap().txdata,
+            vec![funding_tx]
+        );
+        assert_eq!(
+            backend.get_tx_out(&funding_utxo).unwrap().unwrap().value,
+            Amount::from_sat(10_000)
+        );
+
+        let spending_tx = spend(funding_utxo, 9_000);
+        assert_eq!(
+            backend.send_raw_transaction(&spending_tx).unwrap(),
+            spending_tx.txid()
+        );
+        // Double spends and unknown outputs are rejected
+        assert!(matches!(
+            backend.send_raw_transaction(&spend(funding_utxo, 8_000)),
+            Err(BridgeError::UTXOSpent)
+        ));
+        assert!(matches!(
+            backend.send_raw_transaction(&spend(OutPoint::new(Txid::all_zeros(), 1), 1_000)),
+            Err(BridgeError::UTXOSpent)
+        ));
+
+        // Mempool spends are not visible until mined
+        assert!(backend.get_tx_out(&funding_utxo).unwrap().is_some());
+        let block_hash = backend.mine_mempool();
+        assert_eq!(
+            backend.get_block(&block_hash).unwrap().txdata,
+            vec![spending_tx.clone()]
+        );
+        assert!(backend.get_tx_out(&funding_utxo).unwrap().is_none());
+        assert!(backend
+            .get_tx_out(&OutPoint::new(spending_tx.txid(), 0))
+            .unwrap()
+            .is_some());
+    }
+}
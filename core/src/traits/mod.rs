@@ -1 +1,3 @@
+pub mod chain_backend;
+pub mod state_store;
 pub mod verifier;
@@ -0,0 +1,48 @@
+use crate::errors::BridgeError;
+
+/// Key-value storage for everything the bridge persists. Keeps the bridge
+/// independent of whether the state lives in files, a database or an
+/// encrypted store.
+pub trait StateStore: std::fmt::Debug + Send + Sync {
+    /// Returns the value saved under `key`. A missing key is a
+    /// `BridgeError::DatabaseError` of kind `NotFound`.
+    fn load(&self, key: &str) -> Result<Vec<u8>, BridgeError>;
+
+    /// Saves `value` under `key`, replacing any previous value.
+    fn save(&self, key: &str, value: &[u8]) -> Result<(), BridgeError>;
+}
+
+/// In memory `StateStore` for tests, so they do not share the database files
+/// of the working directory.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct MockStateStore {
+    values: std::sync::Mutex<std::collections::HashMap<String, Vec<u8>>>,
+}
+
+#[cfg(test)]
+impl MockStateStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[cfg(test)]
+impl StateStore for MockStateStore {
+    fn load(&self, key: &str) -> Result<Vec<u8>, BridgeError> {
+        self.values
+            .lock()
+            .unwrap()
+            .get(key)
+            .cloned()
+            .ok_or_else(|| BridgeError::DatabaseError(std::io::ErrorKind::NotFound.into()))
+    }
+
+    fn save(&self, key: &str, value: &[u8]) -> Result<(), BridgeError> {
+        self.values
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), value.to_vec());
+        Ok(())
+    }
+}
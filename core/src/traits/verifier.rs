@@ -1,8 +1,8 @@
-use bitcoin::{Address, OutPoint};
+use bitcoin::{Address, Amount, OutPoint};
 use secp256k1::XOnlyPublicKey;
 
 use crate::{
-    constants::VerifierChallenge, errors::BridgeError, operator::DepositPresigns, EVMAddress,
+    constants::VerifierChallenge, errors::BridgeResult, operator::DepositPresigns, EVMAddress,
 };
 
 pub trait VerifierConnector: std::fmt::Debug {
@@ -12,8 +12,8 @@ pub trait VerifierConnector: std::fmt::Debug {
         return_address: &XOnlyPublicKey,
         deposit_index: u32,
         evm_address: &EVMAddress,
-        operator_address: &Address,
-    ) -> Result<DepositPresigns, BridgeError>;
+        claim_outputs: &[(Amount, Address)],
+    ) -> BridgeResult<DepositPresigns>;
 
     fn connector_roots_created(
         &mut self,
@@ -21,7 +21,7 @@ pub trait VerifierConnector: std::fmt::Debug {
         first_source_utxo: &OutPoint,
         start_blockheight: u64,
         period_relative_block_heights: Vec<u32>,
-    ) -> Result<(), BridgeError>;
+    ) -> BridgeResult<()>;
 
-    fn challenge_operator(&self, period: u8) -> Result<VerifierChallenge, BridgeError>;
+    fn challenge_operator(&self, period: u8) -> BridgeResult<VerifierChallenge>;
 }
@@ -2,10 +2,12 @@ use std::str::FromStr;
 
 use crate::{
     constants::{
-        CONNECTOR_TREE_DEPTH, CONNECTOR_TREE_OPERATOR_TAKES_AFTER, DUST_VALUE, K_DEEP,
-        MAX_BITVM_CHALLENGE_RESPONSE_BLOCKS, MIN_RELAY_FEE, NETWORK, USER_TAKES_AFTER,
+        CONNECTOR_TREE_ARITY, CONNECTOR_TREE_DEPTH, CONNECTOR_TREE_OPERATOR_TAKES_AFTER,
+        DUST_VALUE, K_DEEP, MAX_BITVM_CHALLENGE_RESPONSE_BLOCKS, MIN_RELAY_FEE, NETWORK,
+        USER_TAKES_AFTER,
     },
     merkle::MerkleTree,
+    timelock::Timelock,
     utils::get_claim_proof_tree_leaf,
     ConnectorUTXOTree, EVMAddress, HashTree,
 };
@@ -14,7 +16,8 @@ use bitcoin::{
     opcodes::all::{OP_EQUAL, OP_SHA256},
     script::Builder,
     taproot::{TaprootBuilder, TaprootSpendInfo},
-    Address, Amount, OutPoint, ScriptBuf, TxIn, TxOut, Witness,
+    Address, Amount, FeeRate, Network, OutPoint, ScriptBuf, TapNodeHash, TxIn, TxOut, Weight,
+    Witness,
 };
 use clementine_circuits::{
     constants::{BRIDGE_AMOUNT_SATS, CLAIM_MERKLE_TREE_DEPTH, NUM_ROUNDS},
@@ -23,7 +26,11 @@ use clementine_circuits::{
 use secp256k1::{Secp256k1, XOnlyPublicKey};
 use sha2::{Digest, Sha256};
 
-use crate::{errors::BridgeError, script_builder::ScriptBuilder, utils::calculate_amount};
+use crate::{
+    errors::{BridgeError, InvalidPeriodError},
+    script_builder::ScriptBuilder,
+    utils::{calculate_amount, expected_claim_amount, handle_taproot_witness, operator_address},
+};
 use lazy_static::lazy_static;
 
 // This is an unspendable pubkey
@@ -35,6 +42,67 @@ lazy_static! {
     .unwrap();
 }
 
+/// Claim outputs of an operator claim that pays the whole claim amount to
+/// `operator_address`.
+pub fn default_claim_outputs(
+    operator_address: &Address,
+) -> Result<Vec<(Amount, Address)>, BridgeError> {
+    let claim_amount = expected_claim_amount(
+        Amount::from_sat(BRIDGE_AMOUNT_SATS),
+        Amount::from_sat(DUST_VALUE),
+        Amount::from_sat(MIN_RELAY_FEE),
+    )?;
+    Ok(vec![(claim_amount, operator_address.clone())])
+}
+
+/// Derives the bridge addresses for a fixed set of keys, hashes and EVM
+/// address, as `(description, address)` pairs on `NETWORK`. Reviewers compare
+/// them against an independent implementation to catch a wrong internal key,
+/// leaf order or network.
+pub fn self_test_vectors() -> Vec<(String, String)> {
+    // x coordinates of G, 2G and 3G
+    let pks: Vec<XOnlyPublicKey> = [
+        "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        "c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5",
+        "f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9",
+    ]
+    .iter()
+    .map(|pk| XOnlyPublicKey::from_str(pk).unwrap())
+    .collect();
+    let operator_pk = pks[pks.len() - 1];
+    let transaction_builder = TransactionBuilder::new(pks.clone());
+
+    let (bridge_address, _) = transaction_builder.generate_bridge_address().unwrap();
+    let (deposit_address, _) = transaction_builder
+        .generate_deposit_address(&pks[0], &[0x11; 20])
+        .unwrap();
+    let (connector_tree_node_address, _) = TransactionBuilder::create_connector_tree_node_address(
+        &transaction_builder.secp,
+        &operator_pk,
+        &[0x22; 32],
+    )
+    .unwrap();
+
+    vec![
+        (
+            "operator address of 3G".to_string(),
+            operator_address(operator_pk, NETWORK).to_string(),
+        ),
+        (
+            "bridge address of G, 2G, 3G".to_string(),
+            bridge_address.to_string(),
+        ),
+        (
+            "deposit address of user G, evm address 0x11..11".to_string(),
+            deposit_address.to_string(),
+        ),
+        (
+            "connector tree node address of 3G, hash 0x22..22".to_string(),
+            connector_tree_node_address.to_string(),
+        ),
+    ]
+}
+
 // pub type CreateTxOutputs = (bitcoin::Transaction, Vec<TxOut>, Vec<ScriptBuf>);
 pub struct CreateTxOutputs {
     pub tx: bitcoin::Transaction,
@@ -45,21 +113,127 @@ pub struct CreateTxOutputs {
 
 pub type CreateAddressOutputs = (Address, TaprootSpendInfo);
 
+/// Iterator over the leaves of a connector tree, returned by
+/// [`TransactionBuilder::iter_connector_tree_leaves`]. Walks the tree depth
+/// first, so only one path of the tree is kept in memory at a time.
+#[derive(Debug)]
+pub struct ConnectorTreeLeaves<'a> {
+    secp: &'a Secp256k1<secp256k1::All>,
+    xonly_public_key: &'a XOnlyPublicKey,
+    depth: usize,
+    connector_tree_hashes: &'a HashTree,
+    stack: Vec<(usize, usize, OutPoint)>,
+}
+
+impl ConnectorTreeLeaves<'_> {
+    fn child_addresses(&self, level: usize, index: usize) -> Result<Vec<Address>, BridgeError> {
+        (0..CONNECTOR_TREE_ARITY)
+            .map(|k| self.node_address(level + 1, CONNECTOR_TREE_ARITY * index + k))
+            .collect()
+    }
+
+    fn node_address(&self, level: usize, index: usize) -> Result<Address, BridgeError> {
+        let hash = self
+            .connector_tree_hashes
+            .get(level)
+            .and_then(|level_hashes| level_hashes.get(index))
+            .ok_or(BridgeError::MerkleIndexOutOfBounds)?;
+        let (address, _) = TransactionBuilder::create_connector_tree_node_address(
+            self.secp,
+            self.xonly_public_key,
+            hash,
+        )?;
+        Ok(address)
+    }
+}
+
+impl Iterator for ConnectorTreeLeaves<'_> {
+    type Item = Result<(u32, OutPoint), BridgeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((level, index, utxo)) = self.stack.pop() {
+            if level == self.depth {
+                return Some(Ok((index as u32, utxo)));
+            }
+            let addresses = match self.child_addresses(level, index) {
+                Ok(addresses) => addresses,
+                Err(e) => {
+                    self.stack.clear();
+                    return Some(Err(e));
+                }
+            };
+            let txid = TransactionBuilder::create_connector_tree_tx(
+                &utxo,
+                self.depth - level - 1,
+                addresses,
+            )
+            .txid();
+            // Last child first, so that leaves come out in index order
+            for k in (0..CONNECTOR_TREE_ARITY).rev() {
+                self.stack.push((
+                    level + 1,
+                    CONNECTOR_TREE_ARITY * index + k,
+                    OutPoint {
+                        txid,
+                        vout: k as u32,
+                    },
+                ));
+            }
+        }
+        None
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct TransactionBuilder {
     pub secp: Secp256k1<secp256k1::All>,
     pub verifiers_pks: Vec<XOnlyPublicKey>,
     pub script_builder: ScriptBuilder,
+    /// Number of verifier signatures the bridge address needs, all of them
+    /// by default
+    pub threshold: usize,
+    /// Network the addresses are encoded for, `NETWORK` by default
+    pub network: Network,
 }
 
 impl TransactionBuilder {
     pub fn new(verifiers_pks: Vec<XOnlyPublicKey>) -> Self {
         let secp = Secp256k1::new();
         let script_builder = ScriptBuilder::new(verifiers_pks.clone());
+        let threshold = verifiers_pks.len();
         Self {
             secp,
             verifiers_pks,
             script_builder,
+            threshold,
+            network: NETWORK,
+        }
+    }
+
+    /// Encodes the addresses for `network`. The scripts, so the transactions
+    /// and their signatures, do not change.
+    pub fn set_network(&mut self, network: Network) {
+        self.network = network;
+    }
+
+    /// Makes the bridge address spendable with `threshold` of the verifier
+    /// signatures. Every verifier and the operator must use the same
+    /// threshold, or they derive different bridge addresses.
+    pub fn set_threshold(&mut self, threshold: usize) -> Result<(), BridgeError> {
+        if threshold == 0 || threshold > self.verifiers_pks.len() {
+            return Err(BridgeError::InvalidThreshold);
+        }
+        self.threshold = threshold;
+        Ok(())
+    }
+
+    /// Script of the bridge address. With every verifier required, this is
+    /// the n-of-n script, so the bridge address does not change.
+    pub fn bridge_script(&self) -> ScriptBuf {
+        if self.threshold >= self.verifiers_pks.len() {
+            self.script_builder.generate_script_n_of_n()
+        } else {
+            self.script_builder.generate_script_k_of_n(self.threshold)
         }
     }
 
@@ -75,25 +249,100 @@ impl TransactionBuilder {
             .add_leaf(1, deposit_script.clone())?
             .add_leaf(1, script_timelock.clone())?;
         let tree_info = taproot.finalize(&self.secp, *INTERNAL_KEY)?;
-        let address = Address::p2tr(&self.secp, *INTERNAL_KEY, tree_info.merkle_root(), NETWORK);
+        let address = Address::p2tr(
+            &self.secp,
+            *INTERNAL_KEY,
+            tree_info.merkle_root(),
+            self.network,
+        );
         Ok((address, tree_info))
     }
 
-    // This function generates bridge address. N-of-N script can be used to spend the funds.
+    /// Returns the merkle root of the deposit address's script tree, which is
+    /// the tweak applied to the internal key. It commits to exactly the deposit
+    /// script and the user's timelock script.
+    pub fn deposit_taproot_merkle_root(
+        &self,
+        user_pk: &XOnlyPublicKey,
+        user_evm_address: &EVMAddress,
+    ) -> Result<TapNodeHash, BridgeError> {
+        let (_, tree_info) = self.generate_deposit_address(user_pk, user_evm_address)?;
+        tree_info
+            .merkle_root()
+            .ok_or(BridgeError::TaprootScriptError)
+    }
+
+    // This function generates bridge address. The `bridge_script` can be used to spend the funds.
     pub fn generate_bridge_address(&self) -> Result<CreateAddressOutputs, BridgeError> {
-        let script_n_of_n = self.script_builder.generate_script_n_of_n();
-        let taproot = TaprootBuilder::new().add_leaf(0, script_n_of_n.clone())?;
+        let bridge_script = self.bridge_script();
+        let taproot = TaprootBuilder::new().add_leaf(0, bridge_script)?;
         let tree_info = taproot.finalize(&self.secp, *INTERNAL_KEY)?;
-        let address = Address::p2tr(&self.secp, *INTERNAL_KEY, tree_info.merkle_root(), NETWORK);
+        let address = Address::p2tr(
+            &self.secp,
+            *INTERNAL_KEY,
+            tree_info.merkle_root(),
+            self.network,
+        );
         Ok((address, tree_info))
     }
 
+    /// This function creates the refund tx that sends the deposit back to the user through the
+    /// timelock script, after `USER_TAKES_AFTER` blocks.
+    pub fn create_refund_tx(
+        &self,
+        deposit_utxo: OutPoint,
+        amount: Amount,
+        evm_address: &EVMAddress,
+        return_address: &XOnlyPublicKey,
+        destination: &Address,
+    ) -> Result<CreateTxOutputs, BridgeError> {
+        let (deposit_address, deposit_taproot_spend_info) =
+            self.generate_deposit_address(return_address, evm_address)?;
+
+        let tx_ins =
+            TransactionBuilder::create_tx_ins_with_sequence(vec![deposit_utxo], USER_TAKES_AFTER);
+        let refund_txout = TxOut {
+            value: amount
+                .checked_sub(Amount::from_sat(MIN_RELAY_FEE))
+                .ok_or(BridgeError::FeeTooHigh)?,
+            script_pubkey: destination.script_pubkey(),
+        };
+        let refund_tx = TransactionBuilder::create_btc_tx(tx_ins, vec![refund_txout]);
+        let prevouts = vec![TxOut {
+            script_pubkey: deposit_address.script_pubkey(),
+            value: amount,
+        }];
+        let refund_script = vec![ScriptBuilder::generate_timelock_script(
+            return_address,
+            USER_TAKES_AFTER,
+        )];
+        Ok(CreateTxOutputs {
+            tx: refund_tx,
+            prevouts,
+            scripts: refund_script,
+            taproot_spend_infos: vec![deposit_taproot_spend_info],
+        })
+    }
+
     /// This function creates the move tx, it's prevouts for signing and the script to be used for the signature.
     pub fn create_move_tx(
         &self,
         deposit_utxo: OutPoint,
         evm_address: &EVMAddress,
         return_address: &XOnlyPublicKey,
+    ) -> Result<CreateTxOutputs, BridgeError> {
+        self.create_move_tx_with_lock_time(deposit_utxo, evm_address, return_address, None)
+    }
+
+    /// Creates the move tx like `create_move_tx`, with `nLockTime` set to
+    /// `lock_time` if given. Setting it to the current height discourages
+    /// fee sniping reorgs. All verifiers must presign with the same lock time.
+    pub fn create_move_tx_with_lock_time(
+        &self,
+        deposit_utxo: OutPoint,
+        evm_address: &EVMAddress,
+        return_address: &XOnlyPublicKey,
+        lock_time: Option<absolute::Height>,
     ) -> Result<CreateTxOutputs, BridgeError> {
         let anyone_can_spend_txout = ScriptBuilder::anyone_can_spend_txout();
         let evm_address_inscription_txout = ScriptBuilder::op_return_txout(evm_address);
@@ -114,7 +363,7 @@ impl TransactionBuilder {
                 - evm_address_inscription_txout.value,
             script_pubkey: bridge_address.script_pubkey(),
         };
-        let move_tx = TransactionBuilder::create_btc_tx(
+        let mut move_tx = TransactionBuilder::create_btc_tx(
             tx_ins,
             vec![
                 bridge_txout,
@@ -122,6 +371,18 @@ impl TransactionBuilder {
                 anyone_can_spend_txout,
             ],
         );
+        if let Some(height) = lock_time {
+            move_tx.lock_time = absolute::LockTime::Blocks(height);
+            // A final sequence would disable nLockTime, and a relative
+            // timelock on the input would delay the move tx past it
+            let lock_time_enforced = move_tx.input.iter().all(|tx_in| {
+                tx_in.sequence.enables_absolute_lock_time()
+                    && !tx_in.sequence.is_relative_lock_time()
+            });
+            if !lock_time_enforced {
+                return Err(BridgeError::InvalidTimelock);
+            }
+        }
         let prevouts = vec![TxOut {
             script_pubkey: deposit_address.script_pubkey(),
             value: Amount::from_sat(BRIDGE_AMOUNT_SATS),
@@ -143,6 +404,43 @@ impl TransactionBuilder {
         operator_xonly: &XOnlyPublicKey,
         hash: &HashType,
     ) -> Result<CreateTxOutputs, BridgeError> {
+        self.create_operator_claim_tx_with_outputs(
+            bridge_utxo,
+            connector_utxo,
+            &default_claim_outputs(operator_address)?,
+            operator_xonly,
+            hash,
+        )
+    }
+
+    /// Same as `create_operator_claim_tx`, with the claimed amount split
+    /// between `claim_outputs`. They must add up to `expected_claim_amount`
+    /// and none of them can be dust.
+    pub fn create_operator_claim_tx_with_outputs(
+        &self,
+        bridge_utxo: OutPoint,
+        connector_utxo: OutPoint,
+        claim_outputs: &[(Amount, Address)],
+        operator_xonly: &XOnlyPublicKey,
+        hash: &HashType,
+    ) -> Result<CreateTxOutputs, BridgeError> {
+        let claim_amount = expected_claim_amount(
+            Amount::from_sat(BRIDGE_AMOUNT_SATS),
+            Amount::from_sat(DUST_VALUE),
+            Amount::from_sat(MIN_RELAY_FEE),
+        )?;
+        let total = claim_outputs
+            .iter()
+            .try_fold(Amount::ZERO, |total, (amount, _)| {
+                total.checked_add(*amount)
+            });
+        let has_dust = claim_outputs
+            .iter()
+            .any(|(amount, address)| *amount < address.script_pubkey().dust_value());
+        if total != Some(claim_amount) || has_dust {
+            return Err(BridgeError::InvalidClaimOutputs);
+        }
+
         let (connector_tree_leaf_address, connector_leaf_taproot_spend_info) =
             TransactionBuilder::create_connector_tree_node_address(
                 &self.secp,
@@ -152,22 +450,18 @@ impl TransactionBuilder {
         let (bridge_address, bridge_taproot_spend_info) = self.generate_bridge_address()?;
 
         let anyone_can_spend_txout: TxOut = ScriptBuilder::anyone_can_spend_txout();
-        let evm_address_inscription_txout: TxOut =
-            ScriptBuilder::op_return_txout(&EVMAddress::default());
         let tx_ins = TransactionBuilder::create_tx_ins(vec![bridge_utxo, connector_utxo]);
-        let claim_txout = TxOut {
-            value: Amount::from_sat(BRIDGE_AMOUNT_SATS)
-                - Amount::from_sat(MIN_RELAY_FEE * 2)
-                - anyone_can_spend_txout.value * 2
-                - evm_address_inscription_txout.value
-                + Amount::from_sat(DUST_VALUE),
-            script_pubkey: operator_address.script_pubkey(),
-        };
-        let claim_tx =
-            TransactionBuilder::create_btc_tx(tx_ins, vec![claim_txout, anyone_can_spend_txout]);
+        let mut tx_outs = TransactionBuilder::create_tx_outs(
+            claim_outputs
+                .iter()
+                .map(|(amount, address)| (*amount, address.script_pubkey()))
+                .collect(),
+        );
+        tx_outs.push(anyone_can_spend_txout);
+        let claim_tx = TransactionBuilder::create_btc_tx(tx_ins, tx_outs);
         let prevouts =
             self.create_operator_claim_tx_prevouts(&bridge_address, &connector_tree_leaf_address)?;
-        let scripts = vec![self.script_builder.generate_script_n_of_n()];
+        let scripts = vec![self.bridge_script()];
 
         Ok(CreateTxOutputs {
             tx: claim_tx,
@@ -240,45 +534,23 @@ impl TransactionBuilder {
                     CLAIM_MERKLE_TREE_DEPTH,
                     j,
                     &connector_tree_hashes[i],
-                );
+                )?;
                 // tracing::debug!("hash: {:?}", hash);
-                claim_proof_merkle_tree_i.add(hash);
+                claim_proof_merkle_tree_i.add(hash)?;
             }
             claim_proof_merkle_roots.push(claim_proof_merkle_tree_i.root());
             claim_proof_merkle_trees.push(claim_proof_merkle_tree_i);
 
-            let (next_connector_source_address, _) = self.create_connector_tree_source_address(
-                start_block_height
-                    + (peiod_relative_block_heights[i + 1]
-                        + MAX_BITVM_CHALLENGE_RESPONSE_BLOCKS
-                        + K_DEEP) as u64,
+            let curr_root_and_next_source_tx = self.create_connector_tree_root_tx(
+                i,
+                cur_connector_source_utxo,
+                cur_amount,
+                start_block_height,
+                peiod_relative_block_heights,
+                &connector_tree_hashes[i][0][0],
             )?;
-            let (connector_bt_root_address, _) =
-                TransactionBuilder::create_connector_tree_node_address(
-                    &self.secp,
-                    &self.verifiers_pks[self.verifiers_pks.len() - 1],
-                    &connector_tree_hashes[i][0][0],
-                )?;
-            let curr_root_and_next_source_tx_ins =
-                TransactionBuilder::create_tx_ins(vec![cur_connector_source_utxo]);
 
-            let curr_root_and_next_source_tx_outs = TransactionBuilder::create_tx_outs(vec![
-                (
-                    cur_amount - single_tree_amount,
-                    next_connector_source_address.script_pubkey(),
-                ),
-                (
-                    single_tree_amount - Amount::from_sat(MIN_RELAY_FEE),
-                    connector_bt_root_address.script_pubkey(),
-                ),
-            ]);
-
-            let curr_root_and_next_source_tx = TransactionBuilder::create_btc_tx(
-                curr_root_and_next_source_tx_ins,
-                curr_root_and_next_source_tx_outs,
-            );
-
-            let txid = curr_root_and_next_source_tx.txid();
+            let txid = curr_root_and_next_source_tx.tx.txid();
 
             cur_connector_source_utxo = OutPoint { txid, vout: 0 };
 
@@ -304,6 +576,146 @@ impl TransactionBuilder {
         ))
     }
 
+    /// Creates the transaction that starts the connector tree of `period`. It
+    /// spends the source UTXO of the period through its absolute timelock path
+    /// into the root of the tree and the source UTXO of the next period.
+    /// `source_amount` is only used for the prevouts, the output amounts depend
+    /// on the period alone so that the txid can be computed in advance.
+    pub fn create_connector_tree_root_tx(
+        &self,
+        period: usize,
+        source_utxo: OutPoint,
+        source_amount: Amount,
+        start_block_height: u64,
+        period_relative_block_heights: &[u32],
+        root_hash: &HashType,
+    ) -> Result<CreateTxOutputs, BridgeError> {
+        if period >= NUM_ROUNDS {
+            return Err(BridgeError::InvalidPeriod(
+                InvalidPeriodError::PeriodNotFound,
+            ));
+        }
+        let source_block_height = |period: usize| {
+            period_relative_block_heights
+                .get(period)
+                .map(|relative_height| {
+                    start_block_height
+                        + (relative_height + MAX_BITVM_CHALLENGE_RESPONSE_BLOCKS + K_DEEP) as u64
+                })
+                .ok_or(BridgeError::InvalidPeriod(
+                    InvalidPeriodError::PeriodNotFound,
+                ))
+        };
+        let lock_height = source_block_height(period)?;
+        let operator_pk = &self.verifiers_pks[self.verifiers_pks.len() - 1];
+
+        let (source_address, source_tree_info) =
+            self.create_connector_tree_source_address(lock_height)?;
+        let (next_source_address, _) =
+            self.create_connector_tree_source_address(source_block_height(period + 1)?)?;
+        let (root_address, _) = TransactionBuilder::create_connector_tree_node_address(
+            &self.secp,
+            operator_pk,
+            root_hash,
+        )?;
+
+        let single_tree_amount = calculate_amount(
+            CONNECTOR_TREE_DEPTH,
+            Amount::from_sat(DUST_VALUE),
+            Amount::from_sat(MIN_RELAY_FEE),
+        );
+        let remaining_amount =
+            Amount::from_sat(single_tree_amount.to_sat() * (NUM_ROUNDS - period) as u64);
+
+        let tx_ins = TransactionBuilder::create_tx_ins(vec![source_utxo]);
+        let tx_outs = TransactionBuilder::create_tx_outs(vec![
+            (
+                remaining_amount - single_tree_amount,
+                next_source_address.script_pubkey(),
+            ),
+            (
+                single_tree_amount - Amount::from_sat(MIN_RELAY_FEE),
+                root_address.script_pubkey(),
+            ),
+        ]);
+        let mut tx = TransactionBuilder::create_btc_tx(tx_ins, tx_outs);
+        tx.lock_time = absolute::LockTime::from_consensus(lock_height as u32);
+
+        let prevouts = vec![TxOut {
+            script_pubkey: source_address.script_pubkey(),
+            value: source_amount,
+        }];
+        let timelock_script =
+            ScriptBuilder::generate_absolute_timelock_script(operator_pk, lock_height as u32);
+
+        Ok(CreateTxOutputs {
+            tx,
+            prevouts,
+            scripts: vec![timelock_script],
+            taproot_spend_infos: vec![source_tree_info],
+        })
+    }
+
+    /// Creates a transaction that spends key path `utxos`, whose outputs are
+    /// `prevouts`, into a single output to `destination`. The fee covers the
+    /// weight of the transaction once every input has its signature.
+    pub fn create_consolidation_tx(
+        utxos: Vec<OutPoint>,
+        prevouts: &[TxOut],
+        destination: &Address,
+        fee_rate: FeeRate,
+    ) -> Result<bitcoin::Transaction, BridgeError> {
+        if utxos.is_empty() || utxos.len() != prevouts.len() {
+            return Err(BridgeError::TxInputNotFound);
+        }
+        let total_amount: Amount = prevouts.iter().map(|prevout| prevout.value).sum();
+        let mut tx = TransactionBuilder::create_btc_tx(
+            TransactionBuilder::create_tx_ins(utxos),
+            TransactionBuilder::create_tx_outs(vec![(total_amount, destination.script_pubkey())]),
+        );
+
+        // Segwit marker and flag, then an element count and a schnorr
+        // signature for each input
+        let witness_weight = Weight::from_wu(2 + 66 * tx.input.len() as u64);
+        let fee = fee_rate
+            .fee_wu(tx.weight() + witness_weight)
+            .ok_or(BridgeError::FeeTooHigh)?;
+        tx.output[0].value = total_amount
+            .checked_sub(fee)
+            .filter(|value| *value >= destination.script_pubkey().dust_value())
+            .ok_or(BridgeError::FeeTooHigh)?;
+        Ok(tx)
+    }
+
+    /// Creates a transaction that spends the connector tree nodes `utxos` of
+    /// `actor_pk` through their preimage leaves into a single output to
+    /// `destination`. Each input gets its [preimage, hash script, control
+    /// block] witness, so the weight is final. The output holds the whole
+    /// amount, the caller takes the fee out of it.
+    pub fn create_connector_tree_sweep_tx(
+        secp: &Secp256k1<secp256k1::All>,
+        actor_pk: &XOnlyPublicKey,
+        utxos: &[(OutPoint, PreimageType, Amount)],
+        destination: &Address,
+    ) -> Result<bitcoin::Transaction, BridgeError> {
+        if utxos.is_empty() {
+            return Err(BridgeError::TxInputNotFound);
+        }
+        let total_amount: Amount = utxos.iter().map(|(_, _, amount)| *amount).sum();
+        let mut tx = TransactionBuilder::create_btc_tx(
+            TransactionBuilder::create_tx_ins(utxos.iter().map(|(utxo, _, _)| *utxo).collect()),
+            TransactionBuilder::create_tx_outs(vec![(total_amount, destination.script_pubkey())]),
+        );
+        for (i, (_, preimage, _)) in utxos.iter().enumerate() {
+            let hash = sha256_hash!(*preimage);
+            let (_, tree_info) =
+                TransactionBuilder::create_connector_tree_node_address(secp, actor_pk, &hash)?;
+            let preimage_script = ScriptBuilder::generate_hash_script(hash);
+            handle_taproot_witness(&mut tx, i, &vec![preimage], &preimage_script, &tree_info)?;
+        }
+        Ok(tx)
+    }
+
     fn create_btc_tx(tx_ins: Vec<TxIn>, tx_outs: Vec<TxOut>) -> bitcoin::Transaction {
         bitcoin::Transaction {
             version: bitcoin::transaction::Version(2),
@@ -326,14 +738,12 @@ impl TransactionBuilder {
         tx_ins
     }
 
-    fn create_tx_ins_with_sequence(utxos: Vec<OutPoint>) -> Vec<TxIn> {
+    fn create_tx_ins_with_sequence(utxos: Vec<OutPoint>, timelock: Timelock) -> Vec<TxIn> {
         let mut tx_ins = Vec::new();
         for utxo in utxos {
             tx_ins.push(TxIn {
                 previous_output: utxo,
-                sequence: bitcoin::transaction::Sequence::from_height(
-                    CONNECTOR_TREE_OPERATOR_TAKES_AFTER,
-                ),
+                sequence: timelock.to_sequence(),
                 script_sig: ScriptBuf::default(),
                 witness: Witness::new(),
             });
@@ -355,6 +765,7 @@ impl TransactionBuilder {
     fn create_taproot_address(
         secp: &Secp256k1<secp256k1::All>,
         scripts: Vec<ScriptBuf>,
+        network: Network,
     ) -> Result<(Address, TaprootSpendInfo), BridgeError> {
         let n = scripts.len();
         if n == 0 {
@@ -374,7 +785,7 @@ impl TransactionBuilder {
         let internal_key = *INTERNAL_KEY;
         let tree_info = taproot_builder.finalize(secp, internal_key)?;
         Ok((
-            Address::p2tr(secp, internal_key, tree_info.merkle_root(), NETWORK),
+            Address::p2tr(secp, internal_key, tree_info.merkle_root(), network),
             tree_info,
         ))
     }
@@ -392,7 +803,7 @@ impl TransactionBuilder {
         let scripts = vec![timelock_script, script_n_of_n];
 
         let (address, tree_info) =
-            TransactionBuilder::create_taproot_address(&self.secp, scripts).unwrap();
+            TransactionBuilder::create_taproot_address(&self.secp, scripts, self.network).unwrap();
         Ok((address, tree_info))
     }
 
@@ -401,18 +812,18 @@ impl TransactionBuilder {
         actor_pk: &XOnlyPublicKey,
         hash: &HashType,
     ) -> Result<CreateAddressOutputs, BridgeError> {
-        let timelock_script = ScriptBuilder::generate_timelock_script(
-            actor_pk,
-            CONNECTOR_TREE_OPERATOR_TAKES_AFTER as u32,
-        );
+        let timelock_script =
+            ScriptBuilder::generate_timelock_script(actor_pk, CONNECTOR_TREE_OPERATOR_TAKES_AFTER);
         let preimage_script = Builder::new()
             .push_opcode(OP_SHA256)
             .push_slice(hash)
             .push_opcode(OP_EQUAL)
             .into_script();
+        // Connector tree node addresses are only used for their script pubkey
         let (address, tree_info) = TransactionBuilder::create_taproot_address(
             secp,
             vec![timelock_script.clone(), preimage_script],
+            NETWORK,
         )?;
         Ok((address, tree_info))
     }
@@ -427,6 +838,7 @@ impl TransactionBuilder {
         let (address, taproot_info) = TransactionBuilder::create_taproot_address(
             &self.secp,
             vec![inscribe_preimage_script.clone()],
+            self.network,
         )?;
         let mut hasher = Sha256::new();
         for elem in preimages_to_be_revealed {
@@ -461,31 +873,29 @@ impl TransactionBuilder {
         })
     }
 
+    /// Creates the transaction that splits a connector tree node into its
+    /// children, one output of equal value for each of `child_addresses`.
     pub fn create_connector_tree_tx(
         utxo: &OutPoint,
         depth: usize,
-        first_address: Address,
-        second_address: Address,
+        child_addresses: Vec<Address>,
     ) -> bitcoin::Transaction {
-        let tx_ins = TransactionBuilder::create_tx_ins_with_sequence(vec![*utxo]);
-        let tx_outs = TransactionBuilder::create_tx_outs(vec![
-            (
-                calculate_amount(
-                    depth,
-                    Amount::from_sat(DUST_VALUE),
-                    Amount::from_sat(MIN_RELAY_FEE),
-                ),
-                first_address.script_pubkey(),
-            ),
-            (
-                calculate_amount(
-                    depth,
-                    Amount::from_sat(DUST_VALUE),
-                    Amount::from_sat(MIN_RELAY_FEE),
-                ),
-                second_address.script_pubkey(),
-            ),
-        ]);
+        let tx_ins = TransactionBuilder::create_tx_ins_with_sequence(
+            vec![*utxo],
+            CONNECTOR_TREE_OPERATOR_TAKES_AFTER,
+        );
+        // Like the root, every node holds arity^depth * (dust_value + fee) - fee
+        let child_amount = calculate_amount(
+            depth,
+            Amount::from_sat(DUST_VALUE),
+            Amount::from_sat(MIN_RELAY_FEE),
+        ) - Amount::from_sat(MIN_RELAY_FEE);
+        let tx_outs = TransactionBuilder::create_tx_outs(
+            child_addresses
+                .iter()
+                .map(|address| (child_amount, address.script_pubkey()))
+                .collect(),
+        );
         TransactionBuilder::create_btc_tx(tx_ins, tx_outs)
     }
 
@@ -499,7 +909,7 @@ impl TransactionBuilder {
         depth: usize,
         connector_tree_hashes: Vec<Vec<[u8; 32]>>,
     ) -> Result<ConnectorUTXOTree, BridgeError> {
-        // Root UTXO value should be at least 2^depth * (dust_value + fee) - fee
+        // Root UTXO value should be at least arity^depth * (dust_value + fee) - fee
         let _total_amount = calculate_amount(
             depth,
             Amount::from_sat(DUST_VALUE),
@@ -520,29 +930,388 @@ impl TransactionBuilder {
             let utxo_tree_previous_level = utxo_binary_tree.last().unwrap();
 
             for (j, utxo) in utxo_tree_previous_level.iter().enumerate() {
-                let (first_address, _) = TransactionBuilder::create_connector_tree_node_address(
-                    &self.secp,
-                    xonly_public_key,
-                    &connector_tree_hashes[i + 1][2 * j],
-                )?;
-                let (second_address, _) = TransactionBuilder::create_connector_tree_node_address(
-                    &self.secp,
-                    xonly_public_key,
-                    &connector_tree_hashes[i + 1][2 * j + 1],
-                )?;
+                let child_addresses = (0..CONNECTOR_TREE_ARITY)
+                    .map(|k| {
+                        TransactionBuilder::create_connector_tree_node_address(
+                            &self.secp,
+                            xonly_public_key,
+                            &connector_tree_hashes[i + 1][CONNECTOR_TREE_ARITY * j + k],
+                        )
+                        .map(|(address, _)| address)
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
 
                 let tx = TransactionBuilder::create_connector_tree_tx(
                     utxo,
                     depth - i - 1,
-                    first_address.clone(),
-                    second_address.clone(),
+                    child_addresses,
                 );
                 let txid = tx.txid();
-                utxo_tree_current_level.push(OutPoint { txid, vout: 0 });
-                utxo_tree_current_level.push(OutPoint { txid, vout: 1 });
+                for k in 0..CONNECTOR_TREE_ARITY {
+                    utxo_tree_current_level.push(OutPoint {
+                        txid,
+                        vout: k as u32,
+                    });
+                }
             }
             utxo_binary_tree.push(utxo_tree_current_level);
         }
         Ok(utxo_binary_tree)
     }
+
+    /// Lazily derives the `(index, outpoint)` of every leaf of the connector
+    /// tree, in the same order as the last level of
+    /// `create_connector_binary_tree`, without building the whole tree.
+    pub fn iter_connector_tree_leaves<'a>(
+        &'a self,
+        xonly_public_key: &'a XOnlyPublicKey,
+        root_utxo: OutPoint,
+        depth: usize,
+        connector_tree_hashes: &'a HashTree,
+    ) -> ConnectorTreeLeaves<'a> {
+        ConnectorTreeLeaves {
+            secp: &self.secp,
+            xonly_public_key,
+            depth,
+            connector_tree_hashes,
+            stack: vec![(0, 0, root_utxo)],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{default_claim_outputs, self_test_vectors, TransactionBuilder};
+    use crate::actor::Actor;
+    use crate::constants::{
+        DUST_VALUE, K_DEEP, MAX_BITVM_CHALLENGE_RESPONSE_BLOCKS, NETWORK, PERIOD_BLOCK_COUNT,
+    };
+    use crate::errors::BridgeError;
+    use crate::script_builder::ScriptBuilder;
+    use bitcoin::taproot::LeafVersion;
+    use bitcoin::{absolute, hashes::Hash, Address, Amount, FeeRate, OutPoint, TxOut, Txid};
+    use clementine_circuits::constants::{BRIDGE_AMOUNT_SATS, NUM_ROUNDS};
+    use clementine_circuits::sha256_hash;
+    use crypto_bigint::rand_core::OsRng;
+
+    #[test]
+    fn test_iter_connector_tree_leaves() {
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let (_, pk) = secp.generate_keypair(&mut OsRng);
+        let xonly_pk = pk.x_only_public_key().0;
+        let transaction_builder = TransactionBuilder::new(vec![xonly_pk]);
+
+        let depth = 3;
+        let connector_tree_hashes: Vec<Vec<[u8; 32]>> = (0..depth + 1)
+            .map(|level| {
+                (0..1 << level)
+                    .map(|i| [(level * 16 + i) as u8; 32])
+                    .collect()
+            })
+            .collect();
+        let root_utxo = OutPoint::new(Txid::all_zeros(), 0);
+
+        let utxo_tree = transaction_builder
+            .create_connector_binary_tree(
+                0,
+                &xonly_pk,
+                &root_utxo,
+                depth,
+                connector_tree_hashes.clone(),
+            )
+            .unwrap();
+        let leaves = transaction_builder
+            .iter_connector_tree_leaves(&xonly_pk, root_utxo, depth, &connector_tree_hashes)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(leaves.len(), 1 << depth);
+        for (i, (index, utxo)) in leaves.iter().enumerate() {
+            assert_eq!(*index as usize, i);
+            assert_eq!(*utxo, utxo_tree[depth][i]);
+        }
+
+        let mut missing_hashes = connector_tree_hashes.clone();
+        missing_hashes[depth].pop();
+        assert!(transaction_builder
+            .iter_connector_tree_leaves(&xonly_pk, root_utxo, depth, &missing_hashes)
+            .any(|leaf| leaf.is_err()));
+    }
+
+    #[test]
+    fn test_create_consolidation_tx() {
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let (_, pk) = secp.generate_keypair(&mut OsRng);
+        let destination = Address::p2tr(&secp, pk.x_only_public_key().0, None, NETWORK);
+        let utxos: Vec<OutPoint> = (0..3)
+            .map(|vout| OutPoint::new(Txid::all_zeros(), vout))
+            .collect();
+        let prevouts: Vec<TxOut> = (0..3)
+            .map(|_| TxOut {
+                value: Amount::from_sat(DUST_VALUE),
+                script_pubkey: destination.script_pubkey(),
+            })
+            .collect();
+        let fee_rate = FeeRate::from_sat_per_vb_unchecked(2);
+
+        let tx = TransactionBuilder::create_consolidation_tx(
+            utxos.clone(),
+            &prevouts,
+            &destination,
+            fee_rate,
+        )
+        .unwrap();
+        assert_eq!(tx.input.len(), 3);
+        assert_eq!(tx.output.len(), 1);
+
+        let mut signed_tx = tx.clone();
+        for input in signed_tx.input.iter_mut() {
+            input.witness.push([0u8; 64]);
+        }
+        let fee = fee_rate.fee_wu(signed_tx.weight()).unwrap();
+        assert_eq!(tx.output[0].value, Amount::from_sat(3 * DUST_VALUE) - fee);
+
+        assert!(matches!(
+            TransactionBuilder::create_consolidation_tx(
+                utxos,
+                &prevouts,
+                &destination,
+                FeeRate::from_sat_per_vb_unchecked(100)
+            ),
+            Err(BridgeError::FeeTooHigh)
+        ));
+    }
+
+    #[test]
+    fn test_create_connector_tree_sweep_tx() {
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let (_, pk) = secp.generate_keypair(&mut OsRng);
+        let xonly_pk = pk.x_only_public_key().0;
+        let destination = Address::p2tr(&secp, xonly_pk, None, NETWORK);
+        let leaves: Vec<(OutPoint, [u8; 32], Amount)> = (0..3)
+            .map(|vout| {
+                (
+                    OutPoint::new(Txid::all_zeros(), vout),
+                    [vout as u8 + 1; 32],
+                    Amount::from_sat(DUST_VALUE),
+                )
+            })
+            .collect();
+
+        let tx = TransactionBuilder::create_connector_tree_sweep_tx(
+            &secp,
+            &xonly_pk,
+            &leaves,
+            &destination,
+        )
+        .unwrap();
+        assert_eq!(tx.input.len(), 3);
+        assert_eq!(tx.output.len(), 1);
+        assert_eq!(tx.output[0].value, Amount::from_sat(3 * DUST_VALUE));
+        assert_eq!(tx.output[0].script_pubkey, destination.script_pubkey());
+
+        for (input, (utxo, preimage, _)) in tx.input.iter().zip(leaves.iter()) {
+            assert_eq!(input.previous_output, *utxo);
+            let hash = sha256_hash!(*preimage);
+            let preimage_script = ScriptBuilder::generate_hash_script(hash);
+            let (_, tree_info) =
+                TransactionBuilder::create_connector_tree_node_address(&secp, &xonly_pk, &hash)
+                    .unwrap();
+            let control_block = tree_info
+                .control_block(&(preimage_script.clone(), LeafVersion::TapScript))
+                .unwrap();
+            assert_eq!(
+                input.witness.to_vec(),
+                vec![
+                    preimage.to_vec(),
+                    preimage_script.to_bytes(),
+                    control_block.serialize()
+                ]
+            );
+        }
+
+        assert!(matches!(
+            TransactionBuilder::create_connector_tree_sweep_tx(&secp, &xonly_pk, &[], &destination),
+            Err(BridgeError::TxInputNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_create_move_tx_with_lock_time() {
+        let signer = Actor::new_with_rng(&mut OsRng);
+        let tx_builder = TransactionBuilder::new(vec![signer.xonly_public_key]);
+        let deposit_utxo = OutPoint::new(Txid::all_zeros(), 0);
+        let evm_address = [1u8; 20];
+        let height = absolute::Height::from_consensus(850_000).unwrap();
+
+        let mut move_tx = tx_builder
+            .create_move_tx_with_lock_time(
+                deposit_utxo,
+                &evm_address,
+                &signer.xonly_public_key,
+                Some(height),
+            )
+            .unwrap();
+        assert_eq!(move_tx.tx.lock_time, absolute::LockTime::Blocks(height));
+        assert!(move_tx.tx.is_lock_time_enabled());
+        assert!(signer
+            .sign_taproot_script_spend_tx_new(&mut move_tx, 0)
+            .is_ok());
+
+        let move_tx = tx_builder
+            .create_move_tx(deposit_utxo, &evm_address, &signer.xonly_public_key)
+            .unwrap();
+        assert_eq!(move_tx.tx.lock_time, absolute::LockTime::ZERO);
+    }
+
+    #[test]
+    fn test_create_operator_claim_tx_with_outputs() {
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let signer = Actor::new_with_rng(&mut OsRng);
+        let tx_builder = TransactionBuilder::new(vec![signer.xonly_public_key]);
+        let bridge_utxo = OutPoint::new(Txid::all_zeros(), 0);
+        let connector_utxo = OutPoint::new(Txid::all_zeros(), 1);
+        let hash = [3u8; 32];
+        let (_, other_pk) = secp.generate_keypair(&mut OsRng);
+        let other_address = Address::p2tr(&secp, other_pk.x_only_public_key().0, None, NETWORK);
+
+        let (claim_amount, operator_address) = default_claim_outputs(&signer.address)
+            .unwrap()
+            .pop()
+            .unwrap();
+        let split = Amount::from_sat(claim_amount.to_sat() / 2);
+        let claim_outputs = vec![
+            (split, operator_address.clone()),
+            (claim_amount - split, other_address.clone()),
+        ];
+        let claim_tx = tx_builder
+            .create_operator_claim_tx_with_outputs(
+                bridge_utxo,
+                connector_utxo,
+                &claim_outputs,
+                &signer.xonly_public_key,
+                &hash,
+            )
+            .unwrap();
+        // Claim outputs and the anyone can spend output
+        assert_eq!(claim_tx.tx.output.len(), 3);
+        assert_eq!(claim_tx.tx.output[1].value, claim_amount - split);
+        assert_eq!(
+            claim_tx.tx.output[1].script_pubkey,
+            other_address.script_pubkey()
+        );
+
+        let wrong_sum = vec![(split, operator_address.clone()), (split, other_address)];
+        assert!(matches!(
+            tx_builder.create_operator_claim_tx_with_outputs(
+                bridge_utxo,
+                connector_utxo,
+                &wrong_sum,
+                &signer.xonly_public_key,
+                &hash,
+            ),
+            Err(BridgeError::InvalidClaimOutputs)
+        ));
+    }
+
+    #[test]
+    fn test_bridge_address_threshold() {
+        let pks: Vec<_> = (0..3)
+            .map(|_| Actor::new_with_rng(&mut OsRng).xonly_public_key)
+            .collect();
+        let n_of_n_address = TransactionBuilder::new(pks.clone())
+            .generate_bridge_address()
+            .unwrap()
+            .0;
+
+        let mut first = TransactionBuilder::new(pks.clone());
+        let mut second = TransactionBuilder::new(pks.clone());
+        first.set_threshold(2).unwrap();
+        second.set_threshold(2).unwrap();
+        let multisig_address = first.generate_bridge_address().unwrap().0;
+        assert_eq!(
+            multisig_address,
+            second.generate_bridge_address().unwrap().0
+        );
+        assert_ne!(multisig_address, n_of_n_address);
+
+        // Requiring every verifier keeps the n-of-n address
+        second.set_threshold(3).unwrap();
+        assert_eq!(second.generate_bridge_address().unwrap().0, n_of_n_address);
+
+        assert!(matches!(
+            first.set_threshold(0),
+            Err(BridgeError::InvalidThreshold)
+        ));
+        assert!(matches!(
+            first.set_threshold(4),
+            Err(BridgeError::InvalidThreshold)
+        ));
+        assert_eq!(first.threshold, 2);
+    }
+
+    #[test]
+    fn test_self_test_vectors() {
+        let addresses: Vec<String> = self_test_vectors()
+            .into_iter()
+            .map(|(_, address)| address)
+            .collect();
+        assert_eq!(
+            addresses,
+            vec![
+                "bcrt1pgxxyvcmdncdxs06cudd5yvmwwahaesaj6n3eu7st7x4sw9hrchaqg4dc89",
+                "bcrt1p7akcknylaqal47mfyx9lmq87n3zy4e809kjlqrhw04hmp8ax55lqykvwsz",
+                "bcrt1pmv46lpgkzytvn0yc9ujr6fkrrltspnq0c3yu8kg0jpq9s4xa7lvs4edccv",
+                "bcrt1pxt8rf523mxpa856cnl2tm2yx4r9f28lth6u7l540uhu9027005uq9dh6jn",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_create_connector_tree_root_tx() {
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let (_, pk) = secp.generate_keypair(&mut OsRng);
+        let xonly_pk = pk.x_only_public_key().0;
+        let transaction_builder = TransactionBuilder::new(vec![xonly_pk]);
+
+        let root_hash = [7u8; 32];
+        let source_utxo = OutPoint::new(Txid::all_zeros(), 0);
+        let period_relative_block_heights: Vec<u32> = (0..NUM_ROUNDS as u32 + 1)
+            .map(|i| PERIOD_BLOCK_COUNT * (i + 1))
+            .collect();
+
+        let root_tx = transaction_builder
+            .create_connector_tree_root_tx(
+                0,
+                source_utxo,
+                Amount::from_sat(BRIDGE_AMOUNT_SATS),
+                100,
+                &period_relative_block_heights,
+                &root_hash,
+            )
+            .unwrap();
+        let (root_address, _) =
+            TransactionBuilder::create_connector_tree_node_address(&secp, &xonly_pk, &root_hash)
+                .unwrap();
+        assert_eq!(root_tx.tx.input[0].previous_output, source_utxo);
+        assert_eq!(
+            root_tx.tx.output[1].script_pubkey,
+            root_address.script_pubkey()
+        );
+        assert_eq!(
+            root_tx.tx.lock_time.to_consensus_u32() as u64,
+            100 + (PERIOD_BLOCK_COUNT + MAX_BITVM_CHALLENGE_RESPONSE_BLOCKS + K_DEEP) as u64
+        );
+
+        assert!(transaction_builder
+            .create_connector_tree_root_tx(
+                NUM_ROUNDS,
+                source_utxo,
+                Amount::from_sat(BRIDGE_AMOUNT_SATS),
+                100,
+                &period_relative_block_heights,
+                &root_hash,
+            )
+            .is_err());
+    }
 }
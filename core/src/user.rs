@@ -1,15 +1,33 @@
 use crate::actor::Actor;
+use crate::constants::{NETWORK, USER_TAKES_AFTER};
 use crate::errors::BridgeError;
 use crate::extended_rpc::ExtendedRpc;
-use crate::transaction_builder::TransactionBuilder;
+use crate::script_builder::ScriptBuilder;
+use crate::timelock::Timelock;
+use crate::transaction_builder::{TransactionBuilder, INTERNAL_KEY};
+use crate::utils::{handle_taproot_witness_new, ownership_proof_digest};
 use crate::EVMAddress;
-use bitcoin::secp256k1::Secp256k1;
+use bitcoin::secp256k1::{schnorr, Keypair, Message, Secp256k1};
+use bitcoin::taproot::LeafVersion;
+use bitcoin::Address;
+use bitcoin::Amount;
 use bitcoin::OutPoint;
 use bitcoin::Transaction;
 use bitcoin::XOnlyPublicKey;
+use bitcoin::{TapLeafHash, TapNodeHash};
 use clementine_circuits::constants::BRIDGE_AMOUNT_SATS;
 use secp256k1::SecretKey;
 
+/// First block height that can include the refund of a deposit confirmed at
+/// `deposit_confirmed_height`, which is when the `USER_TAKES_AFTER` timelock
+/// of the refund script expires.
+pub fn refund_available_height(deposit_confirmed_height: u64) -> u64 {
+    match USER_TAKES_AFTER {
+        Timelock::Blocks(blocks) => deposit_confirmed_height + blocks as u64,
+        Timelock::Time(_) => unreachable!("USER_TAKES_AFTER is in blocks"),
+    }
+}
+
 #[derive(Debug)]
 pub struct User {
     pub rpc: ExtendedRpc,
@@ -46,6 +64,76 @@ impl User {
         Ok((deposit_utxo, self.signer.xonly_public_key, evm_address))
     }
 
+    /// Checks that the deposit address commits to a refund leaf that lets
+    /// `return_address` take the funds back after `timelock`, by re-deriving
+    /// the taproot merkle root from the deposit and refund leaves. Should be
+    /// called before sending funds to an address given by someone else.
+    pub fn verify_refund_path(
+        &self,
+        deposit_address: &Address,
+        return_address: &XOnlyPublicKey,
+        evm_address: &EVMAddress,
+        timelock: Timelock,
+    ) -> Result<bool, BridgeError> {
+        let deposit_script = self
+            .transaction_builder
+            .script_builder
+            .create_deposit_script(evm_address);
+        let refund_script = ScriptBuilder::generate_timelock_script(return_address, timelock);
+        let merkle_root = TapNodeHash::from_node_hashes(
+            TapLeafHash::from_script(&deposit_script, LeafVersion::TapScript).into(),
+            TapLeafHash::from_script(&refund_script, LeafVersion::TapScript).into(),
+        );
+        let expected_address = Address::p2tr(&self.secp, *INTERNAL_KEY, Some(merkle_root), NETWORK);
+        Ok(expected_address.script_pubkey() == deposit_address.script_pubkey())
+    }
+
+    /// Builds and signs the transaction that takes a deposit back to
+    /// `destination` through the timelock script. It is only valid once the
+    /// deposit has `USER_TAKES_AFTER` confirmations.
+    pub fn build_refund_tx(
+        &self,
+        deposit_utxo: OutPoint,
+        amount: Amount,
+        evm_address: &EVMAddress,
+        destination: &Address,
+    ) -> Result<Transaction, BridgeError> {
+        let mut refund_tx = self.transaction_builder.create_refund_tx(
+            deposit_utxo,
+            amount,
+            evm_address,
+            &self.signer.xonly_public_key,
+            destination,
+        )?;
+        let sig = self
+            .signer
+            .sign_taproot_script_spend_tx_new(&mut refund_tx, 0)?;
+        handle_taproot_witness_new(&mut refund_tx, &vec![sig.as_ref()], 0)?;
+        Ok(refund_tx.tx)
+    }
+
+    /// Checks whether a refund of the deposit would be accepted into the next
+    /// block. An unconfirmed deposit can not be refunded yet.
+    pub fn refund_available_now(&self, deposit_utxo: &OutPoint) -> Result<bool, BridgeError> {
+        let confirmations = match self.rpc.confirmation_blocks(&deposit_utxo.txid) {
+            Ok(0) | Err(BridgeError::NoConfirmationData) => return Ok(false),
+            Ok(confirmations) => confirmations as u64,
+            Err(e) => return Err(e),
+        };
+        let current_height = self.rpc.get_block_count()?;
+        let deposit_confirmed_height = current_height + 1 - confirmations;
+        Ok(current_height + 1 >= refund_available_height(deposit_confirmed_height))
+    }
+
+    /// Signs `message` with the key of the user's return address. The operator
+    /// checks it with `verify_address_ownership` before accepting the deposit.
+    pub fn prove_address_ownership(&self, message: &[u8]) -> schnorr::Signature {
+        self.secp.sign_schnorr(
+            &Message::from_digest(ownership_proof_digest(message)),
+            &Keypair::from_secret_key(&self.secp, &self.signer.secret_key),
+        )
+    }
+
     pub fn generate_deposit_proof(&self, _move_txid: Transaction) -> Result<(), BridgeError> {
         // let out = self.rpc.get_spent_tx_out(&deposit_utxo)?;
         // self.rpc.get_spent_tx_out(outpoint)
@@ -53,3 +141,133 @@ impl User {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{refund_available_height, User};
+    use crate::utils::verify_address_ownership;
+    use crate::{constants::USER_TAKES_AFTER, extended_rpc::ExtendedRpc, timelock::Timelock};
+    use bitcoin::{hashes::Hash, relative, Amount, OutPoint, Sequence, Txid};
+    use clementine_circuits::constants::BRIDGE_AMOUNT_SATS;
+    use crypto_bigint::rand_core::OsRng;
+
+    #[test]
+    fn test_build_refund_tx() {
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let (_, verifier_pk) = secp.generate_keypair(&mut OsRng);
+        let (user_sk, _) = secp.generate_keypair(&mut OsRng);
+        let user = User::new(
+            ExtendedRpc::new(),
+            vec![verifier_pk.x_only_public_key().0],
+            user_sk,
+        );
+        let evm_address = [1u8; 20];
+        let deposit_utxo = OutPoint::new(Txid::all_zeros(), 0);
+
+        let refund_tx = user
+            .build_refund_tx(
+                deposit_utxo,
+                Amount::from_sat(BRIDGE_AMOUNT_SATS),
+                &evm_address,
+                &user.signer.address,
+            )
+            .unwrap();
+        assert_eq!(refund_tx.input[0].previous_output, deposit_utxo);
+        assert_eq!(refund_tx.input[0].sequence, USER_TAKES_AFTER.to_sequence());
+        // BIP68 relative height: type flag and disable bit unset, blocks in the low 16 bits
+        let Timelock::Blocks(blocks) = USER_TAKES_AFTER else {
+            unreachable!()
+        };
+        assert_eq!(refund_tx.input[0].sequence, Sequence(blocks as u32));
+        assert_eq!(
+            refund_tx.input[0].sequence.to_relative_lock_time(),
+            Some(relative::LockTime::from_height(blocks))
+        );
+        // Signature, timelock script and control block
+        assert_eq!(refund_tx.input[0].witness.len(), 3);
+        assert_eq!(
+            refund_tx.output[0].script_pubkey,
+            user.signer.address.script_pubkey()
+        );
+    }
+
+    #[test]
+    fn test_refund_available_height() {
+        assert_eq!(refund_available_height(100), 300);
+        assert_eq!(
+            refund_available_height(0),
+            USER_TAKES_AFTER.to_sequence().to_consensus_u32() as u64
+        );
+    }
+
+    #[test]
+    fn test_verify_refund_path() {
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let (_, verifier_pk) = secp.generate_keypair(&mut OsRng);
+        let (user_sk, _) = secp.generate_keypair(&mut OsRng);
+        let user = User::new(
+            ExtendedRpc::new(),
+            vec![verifier_pk.x_only_public_key().0],
+            user_sk,
+        );
+        let evm_address = [1u8; 20];
+        let return_address = user.signer.xonly_public_key;
+        let (deposit_address, _) = user
+            .transaction_builder
+            .generate_deposit_address(&return_address, &evm_address)
+            .unwrap();
+
+        assert!(user
+            .verify_refund_path(
+                &deposit_address,
+                &return_address,
+                &evm_address,
+                USER_TAKES_AFTER
+            )
+            .unwrap());
+        assert!(!user
+            .verify_refund_path(
+                &deposit_address,
+                &return_address,
+                &evm_address,
+                Timelock::Blocks(1)
+            )
+            .unwrap());
+        assert!(!user
+            .verify_refund_path(
+                &deposit_address,
+                &verifier_pk.x_only_public_key().0,
+                &evm_address,
+                USER_TAKES_AFTER
+            )
+            .unwrap());
+    }
+
+    #[test]
+    fn test_address_ownership_proof() {
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let (_, verifier_pk) = secp.generate_keypair(&mut OsRng);
+        let (user_sk, _) = secp.generate_keypair(&mut OsRng);
+        let user = User::new(
+            ExtendedRpc::new(),
+            vec![verifier_pk.x_only_public_key().0],
+            user_sk,
+        );
+        let message = b"deposit to 0x0101010101010101010101010101010101010101";
+
+        let signature = user.prove_address_ownership(message);
+        assert!(
+            verify_address_ownership(user.signer.xonly_public_key, message, &signature).is_ok()
+        );
+        assert!(verify_address_ownership(
+            user.signer.xonly_public_key,
+            b"another message",
+            &signature
+        )
+        .is_err());
+        assert!(
+            verify_address_ownership(verifier_pk.x_only_public_key().0, message, &signature)
+                .is_err()
+        );
+    }
+}
@@ -1,9 +1,14 @@
 use std::borrow::BorrowMut;
+use std::sync::Once;
+use std::time::Duration;
 
+use bitcoin::secp256k1::{schnorr, Message, Secp256k1, Verification};
 use bitcoin::sighash::SighashCache;
-use bitcoin::{self, OutPoint, XOnlyPublicKey};
+use bitcoin::{self, OutPoint, TxOut, Txid, XOnlyPublicKey};
+use bitcoin::{Address, Network};
 
 use bitcoin::consensus::Decodable;
+use bitcoin::VarInt;
 
 use bitcoin::taproot::ControlBlock;
 use bitcoin::taproot::LeafVersion;
@@ -11,6 +16,8 @@ use bitcoin::taproot::LeafVersion;
 use bitcoin::taproot::TaprootSpendInfo;
 
 use bitcoin::Amount;
+use bitcoin::FeeRate;
+use bitcoin::Weight;
 
 use bitcoin::ScriptBuf;
 
@@ -18,11 +25,21 @@ use hex;
 
 use sha2::{Digest, Sha256};
 
-use crate::constants::CONFIRMATION_BLOCK_COUNT;
-use crate::errors::BridgeError;
+use crate::actor::SpendPath;
+use crate::constants::{
+    BLOCK_TIME_SECS, CONFIRMATION_BLOCK_COUNT, CONNECTOR_TREE_ARITY, CONNECTOR_TREE_DEPTH,
+    CONNECTOR_TREE_OPERATOR_TAKES_AFTER, MIN_RELAY_FEE, VERIFIER_REACTION_SECS,
+};
+use crate::errors::{BridgeError, BridgeResult};
 use crate::extended_rpc::ExtendedRpc;
-use crate::transaction_builder::{CreateTxOutputs, TransactionBuilder};
+use crate::script_builder::ScriptBuilder;
+use crate::timelock::Timelock;
+use crate::traits::chain_backend::ChainBackend;
+use crate::transaction_builder::{CreateTxOutputs, TransactionBuilder, INTERNAL_KEY};
 use crate::{EVMAddress, HashTree};
+use clementine_circuits::constants::NUM_ROUNDS;
+use clementine_circuits::incremental_merkle::IncrementalMerkleTree;
+use clementine_circuits::HashType;
 
 pub fn parse_hex_to_btc_tx(
     tx_hex: &str,
@@ -36,10 +53,32 @@ pub fn parse_hex_to_btc_tx(
     }
 }
 
-pub fn create_control_block(tree_info: TaprootSpendInfo, script: &ScriptBuf) -> ControlBlock {
+pub fn create_control_block(
+    tree_info: TaprootSpendInfo,
+    script: &ScriptBuf,
+) -> BridgeResult<ControlBlock> {
     tree_info
         .control_block(&(script.clone(), LeafVersion::TapScript))
-        .expect("Cannot create control block")
+        .ok_or(BridgeError::ControlBlockError)
+}
+
+/// Checks that a control block received from someone else is for
+/// `internal_key`, and that its merkle path from `script` reproduces the tweak
+/// of `output_key`.
+pub fn verify_control_block<C: Verification>(
+    secp: &Secp256k1<C>,
+    internal_key: XOnlyPublicKey,
+    output_key: XOnlyPublicKey,
+    script: &bitcoin::Script,
+    control_block: &ControlBlock,
+) -> BridgeResult<()> {
+    if control_block.internal_key != internal_key
+        || control_block.leaf_version != LeafVersion::TapScript
+        || !control_block.verify_taproot_commitment(secp, output_key, script)
+    {
+        return Err(BridgeError::ControlBlockError);
+    }
+    Ok(())
 }
 
 pub fn check_deposit_utxo(
@@ -49,7 +88,7 @@ pub fn check_deposit_utxo(
     return_address: &XOnlyPublicKey,
     evm_address: &EVMAddress,
     amount_sats: u64,
-) -> Result<(), BridgeError> {
+) -> BridgeResult<()> {
     if rpc.confirmation_blocks(&outpoint.txid)? < CONFIRMATION_BLOCK_COUNT {
         return Err(BridgeError::DepositNotFinalized);
     }
@@ -64,14 +103,75 @@ pub fn check_deposit_utxo(
         return Err(BridgeError::InvalidDepositUTXO);
     }
 
-    if rpc.is_utxo_spent(outpoint)? {
+    if rpc.is_utxo_spent(outpoint, true)? {
         return Err(BridgeError::UTXOSpent);
     }
     Ok(())
 }
 
+/// Smallest connector tree CSV, in blocks, that leaves a verifier
+/// `verifier_reaction_secs` to notice a preimage reveal and broadcast a burn,
+/// then `confirmation_margin` blocks to get it confirmed before the operator
+/// can take the branch.
+pub fn safe_operator_csv(
+    block_time_secs: u64,
+    verifier_reaction_secs: u64,
+    confirmation_margin: u32,
+) -> u16 {
+    let reaction_blocks = verifier_reaction_secs.div_ceil(block_time_secs.max(1));
+    let blocks = reaction_blocks.saturating_add(confirmation_margin as u64);
+    blocks.clamp(1, u16::MAX as u64) as u16
+}
+
+/// Warns if `CONNECTOR_TREE_OPERATOR_TAKES_AFTER` is shorter than the
+/// `safe_operator_csv` of the configured block time, verifier reaction time
+/// and confirmations. Meant to be called at startup, it only checks once per
+/// process.
+pub fn check_connector_tree_csv() {
+    static CHECK: Once = Once::new();
+    CHECK.call_once(|| {
+        let safe_csv = safe_operator_csv(
+            BLOCK_TIME_SECS,
+            VERIFIER_REACTION_SECS,
+            CONFIRMATION_BLOCK_COUNT,
+        );
+        if let Timelock::Blocks(blocks) = CONNECTOR_TREE_OPERATOR_TAKES_AFTER {
+            if blocks < safe_csv {
+                tracing::warn!(
+                    "Connector tree CSV of {} blocks leaves verifiers less than the {} blocks they need to burn a branch",
+                    blocks,
+                    safe_csv
+                );
+            }
+        }
+    });
+}
+
 pub fn calculate_amount(depth: usize, value: Amount, fee: Amount) -> Amount {
-    (value + fee) * (2u64.pow(depth as u32))
+    (value + fee) * (CONNECTOR_TREE_ARITY as u64).pow(depth as u32)
+}
+
+/// Checks that the children of a connector tree node together with the fee
+/// of the spending transaction add up to the value of the node. Equal
+/// siblings alone do not catch value taken out at an internal node.
+pub fn validate_tree_value_invariant(
+    parent_amount: Amount,
+    children: &[Amount],
+    fee: Amount,
+) -> BridgeResult<()> {
+    let total = children
+        .iter()
+        .try_fold(fee, |total, child| total.checked_add(*child));
+    if total != Some(parent_amount) {
+        tracing::error!(
+            "Connector tree node of {} is split into {:?} with fee {}",
+            parent_amount,
+            children,
+            fee
+        );
+        return Err(BridgeError::ConnectorTreeValueMismatch);
+    }
+    Ok(())
 }
 
 pub fn handle_taproot_witness<T: AsRef<[u8]>>(
@@ -80,7 +180,7 @@ pub fn handle_taproot_witness<T: AsRef<[u8]>>(
     witness_elements: &Vec<T>,
     script: &ScriptBuf,
     tree_info: &TaprootSpendInfo,
-) -> Result<(), BridgeError> {
+) -> BridgeResult<()> {
     let mut sighash_cache = SighashCache::new(tx.borrow_mut());
     let witness = sighash_cache
         .witness_mut(index)
@@ -100,7 +200,17 @@ pub fn handle_taproot_witness_new<T: AsRef<[u8]>>(
     tx: &mut CreateTxOutputs,
     witness_elements: &Vec<T>,
     index: usize,
-) -> Result<(), BridgeError> {
+) -> BridgeResult<()> {
+    let script = tx
+        .scripts
+        .get(index)
+        .ok_or(BridgeError::TaprootScriptError)?;
+    let spend_control_block = tx
+        .taproot_spend_infos
+        .get(index)
+        .ok_or(BridgeError::ControlBlockError)?
+        .control_block(&(script.clone(), LeafVersion::TapScript))
+        .ok_or(BridgeError::ControlBlockError)?;
     let mut sighash_cache = SighashCache::new(tx.tx.borrow_mut());
     let witness = sighash_cache
         .witness_mut(index)
@@ -108,55 +218,496 @@ pub fn handle_taproot_witness_new<T: AsRef<[u8]>>(
     for elem in witness_elements {
         witness.push(elem);
     }
-    let spend_control_block = tx.taproot_spend_infos[index]
-        .control_block(&(tx.scripts[index].clone(), LeafVersion::TapScript))
-        .ok_or(BridgeError::ControlBlockError)?;
-    witness.push(tx.scripts[index].clone());
+    witness.push(script.clone());
     witness.push(&spend_control_block.serialize());
     Ok(())
 }
 
-pub fn get_claim_reveal_indices(depth: usize, count: u32) -> Vec<(usize, usize)> {
-    assert!(count <= 2u32.pow(depth as u32));
+/// Fills the witness of the input for the given spend path. A key spend only
+/// carries the witness elements, a script spend also carries the script and
+/// its control block.
+pub fn handle_taproot_spend_witness<T: AsRef<[u8]>>(
+    tx: &mut CreateTxOutputs,
+    witness_elements: &Vec<T>,
+    index: usize,
+    spend_path: SpendPath,
+) -> BridgeResult<()> {
+    match spend_path {
+        SpendPath::ScriptSpend => handle_taproot_witness_new(tx, witness_elements, index),
+        SpendPath::KeySpend => {
+            let mut sighash_cache = SighashCache::new(tx.tx.borrow_mut());
+            let witness = sighash_cache
+                .witness_mut(index)
+                .ok_or(BridgeError::TxInputNotFound)?;
+            for elem in witness_elements {
+                witness.push(elem);
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Weight of an input that spends a connector tree UTXO with its timelock script.
+/// Every connector tree node has two leaves, so the control block has a single
+/// merkle branch.
+pub fn connector_tree_input_weight() -> Weight {
+    let timelock_script =
+        ScriptBuilder::generate_timelock_script(&INTERNAL_KEY, CONNECTOR_TREE_OPERATOR_TAKES_AFTER);
+    // previous output, empty script_sig and sequence
+    let base_size = 36 + 1 + 4;
+    // element count, schnorr signature, script and control block
+    let witness_size = 1 + (1 + 64) + (1 + timelock_script.len()) + (1 + 33 + 32);
+    Weight::from_wu((base_size * 4 + witness_size) as u64)
+}
+
+/// Witness weight of a script spend of the n-of-n script, with one schnorr
+/// signature per verifier followed by the script and the control block.
+/// Grows linearly with the number of verifiers.
+pub fn n_of_n_witness_weight(
+    num_verifiers: usize,
+    script_len: usize,
+    control_block_len: usize,
+) -> usize {
+    let item_size = |len: usize| VarInt(len as u64).size() + len;
+    VarInt((num_verifiers + 2) as u64).size()
+        + num_verifiers * item_size(64)
+        + item_size(script_len)
+        + item_size(control_block_len)
+}
+
+/// Estimated witness bytes of spending an n-of-n output through the script
+/// path, and through the key path with a MuSig2 aggregated signature. The
+/// script is assumed to be the only leaf of the taproot tree.
+pub fn compare_witness_sizes(num_verifiers: usize) -> (usize, usize) {
+    // A pushed key and OP_CHECKSIGVERIFY per verifier, then OP_TRUE
+    let script_len = num_verifiers * (1 + 32 + 1) + 1;
+    let script_path = n_of_n_witness_weight(num_verifiers, script_len, 33);
+    // Element count and the aggregated schnorr signature
+    let key_path = 1 + 1 + 64;
+    (script_path, key_path)
+}
+
+/// Returns the connector tree leaves whose value is bigger than the fee needed
+/// to spend them at the given fee rate.
+pub fn select_claimable_leaves(leaves: &[(OutPoint, Amount)], fee_rate: FeeRate) -> Vec<OutPoint> {
+    let input_weight = connector_tree_input_weight();
+    leaves
+        .iter()
+        .filter(|(_, amount)| match fee_rate.fee_wu(input_weight) {
+            Some(fee) => *amount > fee,
+            None => false,
+        })
+        .map(|(outpoint, _)| *outpoint)
+        .collect()
+}
+
+/// Checks that the connector input of a claim transaction is timelocked for at
+/// least `min_csv`, in the same unit. A shorter timelock would let the operator
+/// claim before the challenge window of the verifiers ends.
+pub fn validate_claim_csv(
+    tx: &bitcoin::Transaction,
+    connector_input_index: usize,
+    min_csv: Timelock,
+) -> BridgeResult<()> {
+    let input = tx
+        .input
+        .get(connector_input_index)
+        .ok_or(BridgeError::TxInputNotFound)?;
+    let valid = match (Timelock::from_sequence(input.sequence)?, min_csv) {
+        (Timelock::Blocks(csv), Timelock::Blocks(min)) => csv >= min,
+        (Timelock::Time(csv), Timelock::Time(min)) => csv >= min,
+        _ => false,
+    };
+    if !valid {
+        return Err(BridgeError::InvalidTimelock);
+    }
+    Ok(())
+}
+
+/// Digest signed to prove control of a key. It is tagged like BIP-322
+/// messages, so the signature can not be a valid transaction signature.
+pub fn ownership_proof_digest(message: &[u8]) -> [u8; 32] {
+    let tag = Sha256::digest(b"BIP0322-signed-message");
+    let mut hasher = Sha256::new();
+    hasher.update(tag);
+    hasher.update(tag);
+    hasher.update(message);
+    hasher.finalize().into()
+}
+
+/// Checks a proof that the depositor controls `return_address`, so a refund
+/// can not be sent to a key nobody controls.
+pub fn verify_address_ownership(
+    return_address: XOnlyPublicKey,
+    message: &[u8],
+    signature: &schnorr::Signature,
+) -> BridgeResult<()> {
+    let secp = Secp256k1::verification_only();
+    secp.verify_schnorr(
+        signature,
+        &Message::from_digest(ownership_proof_digest(message)),
+        &return_address,
+    )?;
+    Ok(())
+}
+
+/// Canonical payout address of the operator, derived from its key in the same
+/// way as the address of an `Actor`.
+pub fn operator_address(operator_pk: XOnlyPublicKey, network: Network) -> Address {
+    Address::p2tr(&Secp256k1::verification_only(), operator_pk, None, network)
+}
+
+/// Checks that the list of public keys is not empty and has no duplicates. A
+/// repeated key would fill two slots of the n-of-n script with one signer.
+pub fn check_verifiers_pks(verifiers_pks: &[XOnlyPublicKey]) -> BridgeResult<()> {
+    if verifiers_pks.is_empty() {
+        return Err(BridgeError::PublicKeyNotFound);
+    }
+    for (i, pk) in verifiers_pks.iter().enumerate() {
+        if verifiers_pks[..i].contains(pk) {
+            return Err(BridgeError::DuplicateVerifier);
+        }
+    }
+    Ok(())
+}
+
+/// Checks that there is a valid signature of `sighash` from every verifier
+/// before the n-of-n witness is assembled. Logs the keys that have none.
+pub fn threshold_satisfied<C: Verification>(
+    secp: &Secp256k1<C>,
+    verifiers: &[XOnlyPublicKey],
+    sigs: &[(XOnlyPublicKey, schnorr::Signature)],
+    sighash: &Message,
+) -> BridgeResult<()> {
+    let missing: Vec<&XOnlyPublicKey> = verifiers
+        .iter()
+        .filter(|verifier| {
+            !sigs
+                .iter()
+                .any(|(pk, sig)| pk == *verifier && secp.verify_schnorr(sig, sighash, pk).is_ok())
+        })
+        .collect();
+    if !missing.is_empty() {
+        tracing::error!("Missing valid signatures from verifiers: {:?}", missing);
+        return Err(BridgeError::FailedToGetPresigns);
+    }
+    Ok(())
+}
+
+/// Fee paid by a transaction, given the outputs it spends in input order.
+pub fn transaction_fee(tx: &bitcoin::Transaction, prevouts: &[TxOut]) -> BridgeResult<Amount> {
+    if prevouts.len() != tx.input.len() {
+        return Err(BridgeError::TxInputNotFound);
+    }
+    let input_value: Amount = prevouts.iter().map(|prevout| prevout.value).sum();
+    let output_value: Amount = tx.output.iter().map(|output| output.value).sum();
+    input_value
+        .checked_sub(output_value)
+        .ok_or(BridgeError::BitcoinTransactionError)
+}
+
+/// Part of the fee of a transaction above what `confirmed_fee_rate`, the rate
+/// of the block it was mined in, would have required. Zero if it underpaid.
+pub fn fee_overpayment(
+    tx: &bitcoin::Transaction,
+    prevouts: &[TxOut],
+    confirmed_fee_rate: FeeRate,
+) -> BridgeResult<Amount> {
+    let fee = transaction_fee(tx, prevouts)?;
+    let required_fee = confirmed_fee_rate
+        .fee_wu(tx.weight())
+        .ok_or(BridgeError::BitcoinTransactionError)?;
+    Ok(fee.checked_sub(required_fee).unwrap_or(Amount::ZERO))
+}
+
+/// Fee rate of a parent and child package, which is what the node uses to
+/// decide whether a CPFP child gets a stuck parent mined.
+pub fn package_fee_rate(
+    parent: &bitcoin::Transaction,
+    parent_prevouts: &[TxOut],
+    child: &bitcoin::Transaction,
+    child_prevouts: &[TxOut],
+) -> BridgeResult<FeeRate> {
+    let fee = transaction_fee(parent, parent_prevouts)? + transaction_fee(child, child_prevouts)?;
+    let weight = parent.weight() + child.weight();
+    Ok(FeeRate::from_sat_per_kwu(
+        fee.to_sat() * 1000 / weight.to_wu(),
+    ))
+}
+
+/// Fee a CPFP child of `child_weight` has to pay for its package with
+/// `parent` to reach `target_fee_rate`. Never less than the child's own fee
+/// at that rate, even when the parent already pays enough.
+pub fn cpfp_child_fee(
+    parent: &bitcoin::Transaction,
+    parent_prevouts: &[TxOut],
+    child_weight: Weight,
+    target_fee_rate: FeeRate,
+) -> BridgeResult<Amount> {
+    let parent_fee = transaction_fee(parent, parent_prevouts)?;
+    let package_fee = target_fee_rate
+        .fee_wu(parent.weight() + child_weight)
+        .ok_or(BridgeError::FeeTooHigh)?;
+    let child_fee = target_fee_rate
+        .fee_wu(child_weight)
+        .ok_or(BridgeError::FeeTooHigh)?;
+    Ok(package_fee
+        .checked_sub(parent_fee)
+        .unwrap_or(Amount::ZERO)
+        .max(child_fee))
+}
+
+/// Fee rate of `target_feerate_sat_vb` for a CPFP package, checked to be at
+/// least `min_fee_rate` and the minimum relay fee rate, and at most
+/// `max_fee_rate`, since the child pays for the whole package out of a
+/// single output.
+pub fn cpfp_target_fee_rate(
+    target_feerate_sat_vb: u64,
+    min_fee_rate: FeeRate,
+    max_fee_rate: FeeRate,
+) -> BridgeResult<FeeRate> {
+    let target_fee_rate =
+        FeeRate::from_sat_per_vb(target_feerate_sat_vb).ok_or(BridgeError::FeeTooHigh)?;
+    check_fee_rate_bounds(
+        target_fee_rate,
+        min_fee_rate.max(FeeRate::BROADCAST_MIN),
+        max_fee_rate,
+    )?;
+    Ok(target_fee_rate)
+}
+
+/// Calls `attempt` at `min_fee_rate`, then at a fee rate `step` higher each
+/// time it returns `None`, e.g. because the transaction it broadcast is still
+/// not mined. The last attempt is at `max_fee_rate`; instead of going above
+/// it, escalation stops with `FeeTooHigh`.
+pub fn escalate_fee_rate<T>(
+    min_fee_rate: FeeRate,
+    max_fee_rate: FeeRate,
+    step: FeeRate,
+    mut attempt: impl FnMut(FeeRate) -> BridgeResult<Option<T>>,
+) -> BridgeResult<T> {
+    check_fee_rate_bounds(min_fee_rate, min_fee_rate, max_fee_rate)?;
+    if step == FeeRate::ZERO {
+        return Err(BridgeError::FeeTooLow);
+    }
+    let mut fee_rate = min_fee_rate;
+    loop {
+        if let Some(result) = attempt(fee_rate)? {
+            return Ok(result);
+        }
+        if fee_rate == max_fee_rate {
+            tracing::warn!(
+                "Fee escalation reached the ceiling of {:?} without success",
+                max_fee_rate
+            );
+            return Err(BridgeError::FeeTooHigh);
+        }
+        fee_rate = FeeRate::from_sat_per_kwu(
+            fee_rate
+                .to_sat_per_kwu()
+                .saturating_add(step.to_sat_per_kwu()),
+        )
+        .min(max_fee_rate);
+    }
+}
+
+/// Calls `call` until it succeeds or fails for a reason other than the node
+/// being unreachable, busy ("Work queue depth exceeded") or warming up. Such
+/// failures are retried up to `max_retries` times, waiting `base_delay` before
+/// the first retry and twice as long before each one after it.
+pub fn rpc_retry<T>(
+    max_retries: u32,
+    base_delay: Duration,
+    mut call: impl FnMut() -> Result<T, bitcoincore_rpc::Error>,
+) -> BridgeResult<T> {
+    let mut attempt = 0;
+    loop {
+        match call() {
+            Ok(result) => return Ok(result),
+            Err(err) if attempt < max_retries && is_transient_rpc_error(&err) => {
+                let delay = base_delay.saturating_mul(2u32.saturating_pow(attempt));
+                attempt += 1;
+                tracing::warn!(
+                    "RPC call failed: {:?}, retrying in {:?} (attempt {} of {})",
+                    err,
+                    delay,
+                    attempt,
+                    max_retries
+                );
+                std::thread::sleep(delay);
+            }
+            Err(err) => return Err(BridgeError::BitcoinRpcError(err)),
+        }
+    }
+}
+
+/// Sends a transaction with `send`, retried like `rpc_retry`. An attempt
+/// that timed out may still have reached the node, so a retry that finds the
+/// transaction already in the mempool or the chain returns `txid`.
+pub fn send_retry(
+    max_retries: u32,
+    base_delay: Duration,
+    txid: Txid,
+    mut send: impl FnMut() -> Result<Txid, bitcoincore_rpc::Error>,
+) -> BridgeResult<Txid> {
+    let mut attempts: u32 = 0;
+    rpc_retry(max_retries, base_delay, || {
+        attempts += 1;
+        match send() {
+            Err(err) if attempts > 1 && is_rpc_already_sent(&err) => {
+                tracing::debug!("Transaction {:?} was sent by an earlier attempt", txid);
+                Ok(txid)
+            }
+            result => result,
+        }
+    })
+}
+
+/// Whether the node rejected a transaction because it already has it, in
+/// the chain (`RPC_VERIFY_ALREADY_IN_CHAIN`) or in the mempool.
+pub fn is_rpc_already_sent(err: &bitcoincore_rpc::Error) -> bool {
+    match err {
+        bitcoincore_rpc::Error::JsonRpc(bitcoincore_rpc::jsonrpc::Error::Rpc(e)) => {
+            e.code == -27 || e.message.contains("txn-already-in-mempool")
+        }
+        _ => false,
+    }
+}
+
+/// Whether the call may succeed if it is retried, because the node was not
+/// reachable, busy or warming up.
+pub fn is_transient_rpc_error(err: &bitcoincore_rpc::Error) -> bool {
+    match err {
+        // Covers dropped connections and HTTP errors like a full work queue
+        bitcoincore_rpc::Error::JsonRpc(bitcoincore_rpc::jsonrpc::Error::Transport(_)) => true,
+        // RPC_IN_WARMUP
+        bitcoincore_rpc::Error::JsonRpc(bitcoincore_rpc::jsonrpc::Error::Rpc(e)) => e.code == -28,
+        _ => false,
+    }
+}
+
+/// Whether the node does not know the requested transaction, block or key
+/// (`RPC_INVALID_ADDRESS_OR_KEY`). Confirmed transactions outside the wallet
+/// are only known to a node with `-txindex`, see `ExtendedRpc::check_txindex`.
+pub fn is_rpc_not_found(err: &bitcoincore_rpc::Error) -> bool {
+    matches!(
+        err,
+        bitcoincore_rpc::Error::JsonRpc(bitcoincore_rpc::jsonrpc::Error::Rpc(e)) if e.code == -5
+    )
+}
+
+/// Checks that the fee rate is within `[min_fee_rate, max_fee_rate]`. The
+/// ceiling keeps a fee spike from burning the whole value of an output.
+pub fn check_fee_rate_bounds(
+    fee_rate: FeeRate,
+    min_fee_rate: FeeRate,
+    max_fee_rate: FeeRate,
+) -> BridgeResult<()> {
+    if fee_rate < min_fee_rate {
+        return Err(BridgeError::FeeTooLow);
+    }
+    if fee_rate > max_fee_rate {
+        return Err(BridgeError::FeeTooHigh);
+    }
+    Ok(())
+}
+
+/// Value of the operator's output in the claim transaction. The claim spends
+/// the bridge output of the move transaction and a connector tree leaf, and
+/// both transactions pay `relay_fee` and an anchor output.
+pub fn expected_claim_amount(
+    bridge_amount: Amount,
+    dust: Amount,
+    relay_fee: Amount,
+) -> BridgeResult<Amount> {
+    let anyone_can_spend_value = ScriptBuilder::anyone_can_spend_txout().value;
+    let evm_address_inscription_value =
+        ScriptBuilder::op_return_txout(&EVMAddress::default()).value;
+    relay_fee
+        .checked_add(anyone_can_spend_value)
+        .and_then(|cost| cost.checked_mul(2))
+        .and_then(|cost| cost.checked_add(evm_address_inscription_value))
+        .and_then(|cost| bridge_amount.checked_sub(cost))
+        .and_then(|amount| amount.checked_add(dust))
+        .ok_or(BridgeError::BitcoinTransactionError)
+}
+
+/// Fee for a transaction of `vsize` virtual bytes to confirm in `conf_target`
+/// blocks. Falls back to `MIN_RELAY_FEE` when the backend has no estimate, as
+/// on regtest, and never goes below it.
+pub fn estimate_fee(
+    backend: &(impl ChainBackend + ?Sized),
+    vsize: usize,
+    conf_target: u16,
+) -> BridgeResult<Amount> {
+    let min_fee = Amount::from_sat(MIN_RELAY_FEE);
+    let fee = match backend.estimate_fee_rate(conf_target)? {
+        Some(fee_rate) => fee_rate
+            .fee_vb(vsize as u64)
+            .ok_or(BridgeError::BitcoinTransactionError)?,
+        None => min_fee,
+    };
+    Ok(fee.max(min_fee))
+}
+
+/// Checks that the absolute fee is within `[min_fee, max_fee]`.
+pub fn check_fee_bounds(fee: Amount, min_fee: Amount, max_fee: Amount) -> BridgeResult<()> {
+    if fee < min_fee {
+        return Err(BridgeError::FeeTooLow);
+    }
+    if fee > max_fee {
+        return Err(BridgeError::FeeTooHigh);
+    }
+    Ok(())
+}
+
+pub fn get_claim_reveal_indices(depth: usize, count: u32) -> BridgeResult<Vec<(usize, usize)>> {
+    if count as u64 > 1u64 << depth {
+        return Err(BridgeError::MerkleIndexOutOfBounds);
+    }
 
     if count == 0 {
-        return vec![(0, 0)];
+        return Ok(vec![(0, 0)]);
     }
 
     let mut indices: Vec<(usize, usize)> = Vec::new();
     if count == 2u32.pow(depth as u32) {
-        return indices;
+        return Ok(indices);
     }
 
     if count % 2 == 1 {
         indices.push((depth, count as usize));
-        indices.extend(get_claim_reveal_indices(depth - 1, (count + 1) / 2));
+        indices.extend(get_claim_reveal_indices(depth - 1, (count + 1) / 2)?);
     } else {
-        indices.extend(get_claim_reveal_indices(depth - 1, count / 2));
+        indices.extend(get_claim_reveal_indices(depth - 1, count / 2)?);
     }
 
-    indices
+    Ok(indices)
 }
 
 pub fn get_claim_proof_tree_leaf(
     depth: usize,
     num_claims: usize,
     connector_tree_hashes: &HashTree,
-) -> [u8; 32] {
-    let indices = get_claim_reveal_indices(depth, num_claims as u32);
+) -> BridgeResult<[u8; 32]> {
+    let indices = get_claim_reveal_indices(depth, num_claims as u32)?;
     let mut hasher = Sha256::new();
-    indices.iter().for_each(|(level, index)| {
-        hasher.update(connector_tree_hashes[*level][*index]);
-    });
-    hasher.finalize().into()
+    for (level, index) in indices.iter() {
+        let hash = connector_tree_hashes
+            .get(*level)
+            .and_then(|level_hashes| level_hashes.get(*index))
+            .ok_or(BridgeError::MerkleIndexOutOfBounds)?;
+        hasher.update(hash);
+    }
+    Ok(hasher.finalize().into())
 }
 pub fn calculate_claim_proof_root(
     depth: usize,
     connector_tree_hashes: &Vec<Vec<[u8; 32]>>,
-) -> [u8; 32] {
+) -> BridgeResult<[u8; 32]> {
     let mut hashes: Vec<[u8; 32]> = Vec::new();
     for i in 0..2u32.pow(depth as u32) {
-        let hash = get_claim_proof_tree_leaf(depth, i as usize, connector_tree_hashes);
+        let hash = get_claim_proof_tree_leaf(depth, i as usize, connector_tree_hashes)?;
         hashes.push(hash);
     }
     let mut level = 0;
@@ -172,13 +723,591 @@ pub fn calculate_claim_proof_root(
         hashes = level_hashes.clone();
         level += 1;
     }
-    hashes[0]
+    Ok(hashes[0])
+}
+
+/// Depth of the tree `connector_tree_commitment` folds the nodes into. Each
+/// round has less than `2^(CONNECTOR_TREE_DEPTH + 1)` nodes.
+const CONNECTOR_TREE_COMMITMENT_DEPTH: usize =
+    CONNECTOR_TREE_DEPTH + 1 + (usize::BITS - (NUM_ROUNDS - 1).leading_zeros()) as usize;
+
+/// Single hash committing to the connector tree hashes of every round, so the
+/// operator and the verifiers can compare their trees before any deposit is
+/// presigned. The hashes are added to an incremental merkle tree round by
+/// round, level by level from the root and left to right. Every round must
+/// have `CONNECTOR_TREE_DEPTH + 1` levels with `CONNECTOR_TREE_ARITY^i`
+/// hashes at level `i`, so the same hashes can not be split differently.
+pub fn connector_tree_commitment(connector_tree_hashes: &[HashTree]) -> BridgeResult<HashType> {
+    if connector_tree_hashes.len() != NUM_ROUNDS
+        || connector_tree_hashes.iter().any(|hashes| {
+            hashes.len() != CONNECTOR_TREE_DEPTH + 1
+                || hashes.iter().enumerate().any(|(level, level_hashes)| {
+                    level_hashes.len() != CONNECTOR_TREE_ARITY.pow(level as u32)
+                })
+        })
+    {
+        return Err(BridgeError::MerkleIndexOutOfBounds);
+    }
+
+    let mut imt = IncrementalMerkleTree::<CONNECTOR_TREE_COMMITMENT_DEPTH>::new();
+    for hash in connector_tree_hashes.iter().flatten().flatten() {
+        imt.add(*hash);
+    }
+    Ok(imt.root)
 }
 
 // tests
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::constants::{DUST_VALUE, MIN_RELAY_FEE, NUM_VERIFIERS};
+    use crate::traits::chain_backend::MockChainBackend;
+    use bitcoin::hashes::Hash;
+    use bitcoin::Txid;
+    use clementine_circuits::constants::BRIDGE_AMOUNT_SATS;
+    use clementine_circuits::sha256_hash;
+
+    /// `n` random x-only public keys
+    fn random_xonly_pks(n: usize) -> Vec<XOnlyPublicKey> {
+        let secp = Secp256k1::new();
+        (0..n)
+            .map(|_| {
+                secp.generate_keypair(&mut crypto_bigint::rand_core::OsRng)
+                    .1
+                    .x_only_public_key()
+                    .0
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_connector_tree_input_weight() {
+        // 41 bytes of non-witness data and 170 bytes of witness
+        assert_eq!(connector_tree_input_weight(), Weight::from_wu(334));
+    }
+
+    #[test]
+    fn test_n_of_n_witness_weight() {
+        for num_verifiers in [1, 3, 300] {
+            let verifiers_pks = random_xonly_pks(num_verifiers);
+            let script = ScriptBuilder::new(verifiers_pks).generate_script_n_of_n();
+
+            let mut witness = bitcoin::Witness::new();
+            for _ in 0..num_verifiers {
+                witness.push([0u8; 64]);
+            }
+            witness.push(script.as_bytes());
+            witness.push([0u8; 33]);
+
+            assert_eq!(
+                n_of_n_witness_weight(num_verifiers, script.len(), 33),
+                witness.size()
+            );
+        }
+    }
+
+    #[test]
+    fn test_safe_operator_csv() {
+        assert_eq!(safe_operator_csv(600, 3600, 6), 12);
+        assert_eq!(safe_operator_csv(600, 3601, 6), 13);
+        assert_eq!(safe_operator_csv(600, 0, 0), 1);
+        assert_eq!(safe_operator_csv(1, u64::MAX, 1), u16::MAX);
+    }
+
+    #[test]
+    fn test_validate_tree_value_invariant() {
+        let fee = Amount::from_sat(MIN_RELAY_FEE);
+        let dust = Amount::from_sat(DUST_VALUE);
+        let parent = calculate_amount(3, dust, fee) - fee;
+        let child = calculate_amount(2, dust, fee) - fee;
+        assert!(validate_tree_value_invariant(parent, &[child, child], fee).is_ok());
+        assert!(matches!(
+            validate_tree_value_invariant(parent, &[child, child - fee], fee),
+            Err(BridgeError::ConnectorTreeValueMismatch)
+        ));
+        assert!(matches!(
+            validate_tree_value_invariant(parent, &[child, child], Amount::ZERO),
+            Err(BridgeError::ConnectorTreeValueMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_compare_witness_sizes() {
+        assert_eq!(compare_witness_sizes(NUM_VERIFIERS), (433, 66));
+
+        let verifiers_pks = random_xonly_pks(NUM_VERIFIERS);
+        let script = ScriptBuilder::new(verifiers_pks).generate_script_n_of_n();
+        assert_eq!(
+            compare_witness_sizes(NUM_VERIFIERS).0,
+            n_of_n_witness_weight(NUM_VERIFIERS, script.len(), 33)
+        );
+    }
+
+    #[test]
+    fn test_select_claimable_leaves() {
+        let leaves: Vec<(OutPoint, Amount)> = [1_000, 50_000, 50]
+            .iter()
+            .enumerate()
+            .map(|(vout, value)| {
+                (
+                    OutPoint {
+                        txid: Txid::all_zeros(),
+                        vout: vout as u32,
+                    },
+                    Amount::from_sat(*value),
+                )
+            })
+            .collect();
+
+        // A connector tree input is 334 WU, about 84 sat at 1 sat/vB, 1670
+        // sat at 20 sat/vB and 83500 sat at 1000 sat/vB
+        let claimable = select_claimable_leaves(&leaves, FeeRate::from_sat_per_vb_unchecked(1));
+        assert_eq!(claimable, vec![leaves[0].0, leaves[1].0]);
+
+        let claimable = select_claimable_leaves(&leaves, FeeRate::from_sat_per_vb_unchecked(20));
+        assert_eq!(claimable, vec![leaves[1].0]);
+
+        let claimable = select_claimable_leaves(&leaves, FeeRate::from_sat_per_vb_unchecked(1000));
+        assert!(claimable.is_empty());
+    }
+
+    #[test]
+    fn test_validate_claim_csv() {
+        let tx = |sequence: bitcoin::Sequence| bitcoin::Transaction {
+            version: bitcoin::transaction::Version(2),
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![bitcoin::TxIn {
+                previous_output: OutPoint::new(Txid::all_zeros(), 0),
+                script_sig: ScriptBuf::new(),
+                sequence,
+                witness: bitcoin::Witness::new(),
+            }],
+            output: vec![],
+        };
+
+        let min_csv = Timelock::Blocks(10);
+        assert!(validate_claim_csv(&tx(Timelock::Blocks(10).to_sequence()), 0, min_csv).is_ok());
+        assert!(validate_claim_csv(&tx(Timelock::Blocks(11).to_sequence()), 0, min_csv).is_ok());
+        assert!(validate_claim_csv(&tx(Timelock::Blocks(9).to_sequence()), 0, min_csv).is_err());
+        assert!(validate_claim_csv(&tx(Timelock::Time(10).to_sequence()), 0, min_csv).is_err());
+        assert!(validate_claim_csv(&tx(bitcoin::Sequence::MAX), 0, min_csv).is_err());
+        assert!(matches!(
+            validate_claim_csv(&tx(Timelock::Blocks(10).to_sequence()), 1, min_csv),
+            Err(BridgeError::TxInputNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_operator_address_matches_actor() {
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let (sk, _) = secp.generate_keypair(&mut crypto_bigint::rand_core::OsRng);
+        let actor = crate::actor::Actor::new(sk);
+        assert_eq!(
+            operator_address(actor.xonly_public_key, crate::constants::NETWORK),
+            actor.address
+        );
+    }
+
+    #[test]
+    fn test_check_verifiers_pks() {
+        let pks = random_xonly_pks(3);
+
+        assert!(check_verifiers_pks(&pks).is_ok());
+        assert!(matches!(
+            check_verifiers_pks(&[]),
+            Err(BridgeError::PublicKeyNotFound)
+        ));
+        assert!(matches!(
+            check_verifiers_pks(&[pks[0], pks[1], pks[0]]),
+            Err(BridgeError::DuplicateVerifier)
+        ));
+    }
+
+    #[test]
+    fn test_threshold_satisfied() {
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let keypairs: Vec<bitcoin::secp256k1::Keypair> = (0..3)
+            .map(|_| bitcoin::secp256k1::Keypair::new(&secp, &mut crypto_bigint::rand_core::OsRng))
+            .collect();
+        let message = bitcoin::secp256k1::Message::from_digest([1u8; 32]);
+        let sigs: Vec<(XOnlyPublicKey, schnorr::Signature)> = keypairs
+            .iter()
+            .map(|keypair| {
+                (
+                    keypair.x_only_public_key().0,
+                    secp.sign_schnorr(&message, keypair),
+                )
+            })
+            .collect();
+        let verifiers: Vec<XOnlyPublicKey> = sigs.iter().map(|(pk, _)| *pk).collect();
+
+        assert!(threshold_satisfied(&secp, &verifiers, &sigs, &message).is_ok());
+        assert!(matches!(
+            threshold_satisfied(&secp, &verifiers, &sigs[1..], &message),
+            Err(BridgeError::FailedToGetPresigns)
+        ));
+
+        // A signature of another message does not count
+        let mut forged = sigs.clone();
+        forged[0].1 = secp.sign_schnorr(&Message::from_digest([2u8; 32]), &keypairs[0]);
+        assert!(matches!(
+            threshold_satisfied(&secp, &verifiers, &forged, &message),
+            Err(BridgeError::FailedToGetPresigns)
+        ));
+    }
+
+    #[test]
+    fn test_fee_overpayment() {
+        let tx = bitcoin::Transaction {
+            version: bitcoin::transaction::Version(2),
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![bitcoin::TxIn {
+                previous_output: OutPoint::new(Txid::all_zeros(), 0),
+                script_sig: ScriptBuf::new(),
+                sequence: bitcoin::Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: bitcoin::Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(9_900),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        let prevouts = vec![TxOut {
+            value: Amount::from_sat(10_000),
+            script_pubkey: ScriptBuf::new(),
+        }];
+        // Pays 100 sats for 240 WU
+        assert_eq!(
+            fee_overpayment(&tx, &prevouts, FeeRate::from_sat_per_kwu(100)).unwrap(),
+            Amount::from_sat(76)
+        );
+        assert_eq!(
+            fee_overpayment(&tx, &prevouts, FeeRate::from_sat_per_kwu(1_000)).unwrap(),
+            Amount::ZERO
+        );
+        assert!(fee_overpayment(&tx, &[], FeeRate::from_sat_per_kwu(100)).is_err());
+    }
+
+    #[test]
+    fn test_package_fee_rate() {
+        let spend = |previous_output: OutPoint, value: u64| bitcoin::Transaction {
+            version: bitcoin::transaction::Version(2),
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![bitcoin::TxIn {
+                previous_output,
+                script_sig: ScriptBuf::new(),
+                sequence: bitcoin::Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: bitcoin::Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(value),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        let parent_prevouts = vec![TxOut {
+            value: Amount::from_sat(10_000),
+            script_pubkey: ScriptBuf::new(),
+        }];
+        // Parent pays 100 sats, child pays 1900 sats, both are 240 WU
+        let parent = spend(OutPoint::new(Txid::all_zeros(), 0), 9_900);
+        let child = spend(OutPoint::new(parent.txid(), 0), 8_000);
+        let child_prevouts = parent.output.clone();
+
+        let parent_fee_rate = FeeRate::from_sat_per_kwu(
+            transaction_fee(&parent, &parent_prevouts).unwrap().to_sat() * 1000
+                / parent.weight().to_wu(),
+        );
+        let package_rate =
+            package_fee_rate(&parent, &parent_prevouts, &child, &child_prevouts).unwrap();
+        assert_eq!(package_rate, FeeRate::from_sat_per_kwu(2_000 * 1000 / 480));
+        assert!(package_rate > parent_fee_rate);
+
+        assert!(matches!(
+            package_fee_rate(&parent, &[], &child, &child_prevouts),
+            Err(BridgeError::TxInputNotFound)
+        ));
+
+        // A child paying `cpfp_child_fee` brings the package to the target
+        let target_fee_rate = FeeRate::from_sat_per_kwu(10_000);
+        let child_fee =
+            cpfp_child_fee(&parent, &parent_prevouts, child.weight(), target_fee_rate).unwrap();
+        assert_eq!(child_fee, Amount::from_sat(4_800 - 100));
+        let bumping_child = spend(OutPoint::new(parent.txid(), 0), 9_900 - child_fee.to_sat());
+        assert!(
+            package_fee_rate(&parent, &parent_prevouts, &bumping_child, &child_prevouts).unwrap()
+                >= target_fee_rate
+        );
+        // The child pays for itself when the parent is already above the target
+        assert_eq!(
+            cpfp_child_fee(
+                &parent,
+                &parent_prevouts,
+                child.weight(),
+                FeeRate::from_sat_per_kwu(100)
+            )
+            .unwrap(),
+            Amount::from_sat(24)
+        );
+        let overspending_child = spend(OutPoint::new(parent.txid(), 0), 10_000);
+        assert!(matches!(
+            package_fee_rate(
+                &parent,
+                &parent_prevouts,
+                &overspending_child,
+                &child_prevouts
+            ),
+            Err(BridgeError::BitcoinTransactionError)
+        ));
+    }
+
+    #[test]
+    fn test_check_fee_rate_bounds() {
+        let min = FeeRate::from_sat_per_vb_unchecked(1);
+        let max = FeeRate::from_sat_per_vb_unchecked(50);
+
+        assert!(check_fee_rate_bounds(min, min, max).is_ok());
+        assert!(check_fee_rate_bounds(max, min, max).is_ok());
+        assert!(matches!(
+            check_fee_rate_bounds(FeeRate::ZERO, min, max),
+            Err(BridgeError::FeeTooLow)
+        ));
+        assert!(matches!(
+            check_fee_rate_bounds(FeeRate::from_sat_per_vb_unchecked(51), min, max),
+            Err(BridgeError::FeeTooHigh)
+        ));
+    }
+
+    #[test]
+    fn test_cpfp_target_fee_rate() {
+        let min = FeeRate::from_sat_per_vb_unchecked(5);
+        let max = FeeRate::from_sat_per_vb_unchecked(50);
+
+        assert_eq!(
+            cpfp_target_fee_rate(50, min, max).unwrap(),
+            FeeRate::from_sat_per_vb_unchecked(50)
+        );
+        assert!(matches!(
+            cpfp_target_fee_rate(51, min, max),
+            Err(BridgeError::FeeTooHigh)
+        ));
+        assert!(matches!(
+            cpfp_target_fee_rate(u64::MAX, min, max),
+            Err(BridgeError::FeeTooHigh)
+        ));
+        assert!(matches!(
+            cpfp_target_fee_rate(4, min, max),
+            Err(BridgeError::FeeTooLow)
+        ));
+        // The minimum relay fee rate is a floor even without a configured one
+        assert!(matches!(
+            cpfp_target_fee_rate(0, FeeRate::ZERO, max),
+            Err(BridgeError::FeeTooLow)
+        ));
+    }
+
+    #[test]
+    fn test_escalate_fee_rate() {
+        let min = FeeRate::from_sat_per_vb_unchecked(5);
+        let max = FeeRate::from_sat_per_vb_unchecked(22);
+        let step = FeeRate::from_sat_per_vb_unchecked(5);
+
+        // Stops at the first accepted fee rate
+        let mut attempts = Vec::new();
+        let accepted = escalate_fee_rate(min, max, step, |fee_rate| {
+            attempts.push(fee_rate);
+            Ok((attempts.len() == 3).then_some(fee_rate))
+        })
+        .unwrap();
+        assert_eq!(accepted, FeeRate::from_sat_per_vb_unchecked(15));
+
+        // Never attempts above the ceiling, the last attempt is at it
+        let mut attempts = Vec::new();
+        let result = escalate_fee_rate(min, max, step, |fee_rate| {
+            attempts.push(fee_rate);
+            Ok(None::<()>)
+        });
+        assert!(matches!(result, Err(BridgeError::FeeTooHigh)));
+        assert_eq!(
+            attempts,
+            [5, 10, 15, 20, 22].map(FeeRate::from_sat_per_vb_unchecked)
+        );
+
+        // Errors of an attempt end the escalation
+        let mut attempts = 0;
+        let result = escalate_fee_rate(min, max, step, |_| {
+            attempts += 1;
+            Err::<Option<()>, _>(BridgeError::UTXOSpent)
+        });
+        assert!(matches!(result, Err(BridgeError::UTXOSpent)));
+        assert_eq!(attempts, 1);
+
+        assert!(matches!(
+            escalate_fee_rate(max, min, step, |_| Ok(Some(()))),
+            Err(BridgeError::FeeTooHigh)
+        ));
+        assert!(matches!(
+            escalate_fee_rate(min, max, FeeRate::ZERO, |_| Ok(Some(()))),
+            Err(BridgeError::FeeTooLow)
+        ));
+    }
+
+    #[test]
+    fn test_expected_claim_amount() {
+        let verifiers_pks = random_xonly_pks(2);
+        let transaction_builder = TransactionBuilder::new(verifiers_pks.clone());
+        let operator_address = operator_address(verifiers_pks[1], Network::Regtest);
+        let claim_tx = transaction_builder
+            .create_operator_claim_tx(
+                OutPoint::new(Txid::all_zeros(), 0),
+                OutPoint::new(Txid::all_zeros(), 1),
+                &operator_address,
+                &verifiers_pks[1],
+                &[0u8; 32],
+            )
+            .unwrap();
+
+        let dust = Amount::from_sat(DUST_VALUE);
+        let relay_fee = Amount::from_sat(MIN_RELAY_FEE);
+        assert_eq!(
+            expected_claim_amount(Amount::from_sat(BRIDGE_AMOUNT_SATS), dust, relay_fee).unwrap(),
+            claim_tx.tx.output[0].value
+        );
+        assert!(matches!(
+            expected_claim_amount(relay_fee, dust, relay_fee),
+            Err(BridgeError::BitcoinTransactionError)
+        ));
+    }
+
+    #[test]
+    fn test_verify_control_block() {
+        let secp = Secp256k1::new();
+        let (_, pk) = secp.generate_keypair(&mut crypto_bigint::rand_core::OsRng);
+        let xonly_pk = pk.x_only_public_key().0;
+        let timelock_script =
+            ScriptBuilder::generate_timelock_script(&xonly_pk, CONNECTOR_TREE_OPERATOR_TAKES_AFTER);
+        let (_, tree_info) =
+            TransactionBuilder::create_connector_tree_node_address(&secp, &xonly_pk, &[1u8; 32])
+                .unwrap();
+        let output_key = tree_info.output_key().to_inner();
+        let control_block = create_control_block(tree_info, &timelock_script).unwrap();
+
+        assert!(verify_control_block(
+            &secp,
+            *INTERNAL_KEY,
+            output_key,
+            &timelock_script,
+            &control_block
+        )
+        .is_ok());
+        assert!(matches!(
+            verify_control_block(
+                &secp,
+                *INTERNAL_KEY,
+                output_key,
+                &ScriptBuf::new(),
+                &control_block
+            ),
+            Err(BridgeError::ControlBlockError)
+        ));
+        assert!(matches!(
+            verify_control_block(
+                &secp,
+                xonly_pk,
+                output_key,
+                &timelock_script,
+                &control_block
+            ),
+            Err(BridgeError::ControlBlockError)
+        ));
+        assert!(matches!(
+            verify_control_block(
+                &secp,
+                *INTERNAL_KEY,
+                xonly_pk,
+                &timelock_script,
+                &control_block
+            ),
+            Err(BridgeError::ControlBlockError)
+        ));
+    }
+
+    #[test]
+    fn test_connector_tree_timelock_witness() {
+        let secp = Secp256k1::new();
+        let (_, pk) = secp.generate_keypair(&mut crypto_bigint::rand_core::OsRng);
+        let xonly_pk = pk.x_only_public_key().0;
+        let (address, tree_info) =
+            TransactionBuilder::create_connector_tree_node_address(&secp, &xonly_pk, &[5u8; 32])
+                .unwrap();
+        let timelock_script =
+            ScriptBuilder::generate_timelock_script(&xonly_pk, CONNECTOR_TREE_OPERATOR_TAKES_AFTER);
+        let mut tx = TransactionBuilder::create_connector_tree_tx(
+            &OutPoint::new(Txid::all_zeros(), 0),
+            1,
+            vec![address.clone(), address],
+        );
+
+        let sig = [7u8; 64];
+        handle_taproot_witness(
+            &mut tx,
+            0,
+            &vec![sig.as_slice()],
+            &timelock_script,
+            &tree_info,
+        )
+        .unwrap();
+
+        let witness = &tx.input[0].witness;
+        assert_eq!(witness.len(), 3);
+        assert_eq!(witness.nth(0).unwrap(), sig.as_slice());
+        assert_eq!(witness.nth(1).unwrap(), timelock_script.as_bytes());
+        let control_block = ControlBlock::decode(witness.nth(2).unwrap()).unwrap();
+        assert!(control_block.verify_taproot_commitment(
+            &secp,
+            tree_info.output_key().to_inner(),
+            &timelock_script
+        ));
+    }
+
+    #[test]
+    fn test_estimate_fee() {
+        let backend = MockChainBackend::new();
+        // No estimate on regtest
+        assert_eq!(
+            estimate_fee(&backend, 200, 6).unwrap(),
+            Amount::from_sat(MIN_RELAY_FEE)
+        );
+
+        backend.set_fee_rate(Some(FeeRate::from_sat_per_vb_unchecked(10)));
+        assert_eq!(
+            estimate_fee(&backend, 200, 6).unwrap(),
+            Amount::from_sat(2000)
+        );
+
+        // Never below the relay minimum
+        backend.set_fee_rate(Some(FeeRate::from_sat_per_vb_unchecked(1)));
+        assert_eq!(
+            estimate_fee(&backend, 200, 6).unwrap(),
+            Amount::from_sat(MIN_RELAY_FEE)
+        );
+    }
+
+    #[test]
+    fn test_check_fee_bounds() {
+        let min = Amount::from_sat(500);
+        let max = Amount::from_sat(10_000);
+
+        assert!(check_fee_bounds(min, min, max).is_ok());
+        assert!(check_fee_bounds(max, min, max).is_ok());
+        assert!(matches!(
+            check_fee_bounds(Amount::from_sat(499), min, max),
+            Err(BridgeError::FeeTooLow)
+        ));
+        assert!(matches!(
+            check_fee_bounds(Amount::from_sat(10_001), min, max),
+            Err(BridgeError::FeeTooHigh)
+        ));
+    }
 
     #[test]
     fn test_get_indices() {
@@ -205,7 +1334,7 @@ mod tests {
         ];
 
         for ((depth, index), expected) in test_cases {
-            let indices = get_claim_reveal_indices(depth, index);
+            let indices = get_claim_reveal_indices(depth, index).unwrap();
             assert_eq!(
                 indices, expected,
                 "Failed at get_indices({}, {})",
@@ -213,4 +1342,150 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_connector_tree_commitment() {
+        let connector_tree_hashes: Vec<HashTree> = (0..NUM_ROUNDS)
+            .map(|round| {
+                (0..CONNECTOR_TREE_DEPTH + 1)
+                    .map(|level| {
+                        (0..CONNECTOR_TREE_ARITY.pow(level as u32))
+                            .map(|i| sha256_hash!([round as u8, level as u8, i as u8]))
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect();
+        let root = connector_tree_commitment(&connector_tree_hashes).unwrap();
+
+        // Another party holding the same trees gets the same root
+        let other_party_hashes = connector_tree_hashes.clone();
+        assert_eq!(
+            connector_tree_commitment(&other_party_hashes).unwrap(),
+            root
+        );
+
+        let mut changed_leaf = connector_tree_hashes.clone();
+        changed_leaf[NUM_ROUNDS - 1][CONNECTOR_TREE_DEPTH][0] = [0u8; 32];
+        assert_ne!(connector_tree_commitment(&changed_leaf).unwrap(), root);
+
+        let mut swapped_rounds = connector_tree_hashes.clone();
+        swapped_rounds.swap(0, NUM_ROUNDS - 1);
+        if NUM_ROUNDS > 1 {
+            assert_ne!(connector_tree_commitment(&swapped_rounds).unwrap(), root);
+        }
+
+        let mut missing_level = connector_tree_hashes;
+        missing_level[0].pop();
+        assert!(matches!(
+            connector_tree_commitment(&missing_level),
+            Err(BridgeError::MerkleIndexOutOfBounds)
+        ));
+    }
+
+    #[test]
+    fn test_rpc_retry() {
+        let transport_error = || {
+            bitcoincore_rpc::Error::JsonRpc(bitcoincore_rpc::jsonrpc::Error::Transport(Box::new(
+                std::io::Error::new(std::io::ErrorKind::ConnectionReset, "connection reset"),
+            )))
+        };
+
+        // Transient failures are retried until the call succeeds
+        let mut calls = 0;
+        let result = rpc_retry(3, Duration::ZERO, || {
+            calls += 1;
+            if calls < 3 {
+                Err(transport_error())
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        // and surfaced once the retries run out
+        let mut calls = 0;
+        let result: BridgeResult<()> = rpc_retry(2, Duration::ZERO, || {
+            calls += 1;
+            Err(transport_error())
+        });
+        assert!(matches!(result, Err(BridgeError::BitcoinRpcError(_))));
+        assert_eq!(calls, 3);
+
+        // Other errors are not retried
+        let mut calls = 0;
+        let result: BridgeResult<()> = rpc_retry(2, Duration::ZERO, || {
+            calls += 1;
+            Err(bitcoincore_rpc::Error::ReturnedError(
+                "bad-txns-inputs-missingorspent".to_string(),
+            ))
+        });
+        assert!(matches!(result, Err(BridgeError::BitcoinRpcError(_))));
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_send_retry() {
+        let txid = Txid::all_zeros();
+        let transport_error = || {
+            bitcoincore_rpc::Error::JsonRpc(bitcoincore_rpc::jsonrpc::Error::Transport(Box::new(
+                std::io::Error::new(std::io::ErrorKind::TimedOut, "timed out"),
+            )))
+        };
+        let rpc_error = |code, message: &str| {
+            bitcoincore_rpc::Error::JsonRpc(bitcoincore_rpc::jsonrpc::Error::Rpc(
+                bitcoincore_rpc::jsonrpc::error::RpcError {
+                    code,
+                    message: message.to_string(),
+                    data: None,
+                },
+            ))
+        };
+
+        // The first attempt got the transaction to the node, the retry finds
+        // it in the chain or in the mempool
+        for already_sent in [
+            rpc_error(-27, "Transaction already in block chain"),
+            rpc_error(-26, "txn-already-in-mempool"),
+        ] {
+            let mut errors = vec![already_sent, transport_error()];
+            let result = send_retry(3, Duration::ZERO, txid, || Err(errors.pop().unwrap()));
+            assert_eq!(result.unwrap(), txid);
+        }
+
+        // A transaction already in the chain is an error if no earlier
+        // attempt sent it
+        let result = send_retry(3, Duration::ZERO, txid, || {
+            Err(rpc_error(-27, "Transaction already in block chain"))
+        });
+        assert!(matches!(result, Err(BridgeError::BitcoinRpcError(_))));
+
+        // Other rejections of a retry are returned
+        let mut errors = vec![
+            rpc_error(-26, "bad-txns-inputs-missingorspent"),
+            transport_error(),
+        ];
+        let result = send_retry(3, Duration::ZERO, txid, || Err(errors.pop().unwrap()));
+        assert!(matches!(result, Err(BridgeError::BitcoinRpcError(_))));
+    }
+
+    #[test]
+    fn test_is_rpc_not_found() {
+        let rpc_error = |code| {
+            bitcoincore_rpc::Error::JsonRpc(bitcoincore_rpc::jsonrpc::Error::Rpc(
+                bitcoincore_rpc::jsonrpc::error::RpcError {
+                    code,
+                    message: String::new(),
+                    data: None,
+                },
+            ))
+        };
+
+        assert!(is_rpc_not_found(&rpc_error(-5)));
+        // RPC_IN_WARMUP
+        assert!(!is_rpc_not_found(&rpc_error(-28)));
+        assert!(!is_rpc_not_found(&bitcoincore_rpc::Error::ReturnedError(
+            "No such mempool or blockchain transaction".to_string()
+        )));
+    }
 }
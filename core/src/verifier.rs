@@ -1,30 +1,319 @@
-use crate::constants::{VerifierChallenge, CONNECTOR_TREE_DEPTH};
+use crate::constants::{
+    BridgeParams, VerifierChallenge, CONNECTOR_TREE_ARITY, CONNECTOR_TREE_DEPTH,
+    FEE_ESTIMATE_CONF_TARGET, MAX_MOVE_TX_FEE, MIN_MOVE_TX_FEE, MIN_RELAY_FEE, NETWORK,
+    WATCH_BLOCK_HASH_WINDOW,
+};
 use crate::db::verifier::VerifierMockDB;
-use crate::errors::BridgeError;
+use crate::errors::{BridgeError, BridgeResult, InvalidPeriodError};
 
+use crate::merkle::MerkleTree;
+use crate::rate_limit::{RateLimit, TokenBucket};
+use crate::traits::chain_backend::ChainBackend;
+use crate::traits::state_store::StateStore;
 use crate::traits::verifier::VerifierConnector;
-use crate::utils::check_deposit_utxo;
-use crate::{EVMAddress, HashTree};
-use bitcoin::Address;
+use crate::utils::{
+    check_deposit_utxo, check_fee_bounds, check_verifiers_pks, connector_tree_commitment,
+    estimate_fee, is_rpc_not_found, is_transient_rpc_error, operator_address, transaction_fee,
+    validate_tree_value_invariant,
+};
+use crate::{ConnectorUTXOTree, EVMAddress, HashTree};
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::schnorr;
 use bitcoin::{secp256k1, secp256k1::Secp256k1, OutPoint};
+use bitcoin::{Address, Amount, BlockHash, FeeRate, Network, ScriptBuf, TxOut, Txid};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use clementine_circuits::constants::{BRIDGE_AMOUNT_SATS, NUM_ROUNDS};
+use clementine_circuits::constants::{BRIDGE_AMOUNT_SATS, CLAIM_MERKLE_TREE_DEPTH, NUM_ROUNDS};
+use clementine_circuits::incremental_merkle::IncrementalMerkleTree;
+use clementine_circuits::{HashType, PreimageType};
 use secp256k1::SecretKey;
 use secp256k1::XOnlyPublicKey;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
-use crate::extended_rpc::ExtendedRpc;
-use crate::transaction_builder::TransactionBuilder;
+use crate::extended_rpc::{ExtendedRpc, UtxoSpendStatus};
+use crate::transaction_builder::{default_claim_outputs, CreateTxOutputs, TransactionBuilder};
 
-use crate::{actor::Actor, operator::DepositPresigns};
+use crate::{
+    actor::Actor,
+    operator::{DepositPresigns, DepositRequest},
+};
+
+/// What the verifier does when an RPC call fails because the node can not be
+/// reached while it is following the chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RpcFailurePolicy {
+    /// Retry up to `attempts` times on a new connection, waiting `backoff`
+    /// before each attempt
+    Reconnect { attempts: u32, backoff: Duration },
+    /// Return the error right away
+    FailFast,
+    /// Wait until the node is back, retrying on a new connection every `interval`
+    Pause { interval: Duration },
+}
+
+/// Lifecycle stage of a deposit, as seen on chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DepositState {
+    /// Deposit UTXO is unspent
+    Deposited,
+    /// Move (kickoff) transaction is in the mempool
+    KickoffBroadcast,
+    /// Move transaction is confirmed and its bridge output is unspent
+    KickoffConfirmed,
+    /// Bridge output of the move transaction is spent by the operator
+    Claimed,
+    /// Deposit UTXO is spent by something other than the move transaction
+    Refunded,
+}
+
+/// Ties a deposit on Bitcoin to its EVM destination with the transactions
+/// involved, for compliance audits.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DepositAuditReport {
+    pub deposit_utxo: OutPoint,
+    pub amount: Amount,
+    pub kickoff_txid: Txid,
+    /// Zero if the move (kickoff) transaction is not mined
+    pub kickoff_confirmations: u32,
+    /// N-of-n address that holds the deposit after the move transaction
+    pub bridge_address: String,
+    pub evm_address: EVMAddress,
+    pub state: DepositState,
+}
+
+/// What a signing request is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SigningRequestKind {
+    /// Move (kickoff) transaction of the deposit
+    Move,
+    /// Operator claim of the deposit for the given round
+    OperatorClaim { period: usize },
+}
+
+/// A sighash to be signed on an offline machine, with the input and the
+/// script leaf it commits to so the signer can check what it signs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SigningRequest {
+    pub kind: SigningRequestKind,
+    pub txid: Txid,
+    pub input_index: usize,
+    pub spend_script: ScriptBuf,
+    /// Taproot script spend sighash, sign it with `Actor::sign`
+    pub sighash: [u8; 32],
+}
+
+/// Failure of `Verifier::new_deposits` at the deposit `index`, with the
+/// presigns of the deposits before it
+#[derive(Debug, Error)]
+#[error("Deposit {index} of the batch failed: {source}")]
+pub struct DepositBatchError {
+    pub index: usize,
+    pub presigns: Vec<DepositPresigns>,
+    pub source: BridgeError,
+}
+
+/// Spend of a tracked connector tree UTXO, seen while rebuilding a watch map
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpendRecord {
+    pub utxo: OutPoint,
+    /// `(depth, index)` of the UTXO in the connector tree
+    pub position: (u32, u32),
+    /// Value of the UTXO, if it was created by an earlier spend
+    pub amount: Option<Amount>,
+    pub spending_txid: Txid,
+    pub block_height: u64,
+}
+
+/// What one scan of a connector tree found, see `Verifier::rebuild_watch_map`
+#[derive(Debug, Default)]
+pub struct WatchResult {
+    /// Unspent connector tree UTXOs, mapped to their `(depth, index)`
+    pub tracked_utxos: HashMap<OutPoint, (u32, u32)>,
+    /// Values of the tracked UTXOs created by a spend. The value of the root
+    /// is not known.
+    pub tracked_amounts: HashMap<OutPoint, Amount>,
+    /// Spends of tracked UTXOs, in chain order
+    pub spends_made: Vec<SpendRecord>,
+    /// Spends that broke the tree, e.g. children that do not add up to the
+    /// value of their parent. The children are tracked anyway.
+    pub errors: Vec<BridgeError>,
+    /// Last block applied to the result, the next scan continues after it
+    pub last_scanned_height: Option<u64>,
+    /// Hashes of the scanned blocks, to notice when they are reorged out
+    pub block_hashes: BTreeMap<u64, BlockHash>,
+}
+
+impl WatchResult {
+    /// Undoes everything applied from the blocks above `height`, e.g. when
+    /// they are reorged out. Spends are undone in reverse chain order, so the
+    /// children of a spend are dropped before its UTXO is tracked again.
+    pub fn rollback_to(&mut self, height: u64) {
+        while self
+            .spends_made
+            .last()
+            .is_some_and(|spend| spend.block_height > height)
+        {
+            let Some(spend) = self.spends_made.pop() else {
+                break;
+            };
+            self.tracked_utxos
+                .retain(|utxo, _| utxo.txid != spend.spending_txid);
+            self.tracked_amounts
+                .retain(|utxo, _| utxo.txid != spend.spending_txid);
+            self.tracked_utxos.insert(spend.utxo, spend.position);
+            if let Some(amount) = spend.amount {
+                self.tracked_amounts.insert(spend.utxo, amount);
+            }
+        }
+        self.block_hashes.split_off(&(height + 1));
+        self.last_scanned_height = self
+            .last_scanned_height
+            .map(|last_scanned_height| last_scanned_height.min(height));
+    }
+
+    /// Forgets the block hashes more than `window` blocks below the last
+    /// scanned block, so they do not pile up over a long watch.
+    pub fn prune_block_hashes(&mut self, window: u64) {
+        if let Some(last_scanned_height) = self.last_scanned_height {
+            self.block_hashes = self
+                .block_hashes
+                .split_off(&last_scanned_height.saturating_sub(window));
+        }
+    }
+}
+
+/// Puts the signatures made for `deposit_signing_requests` back together
+/// into the presigns of the deposit. `signatures` must be in the order of
+/// the requests.
+pub fn assemble_deposit_presigns(
+    signing_requests: &[SigningRequest],
+    signatures: Vec<schnorr::Signature>,
+) -> BridgeResult<DepositPresigns> {
+    if signing_requests.len() != NUM_ROUNDS + 1 || signatures.len() != signing_requests.len() {
+        return Err(BridgeError::FailedToGetPresigns);
+    }
+    let mut move_sign = None;
+    let mut operator_claim_sign = Vec::new();
+    for (signing_request, signature) in signing_requests.iter().zip(signatures) {
+        match signing_request.kind {
+            SigningRequestKind::Move if move_sign.is_none() => move_sign = Some(signature),
+            SigningRequestKind::OperatorClaim { period } if period == operator_claim_sign.len() => {
+                operator_claim_sign.push(signature)
+            }
+            _ => return Err(BridgeError::FailedToGetPresigns),
+        }
+    }
+    Ok(DepositPresigns {
+        move_sign: move_sign.ok_or(BridgeError::FailedToGetPresigns)?,
+        operator_claim_sign,
+    })
+}
+
+/// Presigns `requests` in order with `presign`, stopping at the first one
+/// that fails. See `Verifier::new_deposits`.
+fn presign_batch(
+    requests: &[DepositRequest],
+    mut presign: impl FnMut(&DepositRequest) -> BridgeResult<DepositPresigns>,
+) -> Result<Vec<DepositPresigns>, DepositBatchError> {
+    let mut presigns = Vec::with_capacity(requests.len());
+    for (index, deposit) in requests.iter().enumerate() {
+        match presign(deposit) {
+            Ok(deposit_presigns) => presigns.push(deposit_presigns),
+            Err(source) => {
+                tracing::error!("Deposit {} of the batch failed: {:?}", index, source);
+                return Err(DepositBatchError {
+                    index,
+                    presigns,
+                    source,
+                });
+            }
+        }
+    }
+    Ok(presigns)
+}
+
+/// Everything a verifier needs to resume its work, except the secret key,
+/// which is loaded separately. Used for backups and migrations.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VerifierSnapshot {
+    pub verifiers: Vec<XOnlyPublicKey>,
+    pub operator_pk: XOnlyPublicKey,
+    pub connector_tree_utxos: Vec<ConnectorUTXOTree>,
+    pub connector_tree_hashes: Vec<HashTree>,
+    pub claim_proof_merkle_trees: Vec<MerkleTree<CLAIM_MERKLE_TREE_DEPTH>>,
+    pub start_block_height: u64,
+    pub period_relative_block_heights: Vec<u32>,
+}
+
+/// Snapshot of a verifier together with its connector tree watch state, saved
+/// to disk so that it can resume watching after a crash. Maps are ordered so
+/// the same state is always saved the same way.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VerifierState {
+    pub snapshot: VerifierSnapshot,
+    pub tracked_utxos: BTreeMap<OutPoint, (u32, u32)>,
+    pub tracked_amounts: BTreeMap<OutPoint, Amount>,
+    pub spends_made: Vec<SpendRecord>,
+    pub last_scanned_height: Option<u64>,
+    pub block_hashes: BTreeMap<u64, BlockHash>,
+}
+
+impl VerifierState {
+    /// Saves the state under `key` of `store`.
+    pub fn save(&self, store: &dyn StateStore, key: &str) -> BridgeResult<()> {
+        let serialized =
+            serde_json::to_vec_pretty(self).map_err(|e| BridgeError::DatabaseError(e.into()))?;
+        store.save(key, &serialized)
+    }
+
+    pub fn load(store: &dyn StateStore, key: &str) -> BridgeResult<Self> {
+        let content = store.load(key)?;
+        serde_json::from_slice(&content).map_err(|e| BridgeError::DatabaseError(e.into()))
+    }
+
+    /// Watch state to continue scanning from, without the errors of earlier
+    /// scans.
+    pub fn watch_result(&self) -> WatchResult {
+        WatchResult {
+            tracked_utxos: self.tracked_utxos.clone().into_iter().collect(),
+            tracked_amounts: self.tracked_amounts.clone().into_iter().collect(),
+            spends_made: self.spends_made.clone(),
+            last_scanned_height: self.last_scanned_height,
+            block_hashes: self.block_hashes.clone(),
+            ..Default::default()
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Verifier {
     pub rpc: ExtendedRpc,
+    /// Backend for the chain operations of `ChainBackend`, the node behind
+    /// `rpc` unless changed with `set_chain_backend`
+    pub chain: Box<dyn ChainBackend>,
     pub secp: Secp256k1<secp256k1::All>,
     pub signer: Actor,
     pub transaction_builder: TransactionBuilder,
     pub verifiers: Vec<XOnlyPublicKey>,
     pub operator_pk: XOnlyPublicKey,
+    pub rpc_failure_policy: RpcFailurePolicy,
+    /// Network the verifier acts on, change it with `set_network`
+    pub network: Network,
+    /// Dust, fee and confirmation parameters of `network`
+    pub params: BridgeParams,
+    /// Number of confirmations a spend needs before it is treated as final
+    pub reorg_safety_depth: u32,
+    /// Presign rate limits per operator key, operators without one are not limited
+    pub presign_rate_limits: HashMap<XOnlyPublicKey, RateLimit>,
+    /// Check that each connector leaf a claim is presigned against exists on
+    /// chain with `DUST_VALUE`. Leaves only exist once the connector tree is
+    /// spent down to them, so this is off by default.
+    pub check_connector_leaves: bool,
+    presign_buckets: Mutex<HashMap<XOnlyPublicKey, TokenBucket>>,
     verifier_db_connector: VerifierMockDB,
 }
 
@@ -33,67 +322,25 @@ impl VerifierConnector for Verifier {
     /// this is a endpoint that only the operator can call
     /// 1. Check if the deposit utxo is valid and finalized (6 blocks confirmation)
     /// 2. Check if the utxo is not already spent
-    /// 3. Give move signature and operator claim signatures
+    /// 3. Give move signature and operator claim signatures, paying the claims
+    ///    to `claim_outputs`, which must add up to the claim amount
     fn new_deposit(
         &self,
         start_utxo: OutPoint,
         return_address: &XOnlyPublicKey,
         deposit_index: u32,
         evm_address: &EVMAddress,
-        operator_address: &Address,
-    ) -> Result<DepositPresigns, BridgeError> {
-        check_deposit_utxo(
-            &self.rpc,
-            &self.transaction_builder,
-            &start_utxo,
-            return_address,
-            evm_address,
-            BRIDGE_AMOUNT_SATS,
-        )?;
-
-        let mut move_tx =
-            self.transaction_builder
-                .create_move_tx(start_utxo, evm_address, &return_address)?;
-        let move_txid = move_tx.tx.txid();
-
-        let move_utxo = OutPoint {
-            txid: move_txid,
-            vout: 0,
+        claim_outputs: &[(Amount, Address)],
+    ) -> BridgeResult<DepositPresigns> {
+        // An empty verifier set makes the n-of-n script spendable by anyone
+        check_verifiers_pks(&self.verifiers)?;
+        let deposit = DepositRequest {
+            start_utxo,
+            return_address: *return_address,
+            deposit_index,
+            evm_address: *evm_address,
         };
-
-        let move_sig = self
-            .signer
-            .sign_taproot_script_spend_tx_new(&mut move_tx, 0)?;
-
-        let mut op_claim_sigs = Vec::new();
-
-        for i in 0..NUM_ROUNDS {
-            let connector_utxo = self.verifier_db_connector.get_connector_tree_utxo(i)?
-                [CONNECTOR_TREE_DEPTH][deposit_index as usize];
-            let connector_hash = self.verifier_db_connector.get_connector_tree_hash(
-                i,
-                CONNECTOR_TREE_DEPTH,
-                deposit_index as usize,
-            )?;
-
-            let mut operator_claim_tx = self.transaction_builder.create_operator_claim_tx(
-                move_utxo,
-                connector_utxo,
-                &operator_address,
-                &self.operator_pk,
-                &connector_hash,
-            )?;
-
-            let op_claim_sig = self
-                .signer
-                .sign_taproot_script_spend_tx_new(&mut operator_claim_tx, 0)?;
-            op_claim_sigs.push(op_claim_sig);
-        }
-
-        Ok(DepositPresigns {
-            move_sign: move_sig,
-            operator_claim_sign: op_claim_sigs,
-        })
+        self.presign_deposit(&deposit, claim_outputs)
     }
 
     /// TODO: Add verification for the connector tree hashes
@@ -103,7 +350,7 @@ impl VerifierConnector for Verifier {
         first_source_utxo: &OutPoint,
         start_blockheight: u64,
         period_relative_block_heights: Vec<u32>,
-    ) -> Result<(), BridgeError> {
+    ) -> BridgeResult<()> {
         let (_claim_proof_merkle_roots, _, utxo_trees, claim_proof_merkle_trees) =
             self.transaction_builder.create_all_connector_trees(
                 &connector_tree_hashes,
@@ -128,14 +375,19 @@ impl VerifierConnector for Verifier {
 
     /// Challenges the operator for current period for now
     /// Will return the blockhash, total work, and period
-    fn challenge_operator(&self, period: u8) -> Result<VerifierChallenge, BridgeError> {
+    fn challenge_operator(&self, period: u8) -> BridgeResult<VerifierChallenge> {
         tracing::info!("Verifier starts challenges");
         let last_blockheight = self.rpc.get_block_count()?;
+        let period_relative_block_height = *self
+            .verifier_db_connector
+            .get_period_relative_block_heights()?
+            .get(period as usize)
+            .ok_or(BridgeError::InvalidPeriod(
+                InvalidPeriodError::PeriodNotFound,
+            ))?;
         let last_blockhash = self.rpc.get_block_hash(
             self.verifier_db_connector.get_start_block_height()?
-                + self
-                    .verifier_db_connector
-                    .get_period_relative_block_heights()?[period as usize] as u64
+                + period_relative_block_height as u64
                 - 1,
         )?;
         tracing::debug!("Verifier last_blockhash: {:?}", last_blockhash);
@@ -152,7 +404,8 @@ impl Verifier {
         rpc: ExtendedRpc,
         all_xonly_pks: Vec<XOnlyPublicKey>,
         sk: SecretKey,
-    ) -> Result<Self, BridgeError> {
+    ) -> BridgeResult<Self> {
+        check_verifiers_pks(&all_xonly_pks)?;
         let signer = Actor::new(sk);
         let secp: Secp256k1<secp256k1::All> = Secp256k1::new();
 
@@ -167,14 +420,1905 @@ impl Verifier {
 
         let transaction_builder = TransactionBuilder::new(all_xonly_pks.clone());
         let operator_pk = all_xonly_pks[all_xonly_pks.len() - 1];
+        let params = BridgeParams::for_network(NETWORK);
         Ok(Verifier {
+            chain: Box::new(rpc.clone()),
             rpc,
             secp,
             signer,
             transaction_builder,
             verifiers: all_xonly_pks,
             operator_pk,
+            rpc_failure_policy: RpcFailurePolicy::FailFast,
+            network: NETWORK,
+            params,
+            reorg_safety_depth: params.confirmation_blocks,
+            presign_rate_limits: HashMap::new(),
+            check_connector_leaves: false,
+            presign_buckets: Mutex::new(HashMap::new()),
             verifier_db_connector,
         })
     }
+
+    /// Number of verifier signatures the bridge address needs, see
+    /// `TransactionBuilder::set_threshold`. Deposits presigned after this use
+    /// the new bridge address.
+    pub fn set_threshold(&mut self, threshold: usize) -> BridgeResult<()> {
+        self.transaction_builder.set_threshold(threshold)
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.transaction_builder.threshold
+    }
+
+    /// Acts on `network`: encodes the addresses for it and takes its
+    /// `BridgeParams`, including the reorg safety depth.
+    pub fn set_network(&mut self, network: Network) {
+        self.network = network;
+        self.params = BridgeParams::for_network(network);
+        self.reorg_safety_depth = self.params.confirmation_blocks;
+        self.transaction_builder.set_network(network);
+        self.signer.address = operator_address(self.signer.xonly_public_key, network);
+    }
+
+    /// Uses `chain` for the operations of `ChainBackend`, like an
+    /// `EsploraBackend` for a verifier without a full node.
+    pub fn set_chain_backend(&mut self, chain: Box<dyn ChainBackend>) {
+        self.chain = chain;
+    }
+
+    /// Keeps the verifier database in `store` instead of the default files.
+    /// The content of the previous store is not carried over.
+    pub fn set_state_store(&mut self, store: Arc<dyn StateStore>) {
+        self.verifier_db_connector = VerifierMockDB::with_store(store);
+    }
+
+    /// Captures the verifier set, the operator key and the connector tree state.
+    pub fn snapshot(&self) -> BridgeResult<VerifierSnapshot> {
+        Ok(VerifierSnapshot {
+            verifiers: self.verifiers.clone(),
+            operator_pk: self.operator_pk,
+            connector_tree_utxos: self.verifier_db_connector.get_all_connector_tree_utxos()?,
+            connector_tree_hashes: self.verifier_db_connector.get_all_connector_tree_hashes()?,
+            claim_proof_merkle_trees: self
+                .verifier_db_connector
+                .get_all_claim_proof_merkle_trees()?,
+            start_block_height: self.verifier_db_connector.get_start_block_height()?,
+            period_relative_block_heights: self
+                .verifier_db_connector
+                .get_period_relative_block_heights()?,
+        })
+    }
+
+    /// Creates a verifier from a snapshot taken with `snapshot` and its secret key.
+    pub fn restore(
+        snapshot: VerifierSnapshot,
+        rpc: ExtendedRpc,
+        sk: SecretKey,
+    ) -> BridgeResult<Self> {
+        let verifier = Verifier::new(rpc, snapshot.verifiers, sk)?;
+        if verifier.operator_pk != snapshot.operator_pk {
+            return Err(BridgeError::InvalidOperatorKey);
+        }
+
+        verifier
+            .verifier_db_connector
+            .set_connector_tree_utxos(snapshot.connector_tree_utxos)?;
+        verifier
+            .verifier_db_connector
+            .set_connector_tree_hashes(snapshot.connector_tree_hashes)?;
+        verifier
+            .verifier_db_connector
+            .set_claim_proof_merkle_trees(snapshot.claim_proof_merkle_trees)?;
+        verifier
+            .verifier_db_connector
+            .set_start_block_height(snapshot.start_block_height)?;
+        verifier
+            .verifier_db_connector
+            .set_period_relative_block_heights(snapshot.period_relative_block_heights)?;
+
+        Ok(verifier)
+    }
+
+    /// Captures the snapshot of the verifier with the watch state of its
+    /// connector tree.
+    pub fn state(&self, watch_result: &WatchResult) -> BridgeResult<VerifierState> {
+        Ok(VerifierState {
+            snapshot: self.snapshot()?,
+            tracked_utxos: watch_result.tracked_utxos.clone().into_iter().collect(),
+            tracked_amounts: watch_result.tracked_amounts.clone().into_iter().collect(),
+            spends_made: watch_result.spends_made.clone(),
+            last_scanned_height: watch_result.last_scanned_height,
+            block_hashes: watch_result.block_hashes.clone(),
+        })
+    }
+
+    /// Restores a verifier from a saved state and catches its connector tree
+    /// watch up with the chain, from the block after the last scanned one.
+    pub fn resume(
+        state: VerifierState,
+        rpc: ExtendedRpc,
+        sk: SecretKey,
+    ) -> BridgeResult<(Self, WatchResult)> {
+        let mut watch_result = state.watch_result();
+        let from_height = state.snapshot.start_block_height;
+        let verifier = Verifier::restore(state.snapshot, rpc, sk)?;
+        verifier.watch_connector_tree_range(&mut watch_result, from_height, u64::MAX)?;
+        Ok((verifier, watch_result))
+    }
+
+    /// Presigns a batch of deposits, all paying their claims to
+    /// `claim_outputs`, like calling `new_deposit` for each of them. Stops at
+    /// the first deposit that fails with a `DepositBatchError`, which carries
+    /// its index and the presigns of the deposits before it. Those are
+    /// already recorded as presigned, so they are not lost.
+    pub fn new_deposits(
+        &self,
+        requests: &[DepositRequest],
+        claim_outputs: &[(Amount, Address)],
+    ) -> Result<Vec<DepositPresigns>, DepositBatchError> {
+        check_verifiers_pks(&self.verifiers).map_err(|source| DepositBatchError {
+            index: 0,
+            presigns: Vec::new(),
+            source,
+        })?;
+        presign_batch(requests, |deposit| {
+            self.presign_deposit(deposit, claim_outputs)
+        })
+    }
+
+    /// Checks the deposit and signs its move transaction and operator claims.
+    /// Every deposit takes a presign token of the operator.
+    fn presign_deposit(
+        &self,
+        deposit: &DepositRequest,
+        claim_outputs: &[(Amount, Address)],
+    ) -> BridgeResult<DepositPresigns> {
+        self.presign_deposit_txs(deposit, || {
+            self.deposit_txs(
+                &self.transaction_builder,
+                self.operator_pk,
+                deposit,
+                claim_outputs,
+            )
+        })
+    }
+
+    /// `presign_deposit` on the transactions returned by `deposit_txs`, which
+    /// is only called once the operator got a presign token.
+    fn presign_deposit_txs(
+        &self,
+        deposit: &DepositRequest,
+        deposit_txs: impl FnOnce()
+            -> BridgeResult<(CreateTxOutputs, Vec<CreateTxOutputs>, Vec<HashType>)>,
+    ) -> BridgeResult<DepositPresigns> {
+        self.check_presign_rate_limit(self.operator_pk)?;
+        let (move_tx, operator_claim_txs, connector_hashes) = deposit_txs()?;
+        let presigns = self.sign_deposit_txs(move_tx, operator_claim_txs)?;
+
+        self.verifier_db_connector
+            .add_presigned_connector_hashes(connector_hashes, deposit.start_utxo)?;
+
+        Ok(presigns)
+    }
+
+    /// Signs the move transaction and the operator claims of a deposit.
+    fn sign_deposit_txs(
+        &self,
+        mut move_tx: CreateTxOutputs,
+        operator_claim_txs: Vec<CreateTxOutputs>,
+    ) -> BridgeResult<DepositPresigns> {
+        let move_sig = self
+            .signer
+            .sign_taproot_script_spend_tx_new(&mut move_tx, 0)?;
+        let op_claim_sigs = operator_claim_txs
+            .into_iter()
+            .map(|mut operator_claim_tx| {
+                self.signer
+                    .sign_taproot_script_spend_tx_new(&mut operator_claim_tx, 0)
+            })
+            .collect::<BridgeResult<Vec<_>>>()?;
+
+        Ok(DepositPresigns {
+            move_sign: move_sig,
+            operator_claim_sign: op_claim_sigs,
+        })
+    }
+
+    /// Takes a presign token of the operator if it has a rate limit in
+    /// `presign_rate_limits`.
+    fn check_presign_rate_limit(&self, operator_pk: XOnlyPublicKey) -> BridgeResult<()> {
+        let limit = match self.presign_rate_limits.get(&operator_pk) {
+            Some(limit) => *limit,
+            None => return Ok(()),
+        };
+        let now = Instant::now();
+        let mut buckets = self.presign_buckets.lock().unwrap();
+        let bucket = buckets
+            .entry(operator_pk)
+            .or_insert_with(|| TokenBucket::new(limit, now));
+        if !bucket.try_acquire(now) {
+            tracing::warn!(
+                "Presign rate limit of operator {:?} is exceeded",
+                operator_pk
+            );
+            return Err(BridgeError::OperatorPendingDeposit);
+        }
+        Ok(())
+    }
+
+    /// Recomputes the move (kickoff) transaction of the deposit and checks that
+    /// its txid is the same as the one another party derived. Any difference
+    /// means the presigns of the two parties can not be aggregated.
+    pub fn assert_kickoff_consensus(
+        &self,
+        deposit: &DepositRequest,
+        expected_txid: Txid,
+    ) -> BridgeResult<()> {
+        let move_tx = self.transaction_builder.create_move_tx(
+            deposit.start_utxo,
+            &deposit.evm_address,
+            &deposit.return_address,
+        )?;
+        let move_txid = move_tx.tx.txid();
+
+        if move_txid != expected_txid {
+            tracing::error!(
+                "Kickoff txid mismatch: derived {:?}, expected {:?}",
+                move_txid,
+                expected_txid
+            );
+            return Err(BridgeError::ConsensusMismatch);
+        }
+        Ok(())
+    }
+
+    /// Runs a call on `chain`, handling RPC connection failures according to
+    /// `rpc_failure_policy`. Errors returned by the node itself are never
+    /// retried, except while it is warming up. This is the only retry layer
+    /// of the calls it runs, so they must not go through
+    /// `ExtendedRpc::with_retry`.
+    fn call_chain<T>(
+        &self,
+        call: impl Fn(&dyn ChainBackend) -> BridgeResult<T>,
+    ) -> BridgeResult<T> {
+        let mut attempt: u32 = 0;
+        loop {
+            let err = match call(self.chain.as_ref()) {
+                Ok(result) => return Ok(result),
+                Err(err) => err,
+            };
+            if !matches!(&err, BridgeError::BitcoinRpcError(e) if is_transient_rpc_error(e)) {
+                return Err(err);
+            }
+
+            let wait = match self.rpc_failure_policy {
+                RpcFailurePolicy::FailFast => return Err(err),
+                RpcFailurePolicy::Reconnect { attempts, backoff } => {
+                    if attempt >= attempts {
+                        return Err(err);
+                    }
+                    backoff
+                }
+                RpcFailurePolicy::Pause { interval } => interval,
+            };
+            attempt += 1;
+            tracing::warn!(
+                "RPC connection failed: {:?}, reconnecting (attempt {})",
+                err,
+                attempt
+            );
+            // The RPC client opens a new connection on the next call
+            thread::sleep(wait);
+        }
+    }
+
+    /// Finds where the deposit is in its lifecycle by looking at its deposit
+    /// UTXO and its move (kickoff) transaction on chain. Fails with
+    /// `InvalidDepositUTXO` if the deposit UTXO was never created.
+    pub fn deposit_state(
+        &self,
+        deposit: &DepositRequest,
+        kickoff_txid: Txid,
+    ) -> BridgeResult<DepositState> {
+        self.assert_kickoff_consensus(deposit, kickoff_txid)?;
+
+        match self.rpc.utxo_spend_status(&deposit.start_utxo)? {
+            UtxoSpendStatus::Unspent => return Ok(DepositState::Deposited),
+            UtxoSpendStatus::NotFound => return Err(BridgeError::InvalidDepositUTXO),
+            UtxoSpendStatus::SpentConfirmed | UtxoSpendStatus::SpentMempool => {}
+        }
+
+        let kickoff_info = match self.rpc.get_raw_transaction_info(&kickoff_txid, None) {
+            Ok(info) => info,
+            // The deposit is spent by another transaction
+            Err(e) if is_rpc_not_found(&e) => return Ok(DepositState::Refunded),
+            Err(e) => return Err(e.into()),
+        };
+        if kickoff_info.confirmations.unwrap_or(0) == 0 {
+            return Ok(DepositState::KickoffBroadcast);
+        }
+
+        let bridge_utxo = OutPoint {
+            txid: kickoff_txid,
+            vout: 0,
+        };
+        if self.rpc.is_utxo_spent(&bridge_utxo, false)? {
+            Ok(DepositState::Claimed)
+        } else {
+            Ok(DepositState::KickoffConfirmed)
+        }
+    }
+
+    /// Collects the on-chain data of a deposit into a report that can be stored
+    /// or handed to an auditor.
+    pub fn deposit_audit_report(
+        &self,
+        deposit: &DepositRequest,
+        kickoff_txid: Txid,
+    ) -> BridgeResult<DepositAuditReport> {
+        let state = self.deposit_state(deposit, kickoff_txid)?;
+
+        let deposit_tx = self
+            .rpc
+            .get_raw_transaction(&deposit.start_utxo.txid, None)?;
+        let amount = deposit_tx
+            .output
+            .get(deposit.start_utxo.vout as usize)
+            .ok_or(BridgeError::TxOutputNotFound)?
+            .value;
+
+        let kickoff_confirmations = match state {
+            DepositState::Deposited | DepositState::Refunded => 0,
+            _ => self
+                .rpc
+                .get_raw_transaction_info(&kickoff_txid, None)?
+                .confirmations
+                .unwrap_or(0),
+        };
+        let (bridge_address, _) = self.transaction_builder.generate_bridge_address()?;
+
+        Ok(DepositAuditReport {
+            deposit_utxo: deposit.start_utxo,
+            amount,
+            kickoff_txid,
+            kickoff_confirmations,
+            bridge_address: bridge_address.to_string(),
+            evm_address: deposit.evm_address,
+            state,
+        })
+    }
+
+    /// Builds the move transaction and the operator claim transaction of every
+    /// round for a deposit, after checking the deposit UTXO, the move
+    /// transaction fee and that no connector hash is presigned for another
+    /// deposit. Also returns the connector hashes of the claims.
+    fn deposit_txs(
+        &self,
+        transaction_builder: &TransactionBuilder,
+        operator_pk: XOnlyPublicKey,
+        deposit: &DepositRequest,
+        claim_outputs: &[(Amount, Address)],
+    ) -> BridgeResult<(CreateTxOutputs, Vec<CreateTxOutputs>, Vec<HashType>)> {
+        check_deposit_utxo(
+            &self.rpc,
+            transaction_builder,
+            &deposit.start_utxo,
+            &deposit.return_address,
+            &deposit.evm_address,
+            BRIDGE_AMOUNT_SATS,
+        )?;
+        self.build_deposit_txs(transaction_builder, operator_pk, deposit, claim_outputs)
+    }
+
+    /// `deposit_txs` without the checks of the deposit UTXO on chain.
+    fn build_deposit_txs(
+        &self,
+        transaction_builder: &TransactionBuilder,
+        operator_pk: XOnlyPublicKey,
+        deposit: &DepositRequest,
+        claim_outputs: &[(Amount, Address)],
+    ) -> BridgeResult<(CreateTxOutputs, Vec<CreateTxOutputs>, Vec<HashType>)> {
+        let move_tx = transaction_builder.create_move_tx(
+            deposit.start_utxo,
+            &deposit.evm_address,
+            &deposit.return_address,
+        )?;
+        check_fee_bounds(
+            transaction_fee(&move_tx.tx, &move_tx.prevouts)?,
+            Amount::from_sat(MIN_MOVE_TX_FEE),
+            Amount::from_sat(MAX_MOVE_TX_FEE),
+        )?;
+        let move_utxo = OutPoint {
+            txid: move_tx.tx.txid(),
+            vout: 0,
+        };
+
+        let mut operator_claim_txs = Vec::new();
+        let mut connector_hashes = Vec::new();
+
+        for i in 0..NUM_ROUNDS {
+            let connector_utxo = *self
+                .verifier_db_connector
+                .get_connector_tree_utxo(i)?
+                .get(CONNECTOR_TREE_DEPTH)
+                .and_then(|leaves| leaves.get(deposit.deposit_index as usize))
+                .ok_or(BridgeError::InvalidDepositIndex)?;
+            let connector_hash = self.verifier_db_connector.get_connector_tree_hash(
+                i,
+                CONNECTOR_TREE_DEPTH,
+                deposit.deposit_index as usize,
+            )?;
+            if let Some(presigned_deposit) = self
+                .verifier_db_connector
+                .get_presigned_deposit(connector_hash)?
+            {
+                if presigned_deposit != deposit.start_utxo {
+                    tracing::error!(
+                        "Connector hash of deposit {:?} is already presigned for deposit {:?}",
+                        deposit.start_utxo,
+                        presigned_deposit
+                    );
+                    return Err(BridgeError::DuplicateDepositHash);
+                }
+            }
+            connector_hashes.push(connector_hash);
+            if self.check_connector_leaves {
+                self.check_connector_leaf(&connector_utxo)?;
+            }
+
+            operator_claim_txs.push(transaction_builder.create_operator_claim_tx_with_outputs(
+                move_utxo,
+                connector_utxo,
+                claim_outputs,
+                &operator_pk,
+                &connector_hash,
+            )?);
+        }
+
+        Ok((move_tx, operator_claim_txs, connector_hashes))
+    }
+
+    /// Everything `new_deposit` would sign for the deposit, without signing,
+    /// so the signatures can be made on an offline machine. The requests are
+    /// the move transaction followed by the operator claim of each round, and
+    /// the signatures are put back together with `assemble_deposit_presigns`.
+    /// `verifiers` must be the verifier set of the verifier, with the
+    /// operator last, and the claims pay the operator address. The bridge
+    /// address uses the threshold and network of the verifier. Like
+    /// `new_deposit`, every call takes a presign token of the operator.
+    pub fn deposit_signing_requests(
+        &self,
+        deposit: &DepositRequest,
+        verifiers: &[XOnlyPublicKey],
+    ) -> BridgeResult<Vec<SigningRequest>> {
+        check_verifiers_pks(verifiers)?;
+        if verifiers != self.verifiers.as_slice() {
+            tracing::error!(
+                "Signing requests asked for verifiers {:?}, the verifier set is {:?}",
+                verifiers,
+                self.verifiers
+            );
+            return Err(BridgeError::VerifierSetMismatch);
+        }
+        self.check_presign_rate_limit(self.operator_pk)?;
+        let transaction_builder = self.signing_transaction_builder(verifiers)?;
+        let claim_outputs =
+            default_claim_outputs(&operator_address(self.operator_pk, self.network))?;
+        let (move_tx, operator_claim_txs, connector_hashes) = self.deposit_txs(
+            &transaction_builder,
+            self.operator_pk,
+            deposit,
+            &claim_outputs,
+        )?;
+        let signing_requests = self.signing_requests(move_tx, operator_claim_txs)?;
+
+        // Handing out the sighashes is as good as signing them
+        self.verifier_db_connector
+            .add_presigned_connector_hashes(connector_hashes, deposit.start_utxo)?;
+
+        Ok(signing_requests)
+    }
+
+    /// Signing requests of the move transaction and the operator claims of a
+    /// deposit, in the order `assemble_deposit_presigns` expects.
+    fn signing_requests(
+        &self,
+        move_tx: CreateTxOutputs,
+        operator_claim_txs: Vec<CreateTxOutputs>,
+    ) -> BridgeResult<Vec<SigningRequest>> {
+        let mut signing_requests = Vec::new();
+        for (kind, mut tx) in std::iter::once((SigningRequestKind::Move, move_tx)).chain(
+            operator_claim_txs
+                .into_iter()
+                .enumerate()
+                .map(|(period, tx)| (SigningRequestKind::OperatorClaim { period }, tx)),
+        ) {
+            let sighash = self.signer.sighash_taproot_script_spend(&mut tx, 0)?;
+            signing_requests.push(SigningRequest {
+                kind,
+                txid: tx.tx.txid(),
+                input_index: 0,
+                spend_script: tx.scripts[0].clone(),
+                sighash: sighash.to_byte_array(),
+            });
+        }
+        Ok(signing_requests)
+    }
+
+    /// Transaction builder for `verifiers` with the threshold and network of
+    /// the verifier, so it derives the same bridge address.
+    fn signing_transaction_builder(
+        &self,
+        verifiers: &[XOnlyPublicKey],
+    ) -> BridgeResult<TransactionBuilder> {
+        let mut transaction_builder = TransactionBuilder::new(verifiers.to_vec());
+        transaction_builder.set_threshold(self.transaction_builder.threshold)?;
+        transaction_builder.set_network(self.network);
+        Ok(transaction_builder)
+    }
+
+    /// Checks that the connector leaf is unspent on chain with `DUST_VALUE`,
+    /// otherwise a claim presigned against it could never be spent.
+    fn check_connector_leaf(&self, connector_utxo: &OutPoint) -> BridgeResult<()> {
+        match self.rpc.get_utxo_amount(connector_utxo)? {
+            Some(amount) if amount == Amount::from_sat(self.params.dust_value) => Ok(()),
+            _ => {
+                tracing::error!(
+                    "Connector leaf {:?} does not exist or does not hold the dust value",
+                    connector_utxo
+                );
+                Err(BridgeError::InvalidDepositUTXO)
+            }
+        }
+    }
+
+    /// Spends UTXOs of the verifier's own address into a single output, so
+    /// recovered dust does not fragment its wallet.
+    pub fn consolidate_recovered_utxos(
+        &self,
+        utxos: &[(OutPoint, Amount)],
+        destination: &Address,
+        fee_rate: FeeRate,
+    ) -> BridgeResult<Txid> {
+        let prevouts: Vec<TxOut> = utxos
+            .iter()
+            .map(|(_, amount)| TxOut {
+                value: *amount,
+                script_pubkey: self.signer.address.script_pubkey(),
+            })
+            .collect();
+        let mut tx = TransactionBuilder::create_consolidation_tx(
+            utxos.iter().map(|(utxo, _)| *utxo).collect(),
+            &prevouts,
+            destination,
+            fee_rate,
+        )?;
+        for i in 0..tx.input.len() {
+            let sig = self
+                .signer
+                .sign_taproot_pubkey_spend_tx(&mut tx, &prevouts, i)?;
+            tx.input[i].witness.push(sig.as_ref());
+        }
+        let txid = self.chain.send_raw_transaction(&tx)?;
+        tracing::info!("Verifier broadcast consolidation tx: {:?}", txid);
+        Ok(txid)
+    }
+
+    /// Spends every given connector tree node of the operator through its
+    /// revealed preimage in a single transaction to the verifier's address.
+    /// The fee is estimated once over the whole transaction, instead of one
+    /// `MIN_RELAY_FEE` per node. Every node must be unspent on chain with the
+    /// given amount.
+    pub fn sweep_connector_tree_utxos(
+        &self,
+        utxos: Vec<(OutPoint, PreimageType, Amount)>,
+    ) -> BridgeResult<Txid> {
+        self.check_sweep_amounts(&utxos)?;
+        let mut tx = TransactionBuilder::create_connector_tree_sweep_tx(
+            &self.signer.secp,
+            &self.operator_pk,
+            &utxos,
+            &self.signer.address,
+        )?;
+        tx.output[0].value = self.sweep_output_value(tx.output[0].value, tx.vsize())?;
+        let txid = self.chain.send_raw_transaction(&tx)?;
+        tracing::info!(
+            "Verifier broadcast sweep of {} connector tree UTXOs: {:?}",
+            utxos.len(),
+            txid
+        );
+        Ok(txid)
+    }
+
+    /// Checks that every UTXO to sweep is unspent on chain and holds the given
+    /// amount, since the sweep output is built from these amounts.
+    fn check_sweep_amounts(&self, utxos: &[(OutPoint, PreimageType, Amount)]) -> BridgeResult<()> {
+        for (utxo, _, amount) in utxos {
+            let Some(tx_out) = self.chain.get_tx_out(utxo)? else {
+                tracing::error!("Connector tree UTXO {:?} to sweep is not on chain", utxo);
+                return Err(BridgeError::UTXOSpent);
+            };
+            if tx_out.value != *amount {
+                tracing::error!(
+                    "Connector tree UTXO {:?} to sweep holds {}, not {}",
+                    utxo,
+                    tx_out.value,
+                    amount
+                );
+                return Err(BridgeError::ConnectorTreeValueMismatch);
+            }
+        }
+        Ok(())
+    }
+
+    /// What is left of `total_amount` for the sweep output after the fee of a
+    /// sweep of `vsize`, which is at least the minimum relay fee. Fails with
+    /// `FeeTooHigh` if that is dust.
+    fn sweep_output_value(&self, total_amount: Amount, vsize: usize) -> BridgeResult<Amount> {
+        let fee = estimate_fee(self.chain.as_ref(), vsize, FEE_ESTIMATE_CONF_TARGET)?
+            .max(Amount::from_sat(self.params.min_relay_fee));
+        total_amount
+            .checked_sub(fee)
+            .filter(|value| *value >= self.signer.address.script_pubkey().dust_value())
+            .ok_or(BridgeError::FeeTooHigh)
+    }
+
+    /// Total amount locked in the bridge by the given deposits, for solvency
+    /// reports. Deposits are keyed by their deposit UTXO, so a deposit that is
+    /// listed again under another kickoff txid, e.g. after it was reorged out
+    /// and rebroadcast, is counted once.
+    pub fn total_locked(&self, deposits: &[(DepositRequest, Txid)]) -> BridgeResult<Amount> {
+        let mut seen_deposits = HashSet::new();
+        let mut locked_count = 0;
+        for (deposit, kickoff_txid) in deposits {
+            if !seen_deposits.insert(deposit.start_utxo) {
+                continue;
+            }
+            if self.deposit_state(deposit, *kickoff_txid)? == DepositState::KickoffConfirmed {
+                locked_count += 1;
+            }
+        }
+        Ok(Amount::from_sat(BRIDGE_AMOUNT_SATS * locked_count))
+    }
+
+    /// Rebuilds the map of unspent connector tree UTXOs to their `(depth, index)`
+    /// in the tree, only using on-chain data, along with the spends that were
+    /// applied to it. Starting from `source_utxo` as the root, every block
+    /// from `from_height` to the tip is scanned. When a tracked
+    /// UTXO is spent, it is replaced with the child outputs of the spending
+    /// transaction, unless it is a leaf. Every output of the spending transaction
+    /// is a child, so the `k`th output of a node with `n` outputs gets the index
+    /// `index * n + k` on the next level. A transaction that spends several
+    /// tracked UTXOs at once splits its outputs evenly between them, in the
+    /// order of its inputs.
+    ///
+    /// The children of every spent node whose value is known, which is every
+    /// node but the root, are checked with `validate_tree_value_invariant`.
+    /// Failed checks are collected in `WatchResult::errors` and do not stop
+    /// the scan.
+    ///
+    /// Spends with less than `reorg_safety_depth` confirmations are not applied
+    /// yet, so the spent UTXO stays tracked until its spend is buried deep
+    /// enough. If a reorg drops the spend, the UTXO is still tracked. Deeper
+    /// reorgs are rolled back by `watch_connector_trees_range`.
+    pub fn rebuild_watch_map(
+        &self,
+        source_utxo: OutPoint,
+        from_height: u64,
+    ) -> BridgeResult<WatchResult> {
+        let mut watch_results =
+            self.rebuild_watch_maps(&[(self.operator_pk, source_utxo)], from_height)?;
+        Ok(watch_results.remove(&self.operator_pk).unwrap_or_default())
+    }
+
+    /// Same as `rebuild_watch_map`, for the connector trees of several
+    /// operators, keyed by their public keys. Every block is fetched once and
+    /// checked against the tracked UTXOs of all operators.
+    pub fn rebuild_watch_maps(
+        &self,
+        sources: &[(XOnlyPublicKey, OutPoint)],
+        from_height: u64,
+    ) -> BridgeResult<HashMap<XOnlyPublicKey, WatchResult>> {
+        let mut watch_results: HashMap<XOnlyPublicKey, WatchResult> = sources
+            .iter()
+            .map(|(operator_pk, source_utxo)| {
+                let watch_result = WatchResult {
+                    tracked_utxos: HashMap::from([(*source_utxo, (0, 0))]),
+                    ..Default::default()
+                };
+                (*operator_pk, watch_result)
+            })
+            .collect();
+        self.watch_connector_trees_range(&mut watch_results, from_height, u64::MAX)?;
+        Ok(watch_results)
+    }
+
+    /// Same as `watch_connector_trees_range`, for the connector tree of this
+    /// verifier's operator.
+    pub fn watch_connector_tree_range(
+        &self,
+        watch_result: &mut WatchResult,
+        from_height: u64,
+        to_height: u64,
+    ) -> BridgeResult<()> {
+        let mut watch_results = HashMap::from([(self.operator_pk, std::mem::take(watch_result))]);
+        let scan_result =
+            self.watch_connector_trees_range(&mut watch_results, from_height, to_height);
+        *watch_result = watch_results.remove(&self.operator_pk).unwrap_or_default();
+        scan_result
+    }
+
+    /// Applies the connector tree spends in the blocks up to `to_height` to
+    /// earlier scan results, block by block. Results that were never scanned
+    /// start at `from_height`, the others continue after their
+    /// `last_scanned_height`, so a restarted verifier catches up with the
+    /// blocks it missed. Blocks that do not have `reorg_safety_depth`
+    /// confirmations yet are left for a later scan.
+    ///
+    /// Scanned blocks that were reorged out since the last scan are rolled
+    /// back to the fork point first and scanned again. If the chain changes
+    /// during the scan, `BridgeError::BlockhashNotFound` is returned, and the
+    /// results keep the blocks applied before the change.
+    pub fn watch_connector_trees_range(
+        &self,
+        watch_results: &mut HashMap<XOnlyPublicKey, WatchResult>,
+        from_height: u64,
+        to_height: u64,
+    ) -> BridgeResult<()> {
+        let tip_height = self.call_chain(|chain| chain.get_block_count())?;
+        for (operator_pk, watch_result) in watch_results.iter_mut() {
+            let fork_height = Verifier::find_fork_height(watch_result, tip_height, |height| {
+                self.call_chain(|chain| chain.get_block_hash(height))
+            })?;
+            if let Some(fork_height) = fork_height {
+                tracing::warn!(
+                    "Connector tree of operator {:?} was scanned on reorged blocks, rolling back to height {}",
+                    operator_pk,
+                    fork_height
+                );
+                watch_result.rollback_to(fork_height);
+            }
+        }
+
+        let next_height = |watch_result: &WatchResult| {
+            watch_result
+                .last_scanned_height
+                .map_or(from_height, |height| height + 1)
+        };
+        let Some(start_height) = watch_results.values().map(next_height).min() else {
+            return Ok(());
+        };
+        // A spend in block `height` has `tip_height - height + 1` confirmations
+        let safe_height = (tip_height + 1).saturating_sub(self.reorg_safety_depth.max(1) as u64);
+        for height in start_height..to_height.min(safe_height).saturating_add(1) {
+            let (block_hash, block) = self.call_chain(|chain| {
+                let block_hash = chain.get_block_hash(height)?;
+                Ok((block_hash, chain.get_block(&block_hash)?))
+            })?;
+
+            for watch_result in watch_results.values_mut() {
+                if height < next_height(watch_result) {
+                    continue;
+                }
+                let previous_hash = height
+                    .checked_sub(1)
+                    .and_then(|previous_height| watch_result.block_hashes.get(&previous_height));
+                if previous_hash.is_some_and(|hash| *hash != block.header.prev_blockhash) {
+                    tracing::error!("Block {} does not extend the scanned chain", height);
+                    return Err(BridgeError::BlockhashNotFound);
+                }
+                for tx in block.txdata.iter() {
+                    Verifier::apply_connector_tree_spends(watch_result, tx, height);
+                }
+                watch_result.block_hashes.insert(height, block_hash);
+                watch_result.last_scanned_height = Some(height);
+                watch_result.prune_block_hashes(WATCH_BLOCK_HASH_WINDOW);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Height of the last scanned block of `watch_result` that is still in the
+    /// active chain, if a later scanned block was reorged out. Returns `None`
+    /// when the last scanned block is still in the chain. `block_hash_at`
+    /// gives the hash of the block at a height of the active chain.
+    fn find_fork_height(
+        watch_result: &WatchResult,
+        tip_height: u64,
+        mut block_hash_at: impl FnMut(u64) -> BridgeResult<BlockHash>,
+    ) -> BridgeResult<Option<u64>> {
+        let mut fork_height = None;
+        for (height, block_hash) in watch_result.block_hashes.iter().rev() {
+            if *height <= tip_height {
+                let chain_hash = block_hash_at(*height)?;
+                if chain_hash == *block_hash {
+                    return Ok(fork_height.map(|_| *height));
+                }
+            }
+            fork_height = Some(height.saturating_sub(1));
+        }
+        Ok(fork_height)
+    }
+
+    /// Replaces the tracked UTXOs that `tx` spends with their children in
+    /// `watch_result`, as described in `rebuild_watch_map`, and records the
+    /// spends.
+    fn apply_connector_tree_spends(
+        watch_result: &mut WatchResult,
+        tx: &bitcoin::Transaction,
+        block_height: u64,
+    ) {
+        let spent_positions: Vec<(OutPoint, (u32, u32), Option<Amount>)> =
+            tx.input
+                .iter()
+                .filter_map(|input| {
+                    let utxo = input.previous_output;
+                    watch_result.tracked_utxos.remove(&utxo).map(|position| {
+                        (utxo, position, watch_result.tracked_amounts.remove(&utxo))
+                    })
+                })
+                .collect();
+        if spent_positions.is_empty() {
+            return;
+        }
+        let txid = tx.txid();
+        let arity = CONNECTOR_TREE_ARITY as u32;
+        // A split gives each spent UTXO `CONNECTOR_TREE_ARITY` children, in
+        // input order. A single output takes the spent UTXOs out of the tree,
+        // like a burn or a sweep.
+        let is_split = tx.output.len() == spent_positions.len() * CONNECTOR_TREE_ARITY;
+        let spends_nodes = spent_positions
+            .iter()
+            .any(|(_, (depth, _), _)| *depth as usize != CONNECTOR_TREE_DEPTH);
+        if spends_nodes && !is_split && tx.output.len() != 1 {
+            tracing::warn!(
+                "Transaction {:?} spends {} connector tree UTXOs into {} outputs",
+                txid,
+                spent_positions.len(),
+                tx.output.len()
+            );
+            watch_result
+                .errors
+                .push(BridgeError::InvalidConnectorTreeSpend);
+        }
+
+        for (k, (utxo, (depth, index), amount)) in spent_positions.into_iter().enumerate() {
+            watch_result.spends_made.push(SpendRecord {
+                utxo,
+                position: (depth, index),
+                amount,
+                spending_txid: txid,
+                block_height,
+            });
+            tracing::debug!(
+                "Connector tree UTXO at depth {} index {} is spent by {:?}",
+                depth,
+                index,
+                txid
+            );
+            if depth as usize == CONNECTOR_TREE_DEPTH || !is_split {
+                continue;
+            }
+            let first_vout = k as u32 * arity;
+            let children = &tx.output[first_vout as usize..(first_vout + arity) as usize];
+            if let Some(amount) = amount {
+                if let Err(e) = validate_tree_value_invariant(
+                    amount,
+                    &children.iter().map(|child| child.value).collect::<Vec<_>>(),
+                    Amount::from_sat(MIN_RELAY_FEE),
+                ) {
+                    tracing::warn!(
+                        "Connector tree UTXO at depth {} index {} has invalid children: {:?}",
+                        depth,
+                        index,
+                        e
+                    );
+                    watch_result.errors.push(e);
+                }
+            }
+            for (child, child_txout) in children.iter().enumerate() {
+                let child_utxo = OutPoint {
+                    txid,
+                    vout: first_vout + child as u32,
+                };
+                watch_result
+                    .tracked_utxos
+                    .insert(child_utxo, (depth + 1, index * arity + child as u32));
+                watch_result
+                    .tracked_amounts
+                    .insert(child_utxo, child_txout.value);
+            }
+        }
+    }
+
+    /// Returns the connector tree leaves that are on chain with a different
+    /// script than the leaf address derived from `operator_pk` and the leaf
+    /// hash. Such leaves were built for another operator key, so burns aimed
+    /// at them never match. Leaves that are not on chain are not checked.
+    pub fn find_mismatched_leaves(
+        &self,
+        operator_pk: XOnlyPublicKey,
+    ) -> BridgeResult<Vec<OutPoint>> {
+        let connector_tree_utxos = self.verifier_db_connector.get_all_connector_tree_utxos()?;
+        let connector_tree_hashes = self.verifier_db_connector.get_all_connector_tree_hashes()?;
+
+        let mut mismatched_leaves = Vec::new();
+        for (utxo_tree, hash_tree) in connector_tree_utxos
+            .iter()
+            .zip(connector_tree_hashes.iter())
+        {
+            let (Some(leaves), Some(leaf_hashes)) = (
+                utxo_tree.get(CONNECTOR_TREE_DEPTH),
+                hash_tree.get(CONNECTOR_TREE_DEPTH),
+            ) else {
+                continue;
+            };
+            for (leaf, leaf_hash) in leaves.iter().zip(leaf_hashes.iter()) {
+                let Some(tx_out) = self.chain.get_tx_out(leaf)? else {
+                    continue;
+                };
+                let (expected_address, _) = TransactionBuilder::create_connector_tree_node_address(
+                    &self.secp,
+                    &operator_pk,
+                    leaf_hash,
+                )?;
+                if tx_out.script_pubkey != expected_address.script_pubkey() {
+                    tracing::warn!(
+                        "Connector tree leaf {:?} is not locked to operator {:?}",
+                        leaf,
+                        operator_pk
+                    );
+                    mismatched_leaves.push(*leaf);
+                }
+            }
+        }
+        Ok(mismatched_leaves)
+    }
+
+    /// Commitment to the connector trees of every round, to compare with the
+    /// operator and the other verifiers during setup, see
+    /// `connector_tree_commitment`.
+    pub fn connector_tree_root(&self) -> BridgeResult<HashType> {
+        connector_tree_commitment(&self.verifier_db_connector.get_all_connector_tree_hashes()?)
+    }
+
+    /// Folds the connector tree leaf hashes of the given period into an
+    /// incremental merkle tree and checks that its root is `expected_root`.
+    /// Catches a desync between the stored hashes and the committed root.
+    pub fn verify_tree_commitment(
+        &self,
+        period: usize,
+        expected_root: HashType,
+    ) -> BridgeResult<()> {
+        let connector_tree_hashes = self
+            .verifier_db_connector
+            .get_connector_tree_hashes(period)?;
+        let leaves = connector_tree_hashes
+            .get(CONNECTOR_TREE_DEPTH)
+            .ok_or(BridgeError::MerkleIndexOutOfBounds)?;
+
+        let mut imt = IncrementalMerkleTree::<CONNECTOR_TREE_DEPTH>::new();
+        for leaf in leaves.iter() {
+            imt.add(*leaf);
+        }
+
+        if imt.root != expected_root {
+            tracing::error!(
+                "Connector tree root mismatch for period {}: computed {:?}, expected {:?}",
+                period,
+                imt.root,
+                expected_root
+            );
+            return Err(BridgeError::ConnectorTreeRootMismatch);
+        }
+        Ok(())
+    }
+
+    /// Finds which of the candidate hashes the given connector tree leaf
+    /// commits to, by deriving the leaf address for each candidate and
+    /// comparing it with the on-chain `scriptPubKey`. Returns `None` if none of
+    /// the candidates match.
+    pub fn match_leaf_to_known_hashes(
+        &self,
+        leaf_utxo: OutPoint,
+        operator_pk: &XOnlyPublicKey,
+        candidate_hashes: &[[u8; 32]],
+    ) -> BridgeResult<Option<[u8; 32]>> {
+        let tx = self.rpc.get_raw_transaction(&leaf_utxo.txid, None)?;
+        let script_pubkey = &tx
+            .output
+            .get(leaf_utxo.vout as usize)
+            .ok_or(BridgeError::TxOutputNotFound)?
+            .script_pubkey;
+
+        for hash in candidate_hashes.iter() {
+            let (address, _) = TransactionBuilder::create_connector_tree_node_address(
+                &self.secp,
+                operator_pk,
+                hash,
+            )?;
+            if address.script_pubkey() == *script_pubkey {
+                return Ok(Some(*hash));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        assemble_deposit_presigns, presign_batch, DepositBatchError, RpcFailurePolicy, Verifier,
+        VerifierSnapshot, VerifierState, WatchResult,
+    };
+    use crate::actor::Actor;
+    use crate::constants::{
+        BridgeParams, CONNECTOR_TREE_DEPTH, DUST_VALUE, MIN_RELAY_FEE, PERIOD_BLOCK_COUNT,
+        TEXT_DATABASE,
+    };
+    use crate::db::text::FileStateStore;
+    use crate::errors::BridgeError;
+    use crate::extended_rpc::ExtendedRpc;
+    use crate::merkle::MerkleTree;
+    use crate::operator::{create_all_rounds_connector_preimages, DepositRequest};
+    use crate::rate_limit::RateLimit;
+    use crate::traits::chain_backend::{ChainBackend, MockChainBackend};
+    use crate::traits::state_store::{MockStateStore, StateStore};
+    use crate::traits::verifier::VerifierConnector;
+    use crate::transaction_builder::{default_claim_outputs, TransactionBuilder};
+    use crate::utils::operator_address;
+    use bitcoin::secp256k1::{Message, Secp256k1};
+    use bitcoin::{
+        absolute, hashes::Hash, transaction::Version, Amount, Block, BlockHash, FeeRate, OutPoint,
+        ScriptBuf, TapSighash, Transaction, TxIn, TxOut, Txid,
+    };
+    use clementine_circuits::constants::NUM_ROUNDS;
+    use crypto_bigint::rand_core::{OsRng, RngCore};
+    use std::cell::Cell;
+    use std::collections::{BTreeMap, HashMap};
+    use std::fs;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    /// A verifier of 2 verifiers and the operator, with the connector trees
+    /// of every round created, and a deposit it can presign.
+    fn verifier_with_connector_trees() -> (Verifier, DepositRequest) {
+        let signer = Actor::new_with_rng(&mut OsRng);
+        let pks = vec![
+            signer.xonly_public_key,
+            Actor::new_with_rng(&mut OsRng).xonly_public_key,
+            Actor::new_with_rng(&mut OsRng).xonly_public_key,
+        ];
+        let mut verifier =
+            Verifier::new(ExtendedRpc::new(), pks.clone(), signer.secret_key).unwrap();
+        verifier.set_state_store(Arc::new(MockStateStore::new()));
+        let (_, connector_tree_hashes) =
+            create_all_rounds_connector_preimages(CONNECTOR_TREE_DEPTH, NUM_ROUNDS, &mut OsRng);
+        let period_relative_block_heights = (0..NUM_ROUNDS as u32 + 1)
+            .map(|i| PERIOD_BLOCK_COUNT * (i + 1))
+            .collect();
+        verifier
+            .connector_roots_created(
+                &connector_tree_hashes,
+                &OutPoint::new(Txid::all_zeros(), 0),
+                100,
+                period_relative_block_heights,
+            )
+            .unwrap();
+        let deposit = DepositRequest {
+            start_utxo: OutPoint::new(Txid::all_zeros(), 1),
+            return_address: pks[1],
+            deposit_index: 0,
+            evm_address: [1u8; 20],
+        };
+        (verifier, deposit)
+    }
+
+    #[test]
+    fn test_signing_transaction_builder_uses_threshold() {
+        let (mut verifier, deposit) = verifier_with_connector_trees();
+        verifier.set_threshold(2).unwrap();
+        let transaction_builder = verifier
+            .signing_transaction_builder(&verifier.verifiers)
+            .unwrap();
+        let claim_outputs =
+            default_claim_outputs(&operator_address(verifier.operator_pk, verifier.network))
+                .unwrap();
+
+        // `deposit_signing_requests` hands out the sighashes `new_deposit` signs
+        let (move_tx, operator_claim_txs, _) = verifier
+            .build_deposit_txs(
+                &verifier.transaction_builder,
+                verifier.operator_pk,
+                &deposit,
+                &claim_outputs,
+            )
+            .unwrap();
+        let (request_move_tx, request_operator_claim_txs, _) = verifier
+            .build_deposit_txs(
+                &transaction_builder,
+                verifier.operator_pk,
+                &deposit,
+                &claim_outputs,
+            )
+            .unwrap();
+        assert_eq!(operator_claim_txs.len(), NUM_ROUNDS);
+        for (mut signed_tx, mut request_tx) in std::iter::once((move_tx, request_move_tx)).chain(
+            operator_claim_txs
+                .into_iter()
+                .zip(request_operator_claim_txs),
+        ) {
+            assert_eq!(
+                verifier
+                    .signer
+                    .sighash_taproot_script_spend(&mut signed_tx, 0)
+                    .unwrap(),
+                verifier
+                    .signer
+                    .sighash_taproot_script_spend(&mut request_tx, 0)
+                    .unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_deposit_signing_requests_round_trip() {
+        let (verifier, deposit) = verifier_with_connector_trees();
+        let claim_outputs =
+            default_claim_outputs(&operator_address(verifier.operator_pk, verifier.network))
+                .unwrap();
+        let deposit_txs = || {
+            let (move_tx, operator_claim_txs, _) = verifier
+                .build_deposit_txs(
+                    &verifier.transaction_builder,
+                    verifier.operator_pk,
+                    &deposit,
+                    &claim_outputs,
+                )
+                .unwrap();
+            (move_tx, operator_claim_txs)
+        };
+
+        let (move_tx, operator_claim_txs) = deposit_txs();
+        let presigns = verifier
+            .sign_deposit_txs(move_tx, operator_claim_txs)
+            .unwrap();
+        let (move_tx, operator_claim_txs) = deposit_txs();
+        let signing_requests = verifier
+            .signing_requests(move_tx, operator_claim_txs)
+            .unwrap();
+        let signatures = signing_requests
+            .iter()
+            .map(|signing_request| {
+                verifier
+                    .signer
+                    .sign(TapSighash::from_byte_array(signing_request.sighash))
+            })
+            .collect();
+        let assembled = assemble_deposit_presigns(&signing_requests, signatures).unwrap();
+
+        // Signatures are randomized, so both must verify for the same sighashes
+        let secp = Secp256k1::verification_only();
+        let signed = std::iter::once(presigns.move_sign).chain(presigns.operator_claim_sign);
+        let offline = std::iter::once(assembled.move_sign).chain(assembled.operator_claim_sign);
+        assert_eq!(signing_requests.len(), NUM_ROUNDS + 1);
+        for ((signing_request, signed), offline) in signing_requests.iter().zip(signed).zip(offline)
+        {
+            let message = Message::from_digest(signing_request.sighash);
+            for signature in [signed, offline] {
+                assert!(secp
+                    .verify_schnorr(&signature, &message, &verifier.signer.xonly_public_key)
+                    .is_ok());
+            }
+        }
+    }
+
+    #[test]
+    fn test_deposit_signing_requests_rejects_other_verifiers() {
+        let signer = Actor::new_with_rng(&mut OsRng);
+        let pks = vec![
+            signer.xonly_public_key,
+            Actor::new_with_rng(&mut OsRng).xonly_public_key,
+            Actor::new_with_rng(&mut OsRng).xonly_public_key,
+        ];
+        let mut verifier =
+            Verifier::new(ExtendedRpc::new(), pks.clone(), signer.secret_key).unwrap();
+        let store = Arc::new(MockStateStore::new());
+        verifier.set_state_store(store.clone());
+        let deposit = DepositRequest {
+            start_utxo: OutPoint::new(Txid::all_zeros(), 1),
+            return_address: pks[1],
+            deposit_index: 0,
+            evm_address: [1u8; 20],
+        };
+        let mut verifiers = verifier.verifiers.clone();
+        verifiers.swap(0, 1);
+
+        assert!(matches!(
+            verifier.deposit_signing_requests(&deposit, &verifiers),
+            Err(BridgeError::VerifierSetMismatch)
+        ));
+        assert!(matches!(
+            verifier.deposit_signing_requests(&deposit, &verifier.verifiers[..2]),
+            Err(BridgeError::VerifierSetMismatch)
+        ));
+        // Rejected before the verifier state is touched
+        assert!(matches!(
+            store.load(TEXT_DATABASE),
+            Err(BridgeError::DatabaseError(e)) if e.kind() == std::io::ErrorKind::NotFound
+        ));
+    }
+
+    #[test]
+    fn test_presign_batch_keeps_presigns_before_failure() {
+        let (mut verifier, deposit) = verifier_with_connector_trees();
+        verifier.presign_rate_limits.insert(
+            verifier.operator_pk,
+            RateLimit {
+                capacity: 2,
+                refill_interval: Duration::from_secs(3600),
+            },
+        );
+        let claim_outputs =
+            default_claim_outputs(&operator_address(verifier.operator_pk, verifier.network))
+                .unwrap();
+        let requests: Vec<DepositRequest> = (0..3)
+            .map(|i| DepositRequest {
+                start_utxo: OutPoint::new(Txid::all_zeros(), i + 1),
+                deposit_index: i,
+                ..deposit
+            })
+            .collect();
+
+        // Presigns like `new_deposits`, without the checks of the deposit
+        // UTXOs on chain
+        let result = presign_batch(&requests, |deposit| {
+            verifier.presign_deposit_txs(deposit, || {
+                verifier.build_deposit_txs(
+                    &verifier.transaction_builder,
+                    verifier.operator_pk,
+                    deposit,
+                    &claim_outputs,
+                )
+            })
+        });
+
+        let Err(DepositBatchError {
+            index,
+            presigns,
+            source,
+        }) = result
+        else {
+            panic!("Third deposit must exceed the rate limit");
+        };
+        assert_eq!(index, 2);
+        assert_eq!(presigns.len(), index);
+        assert!(matches!(source, BridgeError::OperatorPendingDeposit));
+    }
+
+    #[test]
+    fn test_new_deposit_rate_limited() {
+        let (mut verifier, deposit) = verifier_with_connector_trees();
+        verifier.presign_rate_limits.insert(
+            verifier.operator_pk,
+            RateLimit {
+                capacity: 1,
+                refill_interval: Duration::from_secs(3600),
+            },
+        );
+        let claim_outputs =
+            default_claim_outputs(&operator_address(verifier.operator_pk, verifier.network))
+                .unwrap();
+        verifier
+            .presign_deposit_txs(&deposit, || {
+                verifier.build_deposit_txs(
+                    &verifier.transaction_builder,
+                    verifier.operator_pk,
+                    &deposit,
+                    &claim_outputs,
+                )
+            })
+            .unwrap();
+
+        // Rejected before the deposit UTXO is looked up, so no node is needed
+        let next = OutPoint::new(Txid::all_zeros(), 2);
+        assert!(matches!(
+            verifier.new_deposit(
+                next,
+                &deposit.return_address,
+                1,
+                &deposit.evm_address,
+                &claim_outputs,
+            ),
+            Err(BridgeError::OperatorPendingDeposit)
+        ));
+    }
+
+    #[test]
+    fn test_sweep_amounts_and_fee() {
+        let signer = Actor::new_with_rng(&mut OsRng);
+        let mut verifier = Verifier::new(
+            ExtendedRpc::new(),
+            vec![signer.xonly_public_key],
+            signer.secret_key,
+        )
+        .unwrap();
+        let funding_tx = Transaction {
+            version: Version(2),
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: signer.address.script_pubkey(),
+            }],
+        };
+        let utxo = OutPoint::new(funding_tx.txid(), 0);
+        let chain = MockChainBackend::new();
+        chain.add_block(vec![funding_tx]);
+        verifier.set_chain_backend(Box::new(chain));
+
+        // The amounts to sweep must be the ones on chain
+        assert!(verifier
+            .check_sweep_amounts(&[(utxo, [0u8; 32], Amount::from_sat(10_000))])
+            .is_ok());
+        assert!(matches!(
+            verifier.check_sweep_amounts(&[(utxo, [0u8; 32], Amount::from_sat(20_000))]),
+            Err(BridgeError::ConnectorTreeValueMismatch)
+        ));
+        assert!(matches!(
+            verifier.check_sweep_amounts(&[(
+                OutPoint::new(Txid::all_zeros(), 0),
+                [0u8; 32],
+                Amount::from_sat(10_000)
+            )]),
+            Err(BridgeError::UTXOSpent)
+        ));
+
+        // Without an estimate the sweep pays the minimum relay fee
+        let min_relay_fee = Amount::from_sat(verifier.params.min_relay_fee);
+        assert_eq!(
+            verifier
+                .sweep_output_value(Amount::from_sat(10_000), 100)
+                .unwrap(),
+            Amount::from_sat(10_000) - min_relay_fee
+        );
+        let dust = signer.address.script_pubkey().dust_value();
+        assert!(matches!(
+            verifier.sweep_output_value(min_relay_fee + dust - Amount::from_sat(1), 100),
+            Err(BridgeError::FeeTooHigh)
+        ));
+    }
+
+    #[test]
+    fn test_sweep_output_value_uses_estimate() {
+        let signer = Actor::new_with_rng(&mut OsRng);
+        let mut verifier = Verifier::new(
+            ExtendedRpc::new(),
+            vec![signer.xonly_public_key],
+            signer.secret_key,
+        )
+        .unwrap();
+        let chain = MockChainBackend::new();
+        chain.set_fee_rate(Some(FeeRate::from_sat_per_vb_unchecked(20)));
+        verifier.set_chain_backend(Box::new(chain));
+
+        assert_eq!(
+            verifier
+                .sweep_output_value(Amount::from_sat(10_000), 100)
+                .unwrap(),
+            Amount::from_sat(8_000)
+        );
+    }
+
+    #[test]
+    fn test_apply_batched_connector_tree_spends() {
+        let signer = Actor::new_with_rng(&mut OsRng);
+        let root_spend = TransactionBuilder::create_connector_tree_tx(
+            &OutPoint::new(Txid::all_zeros(), 0),
+            1,
+            vec![signer.address.clone(), signer.address.clone()],
+        );
+        let children = [
+            OutPoint::new(root_spend.txid(), 0),
+            OutPoint::new(root_spend.txid(), 1),
+        ];
+        let batch_spend = |num_outputs: usize| {
+            let mut tx = TransactionBuilder::create_connector_tree_tx(
+                &children[0],
+                0,
+                vec![signer.address.clone(); num_outputs],
+            );
+            tx.input.push(TxIn {
+                previous_output: children[1],
+                ..tx.input[0].clone()
+            });
+            tx
+        };
+        let watch_result = |tx: &Transaction| {
+            let mut watch_result = WatchResult {
+                tracked_utxos: HashMap::from([(children[0], (1, 0)), (children[1], (1, 1))]),
+                ..Default::default()
+            };
+            Verifier::apply_connector_tree_spends(&mut watch_result, tx, 10);
+            watch_result
+        };
+
+        // Both siblings are split in one transaction, two outputs each
+        let split = batch_spend(4);
+        let split_result = watch_result(&split);
+        assert!(split_result.errors.is_empty());
+        assert_eq!(split_result.spends_made.len(), 2);
+        assert_eq!(
+            split_result.tracked_utxos,
+            HashMap::from([
+                (OutPoint::new(split.txid(), 0), (2, 0)),
+                (OutPoint::new(split.txid(), 1), (2, 1)),
+                (OutPoint::new(split.txid(), 2), (2, 2)),
+                (OutPoint::new(split.txid(), 3), (2, 3)),
+            ])
+        );
+
+        // Three outputs can not be split between two UTXOs
+        let uneven_result = watch_result(&batch_spend(3));
+        assert!(matches!(
+            uneven_result.errors[..],
+            [BridgeError::InvalidConnectorTreeSpend]
+        ));
+        assert_eq!(uneven_result.spends_made.len(), 2);
+        assert!(uneven_result.tracked_utxos.is_empty());
+
+        // A sweep to a single output is not an error
+        let sweep_result = watch_result(&batch_spend(1));
+        assert!(sweep_result.errors.is_empty());
+        assert!(sweep_result.tracked_utxos.is_empty());
+    }
+
+    #[test]
+    fn test_find_fork_height() {
+        let chain = MockChainBackend::new();
+        let mut watch_result = WatchResult::default();
+        for height in 0..5 {
+            watch_result
+                .block_hashes
+                .insert(height, chain.add_block(vec![]));
+        }
+        watch_result.last_scanned_height = Some(4);
+        assert_eq!(
+            Verifier::find_fork_height(&watch_result, 4, |height| chain.get_block_hash(height))
+                .unwrap(),
+            None
+        );
+
+        // Blocks 3 and 4 are dropped, the scan has to go back to block 2
+        chain.truncate(3);
+        assert_eq!(
+            Verifier::find_fork_height(&watch_result, 2, |height| chain.get_block_hash(height))
+                .unwrap(),
+            Some(2)
+        );
+
+        // and also when a longer chain replaces them
+        let fork_tx = Transaction {
+            version: Version(2),
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut {
+                value: Amount::from_sat(DUST_VALUE),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        for _ in 0..3 {
+            chain.add_block(vec![fork_tx.clone()]);
+        }
+        assert_eq!(
+            Verifier::find_fork_height(&watch_result, 5, |height| chain.get_block_hash(height))
+                .unwrap(),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn test_prune_block_hashes() {
+        let mut watch_result = WatchResult {
+            block_hashes: (0..=150)
+                .map(|height| (height, BlockHash::all_zeros()))
+                .collect(),
+            last_scanned_height: Some(150),
+            ..Default::default()
+        };
+
+        watch_result.prune_block_hashes(100);
+        assert_eq!(watch_result.block_hashes.len(), 101);
+        assert_eq!(watch_result.block_hashes.keys().next(), Some(&50));
+    }
+
+    /// `MockChainBackend` that replaces its blocks from `fork_height` on with
+    /// `fork` the first time the hash of the block at `trigger_height` is
+    /// asked for, like a reorg in the middle of a scan.
+    #[derive(Debug)]
+    struct ReorgingChain {
+        chain: MockChainBackend,
+        trigger_height: u64,
+        fork_height: u64,
+        fork: Mutex<Option<Vec<Vec<Transaction>>>>,
+    }
+
+    impl ChainBackend for ReorgingChain {
+        fn get_best_block_hash(&self) -> Result<BlockHash, BridgeError> {
+            self.chain.get_best_block_hash()
+        }
+
+        fn get_block_count(&self) -> Result<u64, BridgeError> {
+            self.chain.get_block_count()
+        }
+
+        fn get_block(&self, block_hash: &BlockHash) -> Result<Block, BridgeError> {
+            self.chain.get_block(block_hash)
+        }
+
+        fn get_block_hash(&self, height: u64) -> Result<BlockHash, BridgeError> {
+            if height == self.trigger_height {
+                if let Some(fork) = self.fork.lock().unwrap().take() {
+                    self.chain.truncate(self.fork_height);
+                    for txdata in fork {
+                        self.chain.add_block(txdata);
+                    }
+                }
+            }
+            self.chain.get_block_hash(height)
+        }
+
+        fn get_tx_out(&self, outpoint: &OutPoint) -> Result<Option<TxOut>, BridgeError> {
+            self.chain.get_tx_out(outpoint)
+        }
+
+        fn send_raw_transaction(&self, tx: &Transaction) -> Result<Txid, BridgeError> {
+            self.chain.send_raw_transaction(tx)
+        }
+
+        fn estimate_fee_rate(&self, conf_target: u16) -> Result<Option<FeeRate>, BridgeError> {
+            self.chain.estimate_fee_rate(conf_target)
+        }
+    }
+
+    /// Verifier that applies spends with a single confirmation, and a
+    /// connector tree root spend to mine.
+    fn watching_verifier() -> (Verifier, OutPoint, Transaction, Actor) {
+        let signer = Actor::new_with_rng(&mut OsRng);
+        let mut verifier = Verifier::new(
+            ExtendedRpc::new(),
+            vec![signer.xonly_public_key],
+            signer.secret_key,
+        )
+        .unwrap();
+        verifier.reorg_safety_depth = 1;
+        let root = OutPoint::new(Txid::all_zeros(), 0);
+        let root_spend = TransactionBuilder::create_connector_tree_tx(
+            &root,
+            1,
+            vec![signer.address.clone(), signer.address.clone()],
+        );
+        (verifier, root, root_spend, signer)
+    }
+
+    /// Transaction that does not touch the connector tree, to make blocks
+    /// of a fork differ from the blocks they replace
+    fn unrelated_tx(signer: &Actor, vout: u32) -> Transaction {
+        TransactionBuilder::create_connector_tree_tx(
+            &OutPoint::new(Txid::all_zeros(), vout),
+            0,
+            vec![signer.address.clone()],
+        )
+    }
+
+    #[test]
+    fn test_watch_connector_tree_reorg() {
+        let (mut verifier, root, root_spend, signer) = watching_verifier();
+        let chain = MockChainBackend::new();
+        verifier.set_chain_backend(Box::new(chain.clone()));
+        chain.add_block(vec![]);
+        chain.add_block(vec![root_spend.clone()]);
+        chain.add_block(vec![]);
+        let children = HashMap::from([
+            (OutPoint::new(root_spend.txid(), 0), (1, 0)),
+            (OutPoint::new(root_spend.txid(), 1), (1, 1)),
+        ]);
+
+        let mut watch_result = WatchResult {
+            tracked_utxos: HashMap::from([(root, (0, 0))]),
+            ..Default::default()
+        };
+        verifier
+            .watch_connector_tree_range(&mut watch_result, 0, u64::MAX)
+            .unwrap();
+        assert_eq!(watch_result.tracked_utxos, children);
+        assert_eq!(watch_result.last_scanned_height, Some(2));
+
+        // A longer chain without the root spend replaces blocks 1 and 2
+        chain.truncate(1);
+        for vout in 1..4 {
+            chain.add_block(vec![unrelated_tx(&signer, vout)]);
+        }
+        verifier
+            .watch_connector_tree_range(&mut watch_result, 0, u64::MAX)
+            .unwrap();
+        assert_eq!(watch_result.tracked_utxos, HashMap::from([(root, (0, 0))]));
+        assert!(watch_result.spends_made.is_empty());
+        assert_eq!(watch_result.last_scanned_height, Some(3));
+        for height in 0..=3 {
+            assert_eq!(
+                watch_result.block_hashes[&height],
+                chain.get_block_hash(height).unwrap()
+            );
+        }
+
+        // The root spend is mined again on the new chain
+        chain.add_block(vec![root_spend.clone()]);
+        verifier
+            .watch_connector_tree_range(&mut watch_result, 0, u64::MAX)
+            .unwrap();
+        assert_eq!(watch_result.tracked_utxos, children);
+        assert_eq!(watch_result.spends_made.len(), 1);
+        assert_eq!(watch_result.spends_made[0].block_height, 4);
+    }
+
+    #[test]
+    fn test_watch_connector_tree_reorg_during_scan() {
+        let (mut verifier, root, root_spend, signer) = watching_verifier();
+        let chain = MockChainBackend::new();
+        chain.add_block(vec![]);
+        chain.add_block(vec![]);
+        chain.add_block(vec![]);
+        let mut watch_result = WatchResult {
+            tracked_utxos: HashMap::from([(root, (0, 0))]),
+            ..Default::default()
+        };
+        verifier.set_chain_backend(Box::new(chain.clone()));
+        verifier
+            .watch_connector_tree_range(&mut watch_result, 0, u64::MAX)
+            .unwrap();
+
+        // Block 3 spends the root, block 4 is replaced with a fork of block 3
+        // while the scan is between them
+        chain.add_block(vec![root_spend.clone()]);
+        chain.add_block(vec![]);
+        verifier.set_chain_backend(Box::new(ReorgingChain {
+            chain: chain.clone(),
+            trigger_height: 4,
+            fork_height: 3,
+            fork: Mutex::new(Some(vec![
+                vec![unrelated_tx(&signer, 1)],
+                vec![unrelated_tx(&signer, 2)],
+            ])),
+        }));
+        assert!(matches!(
+            verifier.watch_connector_tree_range(&mut watch_result, 0, u64::MAX),
+            Err(BridgeError::BlockhashNotFound)
+        ));
+        // Blocks before the change stay applied
+        assert_eq!(watch_result.last_scanned_height, Some(3));
+        assert_eq!(watch_result.spends_made.len(), 1);
+
+        // The next scan rolls the spend back and scans the fork
+        verifier
+            .watch_connector_tree_range(&mut watch_result, 0, u64::MAX)
+            .unwrap();
+        assert_eq!(watch_result.tracked_utxos, HashMap::from([(root, (0, 0))]));
+        assert!(watch_result.spends_made.is_empty());
+        assert_eq!(watch_result.last_scanned_height, Some(4));
+        assert_eq!(
+            watch_result.block_hashes[&3],
+            chain.get_block_hash(3).unwrap()
+        );
+    }
+
+    /// Error of an RPC call that could not reach the node
+    fn connection_error() -> BridgeError {
+        BridgeError::BitcoinRpcError(bitcoincore_rpc::Error::JsonRpc(
+            bitcoincore_rpc::jsonrpc::Error::Transport(Box::new(std::io::Error::from(
+                std::io::ErrorKind::ConnectionRefused,
+            ))),
+        ))
+    }
+
+    #[test]
+    fn test_call_chain_fail_fast() {
+        let signer = Actor::new_with_rng(&mut OsRng);
+        let verifier = Verifier::new(
+            ExtendedRpc::new(),
+            vec![signer.xonly_public_key],
+            signer.secret_key,
+        )
+        .unwrap();
+        let calls = Cell::new(0);
+
+        let result: Result<(), _> = verifier.call_chain(|_| {
+            calls.set(calls.get() + 1);
+            Err(connection_error())
+        });
+        assert!(matches!(result, Err(BridgeError::BitcoinRpcError(_))));
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn test_call_chain_reconnect() {
+        let signer = Actor::new_with_rng(&mut OsRng);
+        let mut verifier = Verifier::new(
+            ExtendedRpc::new(),
+            vec![signer.xonly_public_key],
+            signer.secret_key,
+        )
+        .unwrap();
+        verifier.rpc_failure_policy = RpcFailurePolicy::Reconnect {
+            attempts: 2,
+            backoff: Duration::ZERO,
+        };
+        let calls = Cell::new(0);
+
+        // Succeeds once the node is reachable again
+        let result = verifier.call_chain(|_| {
+            calls.set(calls.get() + 1);
+            if calls.get() < 3 {
+                Err(connection_error())
+            } else {
+                Ok(calls.get())
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        // Gives up after the attempts
+        calls.set(0);
+        let result: Result<(), _> = verifier.call_chain(|_| {
+            calls.set(calls.get() + 1);
+            Err(connection_error())
+        });
+        assert!(result.is_err());
+        assert_eq!(calls.get(), 3);
+
+        // Other errors are returned right away
+        calls.set(0);
+        let result: Result<(), _> = verifier.call_chain(|_| {
+            calls.set(calls.get() + 1);
+            Err(BridgeError::BlockNotFound)
+        });
+        assert!(matches!(result, Err(BridgeError::BlockNotFound)));
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn test_watch_result_rollback() {
+        let signer = Actor::new_with_rng(&mut OsRng);
+        let root = OutPoint::new(Txid::all_zeros(), 0);
+        let mut watch_result = WatchResult {
+            tracked_utxos: HashMap::from([(root, (0, 0))]),
+            ..Default::default()
+        };
+
+        // The root is split at height 10 and its first child at height 11
+        let root_spend = TransactionBuilder::create_connector_tree_tx(
+            &root,
+            1,
+            vec![signer.address.clone(), signer.address.clone()],
+        );
+        let first_child = OutPoint::new(root_spend.txid(), 0);
+        let child_spend = TransactionBuilder::create_connector_tree_tx(
+            &first_child,
+            0,
+            vec![signer.address.clone(), signer.address.clone()],
+        );
+        Verifier::apply_connector_tree_spends(&mut watch_result, &root_spend, 10);
+        watch_result.block_hashes.insert(10, BlockHash::all_zeros());
+        Verifier::apply_connector_tree_spends(&mut watch_result, &child_spend, 11);
+        watch_result.block_hashes.insert(11, BlockHash::all_zeros());
+        watch_result.last_scanned_height = Some(11);
+        assert_eq!(watch_result.tracked_utxos.len(), 3);
+        assert!(watch_result.errors.is_empty());
+        let after_root_spend = HashMap::from([
+            (first_child, (1, 0)),
+            (OutPoint::new(root_spend.txid(), 1), (1, 1)),
+        ]);
+
+        watch_result.rollback_to(10);
+        assert_eq!(watch_result.tracked_utxos, after_root_spend);
+        assert_eq!(
+            watch_result.tracked_amounts[&first_child],
+            Amount::from_sat(2 * DUST_VALUE + MIN_RELAY_FEE)
+        );
+        assert_eq!(watch_result.spends_made.len(), 1);
+        assert_eq!(watch_result.last_scanned_height, Some(10));
+        assert_eq!(watch_result.block_hashes.len(), 1);
+
+        watch_result.rollback_to(9);
+        assert_eq!(watch_result.tracked_utxos, HashMap::from([(root, (0, 0))]));
+        assert!(watch_result.tracked_amounts.is_empty());
+        assert!(watch_result.spends_made.is_empty());
+    }
+
+    #[test]
+    fn test_verifier_state_round_trip() {
+        let pks: Vec<_> = (0..3)
+            .map(|_| Actor::new_with_rng(&mut OsRng).xonly_public_key)
+            .collect();
+        let utxos: Vec<OutPoint> = (0..3)
+            .map(|vout| OutPoint::new(Txid::all_zeros(), vout))
+            .collect();
+        let state = VerifierState {
+            snapshot: VerifierSnapshot {
+                verifiers: pks.clone(),
+                operator_pk: pks[2],
+                connector_tree_utxos: vec![vec![vec![utxos[0]], utxos[1..].to_vec()]],
+                connector_tree_hashes: vec![vec![vec![[1u8; 32]], vec![[2u8; 32], [3u8; 32]]]],
+                claim_proof_merkle_trees: vec![MerkleTree::new()],
+                start_block_height: 100,
+                period_relative_block_heights: vec![50],
+            },
+            tracked_utxos: BTreeMap::from([(utxos[1], (1, 0)), (utxos[2], (1, 1))]),
+            tracked_amounts: BTreeMap::from([
+                (utxos[1], Amount::from_sat(1000)),
+                (utxos[2], Amount::from_sat(1000)),
+            ]),
+            spends_made: Vec::new(),
+            last_scanned_height: Some(120),
+            block_hashes: BTreeMap::from([(120, BlockHash::all_zeros())]),
+        };
+
+        let directory = std::env::temp_dir().join(format!("verifier_state_{:x}", OsRng.next_u64()));
+        fs::create_dir(&directory).unwrap();
+        let store = FileStateStore::new(directory.clone());
+        state.save(&store, "verifier_state.json").unwrap();
+        let saved = store.load("verifier_state.json").unwrap();
+        let loaded = VerifierState::load(&store, "verifier_state.json").unwrap();
+        assert_eq!(loaded, state);
+
+        loaded.save(&store, "verifier_state.json").unwrap();
+        assert_eq!(store.load("verifier_state.json").unwrap(), saved);
+        // Only the saved file is left, without the temporary one
+        assert_eq!(fs::read_dir(&directory).unwrap().count(), 1);
+        fs::remove_dir_all(&directory).unwrap();
+
+        let watch_result = loaded.watch_result();
+        assert_eq!(watch_result.tracked_utxos.len(), 2);
+        assert_eq!(watch_result.last_scanned_height, Some(120));
+    }
+
+    #[test]
+    fn test_set_network() {
+        let signer = Actor::new_with_rng(&mut OsRng);
+        let mut verifier = Verifier::new(
+            ExtendedRpc::new(),
+            vec![signer.xonly_public_key],
+            signer.secret_key,
+        )
+        .unwrap();
+        let regtest_bridge_address = verifier
+            .transaction_builder
+            .generate_bridge_address()
+            .unwrap()
+            .0;
+        assert!(verifier.signer.address.to_string().starts_with("bcrt1"));
+        assert!(regtest_bridge_address.to_string().starts_with("bcrt1"));
+
+        verifier.set_network(bitcoin::Network::Bitcoin);
+        let mainnet_bridge_address = verifier
+            .transaction_builder
+            .generate_bridge_address()
+            .unwrap()
+            .0;
+        assert!(verifier.signer.address.to_string().starts_with("bc1"));
+        assert!(mainnet_bridge_address.to_string().starts_with("bc1"));
+        assert_eq!(
+            mainnet_bridge_address.script_pubkey(),
+            regtest_bridge_address.script_pubkey()
+        );
+        assert_eq!(
+            verifier.params,
+            BridgeParams::for_network(bitcoin::Network::Bitcoin)
+        );
+        assert_eq!(
+            verifier.reorg_safety_depth,
+            verifier.params.confirmation_blocks
+        );
+    }
 }
@@ -0,0 +1,120 @@
+//! # Watchers
+//!
+//! A verifier can follow the chain with several loops at once, e.g. a block
+//! poller and a mempool watcher. They run on their own threads and are stopped
+//! together with a `WatcherSet`, so a restart does not leak threads or lose
+//! state.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use std::thread::{self, JoinHandle};
+
+use crate::errors::{BridgeError, BridgeResult};
+
+/// Shared stop flag of a `WatcherSet`. Watch loops check it between blocks and
+/// return once it is set.
+#[derive(Debug, Clone, Default)]
+pub struct StopSignal(Arc<AtomicBool>);
+
+impl StopSignal {
+    pub fn is_stopped(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    fn stop(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Owns the threads of several watch loops and stops them all at once.
+#[derive(Debug, Default)]
+pub struct WatcherSet {
+    signal: StopSignal,
+    watchers: Vec<(String, JoinHandle<BridgeResult<()>>)>,
+}
+
+impl WatcherSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `watch_loop` on a new thread. It gets the stop signal of the set
+    /// and should return when it is stopped.
+    pub fn spawn<F>(&mut self, name: &str, watch_loop: F)
+    where
+        F: FnOnce(StopSignal) -> BridgeResult<()> + Send + 'static,
+    {
+        let signal = self.signal.clone();
+        self.watchers
+            .push((name.to_string(), thread::spawn(move || watch_loop(signal))));
+    }
+
+    /// Signals every watch loop to stop, waits for all of them, then calls
+    /// `flush` once to save the state they left behind. Returns the first
+    /// error of a watch loop, if any, after the state is flushed.
+    pub fn stop(self, flush: impl FnOnce() -> BridgeResult<()>) -> BridgeResult<()> {
+        self.signal.stop();
+
+        let mut first_error = None;
+        for (name, handle) in self.watchers {
+            let result = handle.join().unwrap_or_else(|_| {
+                tracing::error!("Watcher {} panicked", name);
+                Err(BridgeError::WatcherPanicked)
+            });
+            if let Err(e) = result {
+                tracing::error!("Watcher {} stopped with an error: {:?}", name, e);
+                first_error.get_or_insert(e);
+            }
+        }
+
+        flush()?;
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WatcherSet;
+    use crate::errors::BridgeError;
+    use std::sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    };
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_watcher_set_stop() {
+        let scanned_blocks = Arc::new(AtomicU32::new(0));
+        let mut watchers = WatcherSet::new();
+        for name in ["poller", "mempool"] {
+            let scanned_blocks = scanned_blocks.clone();
+            watchers.spawn(name, move |signal| {
+                while !signal.is_stopped() {
+                    scanned_blocks.fetch_add(1, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(1));
+                }
+                Ok(())
+            });
+        }
+        watchers.spawn("failing", |_| Err(BridgeError::BlockNotFound));
+
+        let flushes = AtomicU32::new(0);
+        let result = watchers.stop(|| {
+            flushes.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        });
+        assert!(matches!(result, Err(BridgeError::BlockNotFound)));
+        assert_eq!(flushes.load(Ordering::SeqCst), 1);
+
+        // Every loop has returned, so the count does not change anymore
+        let scanned = scanned_blocks.load(Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(5));
+        assert_eq!(scanned_blocks.load(Ordering::SeqCst), scanned);
+    }
+}